@@ -5,7 +5,7 @@
 layout(local_size_x = 1) in;
 layout(max_vertices = 3, max_primitives = 1, triangles) out;
 
-layout(location = 0) out vec3[] outColors;
+layout(location = 0) out vec3[] outColor;
 
 layout(push_constant) uniform Constants {
     mat4 mvp;
@@ -20,7 +20,7 @@ void main() {
     gl_MeshVerticesEXT[1].gl_Position = constants.mvp * vec4(0.5, 0.5, 1.0, 1.0);
     gl_MeshVerticesEXT[2].gl_Position = constants.mvp * vec4(0.0, -0.5, 1.0, 1.0);
 
-    outColors[0] = vec3(1.0, 0.0, 0.0);
-    outColors[1] = vec3(0.0, 1.0, 0.0);
-    outColors[2] = vec3(0.0, 0.0, 1.0);
+    outColor[0] = vec3(1.0, 0.0, 0.0);
+    outColor[1] = vec3(0.0, 1.0, 0.0);
+    outColor[2] = vec3(0.0, 0.0, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,24 @@
+#version 460
+
+layout(location = 0) out vec3 outColor;
+
+layout(push_constant) uniform Constants {
+    mat4 mvp;
+} constants;
+
+const vec4 positions[3] = vec4[](
+    vec4(-0.5, 0.5, 1.0, 1.0),
+    vec4(0.5, 0.5, 1.0, 1.0),
+    vec4(0.0, -0.5, 1.0, 1.0)
+);
+
+const vec3 colors[3] = vec3[](
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, 1.0)
+);
+
+void main() {
+    gl_Position = constants.mvp * positions[gl_VertexIndex];
+    outColor = colors[gl_VertexIndex];
+}
\ No newline at end of file
@@ -1,4 +1,4 @@
-use tort_render::RenderPlugin;
+use tort_render::{graph::TransientMemoryDiagnosticsPlugin, RenderPlugin};
 
 use crate::{
     a11y::AccessibilityPlugin,
@@ -45,5 +45,6 @@ impl PluginGroup for DefaultPlugins {
             })
             .add(WinitPlugin::default())
             .add(RenderPlugin::default())
+            .add(TransientMemoryDiagnosticsPlugin)
     }
 }
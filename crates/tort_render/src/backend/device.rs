@@ -1,4 +1,12 @@
-use std::{ops::Deref, os::raw::c_char, sync::Arc};
+use std::{
+    any::{Any, TypeId},
+    ffi::{c_void, CStr},
+    fmt::{self, Debug, Formatter},
+    ops::Deref,
+    os::raw::c_char,
+    ptr,
+    sync::Arc,
+};
 
 use anyhow::Result;
 use ash::{
@@ -14,6 +22,9 @@ use vk_mem_alloc::{Allocator, AllocatorCreateFlags, AllocatorCreateInfo};
 
 use crate::backend::Instance;
 
+const KHR_PORTABILITY_SUBSET: &CStr =
+    unsafe { CStr::from_bytes_with_nul_unchecked(b"VK_KHR_portability_subset\0") };
+
 pub struct DeviceProperties {
     pub properties: vk::PhysicalDeviceProperties,
     pub properties_11: vk::PhysicalDeviceVulkan11Properties<'static>,
@@ -99,46 +110,171 @@ impl DeviceQueueFamilyProperties {
     }
 }
 
+/// The start of every Vulkan struct that can be chained.
+#[repr(C)]
+struct ChainHeader {
+    s_type: vk::StructureType,
+    p_next: *mut c_void,
+}
+
+/// A feature struct of a device extension, e.g. [`vk::PhysicalDeviceMeshShaderFeaturesEXT`].
+///
+/// Implemented for every feature struct that extends both `VkPhysicalDeviceFeatures2` and
+/// `VkDeviceCreateInfo`, registered with
+/// [`DeviceRequirements::register_extension_features`](crate::backend::DeviceRequirements::register_extension_features).
+pub trait ExtensionFeatures: Any {
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// A pointer to the struct, which starts with `sType` and `pNext`.
+    fn as_mut_ptr(&mut self) -> *mut c_void;
+}
+
+impl<T> ExtensionFeatures for T
+where
+    T: vk::ExtendsPhysicalDeviceFeatures2 + vk::ExtendsDeviceCreateInfo + 'static,
+{
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[inline]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    #[inline]
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        (self as *mut T).cast()
+    }
+}
+
+/// An extension feature struct and the extension it belongs to.
+#[derive(Copy, Clone)]
+pub struct ExtensionFeaturesDesc {
+    pub extension: &'static CStr,
+    pub type_id: TypeId,
+    pub new: fn() -> Box<dyn ExtensionFeatures>,
+}
+
+impl ExtensionFeaturesDesc {
+    #[inline]
+    pub fn new<T: ExtensionFeatures + Default>(extension: &'static CStr) -> Self {
+        Self {
+            extension,
+            type_id: TypeId::of::<T>(),
+            new: || Box::<T>::default(),
+        }
+    }
+}
+
+impl Debug for ExtensionFeaturesDesc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.extension)
+    }
+}
+
+/// Links feature structs into a chain and returns its head.
+unsafe fn link_extension_features<'a>(
+    extension_features: impl Iterator<Item = &'a mut Box<dyn ExtensionFeatures>>,
+) -> *mut c_void {
+    let mut head = ptr::null_mut();
+
+    for features in extension_features {
+        let features = features.as_mut_ptr();
+        (*features.cast::<ChainHeader>()).p_next = head;
+        head = features;
+    }
+
+    head
+}
+
+unsafe fn unlink_extension_features<'a>(
+    extension_features: impl Iterator<Item = &'a mut Box<dyn ExtensionFeatures>>,
+) {
+    for features in extension_features {
+        (*features.as_mut_ptr().cast::<ChainHeader>()).p_next = ptr::null_mut();
+    }
+}
+
 #[derive(Default)]
 pub struct DeviceFeatures {
     pub features: vk::PhysicalDeviceFeatures,
     pub features_11: vk::PhysicalDeviceVulkan11Features<'static>,
     pub features_12: vk::PhysicalDeviceVulkan12Features<'static>,
-    pub dynamic_rendering_features: vk::PhysicalDeviceDynamicRenderingFeatures<'static>,
-    pub mesh_shader_features: vk::PhysicalDeviceMeshShaderFeaturesEXT<'static>,
-    pub synchronization2_features: vk::PhysicalDeviceSynchronization2Features<'static>,
+    extension_features: Vec<(ExtensionFeaturesDesc, Box<dyn ExtensionFeatures>)>,
 }
 
 impl DeviceFeatures {
-    #[inline]
-    unsafe fn new(instance: &Instance, physical_device: vk::PhysicalDevice) -> Self {
+    /// Queries the core features and the registered extension feature structs whose extension
+    /// is supported.
+    unsafe fn new(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        extensions: &DeviceExtensions,
+        extension_features: &[ExtensionFeaturesDesc],
+    ) -> Self {
+        let mut extension_features = extension_features
+            .iter()
+            .filter(|desc| extensions.is_supported(desc.extension))
+            .map(|desc| (*desc, (desc.new)()))
+            .collect::<Vec<_>>();
+
         let mut features_11 = vk::PhysicalDeviceVulkan11Features::default();
         let mut features_12 = vk::PhysicalDeviceVulkan12Features::default();
-
-        let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::default();
-        let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default();
-        let mut synchronization2_features = vk::PhysicalDeviceSynchronization2Features::default();
+        features_11.p_next =
+            link_extension_features(extension_features.iter_mut().map(|(_, features)| features));
 
         let mut features = vk::PhysicalDeviceFeatures2::default()
             .push_next(&mut features_11)
-            .push_next(&mut features_12)
-            .push_next(&mut dynamic_rendering_features)
-            .push_next(&mut mesh_shader_features)
-            .push_next(&mut synchronization2_features);
+            .push_next(&mut features_12);
 
         instance
             .loader()
             .get_physical_device_features2(physical_device, &mut features);
 
+        let features = features.features;
+        features_11.p_next = ptr::null_mut();
+        features_12.p_next = ptr::null_mut();
+        unlink_extension_features(extension_features.iter_mut().map(|(_, features)| features));
+
         Self {
-            features: features.features,
+            features,
             features_11,
             features_12,
-            dynamic_rendering_features,
-            mesh_shader_features,
-            synchronization2_features,
+            extension_features,
         }
     }
+
+    /// The same feature structs with every feature disabled.
+    fn disabled(&self) -> Self {
+        Self {
+            extension_features: self
+                .extension_features
+                .iter()
+                .map(|(desc, _)| (*desc, (desc.new)()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The extension feature struct `T`, `None` if it wasn't registered or its extension is
+    /// unsupported.
+    #[inline]
+    pub fn get<T: ExtensionFeatures>(&self) -> Option<&T> {
+        self.extension_features
+            .iter()
+            .find_map(|(_, features)| features.as_any().downcast_ref())
+    }
+
+    #[inline]
+    pub fn get_mut<T: ExtensionFeatures>(&mut self) -> Option<&mut T> {
+        self.extension_features
+            .iter_mut()
+            .find_map(|(_, features)| features.as_any_mut().downcast_mut())
+    }
 }
 
 unsafe impl Send for DeviceFeatures {}
@@ -147,13 +283,6 @@ unsafe impl Sync for DeviceFeatures {}
 pub struct DeviceExtensions {
     supported: Vec<vk::ExtensionProperties>,
     enabled: Vec<*const c_char>,
-
-    ext_mesh_shader: bool,
-
-    khr_dynamic_rendering: bool,
-    khr_portability_subset: bool,
-    khr_swapchain: bool,
-    khr_synchronization2: bool,
 }
 
 impl DeviceExtensions {
@@ -165,118 +294,129 @@ impl DeviceExtensions {
         Ok(Self {
             supported,
             enabled: Vec::new(),
+        })
+    }
 
-            ext_mesh_shader: false,
+    #[inline]
+    pub fn supported(&self) -> &[vk::ExtensionProperties] {
+        &self.supported
+    }
 
-            khr_dynamic_rendering: false,
-            khr_portability_subset: false,
-            khr_swapchain: false,
-            khr_synchronization2: false,
-        })
+    #[inline]
+    pub fn is_supported(&self, name: &CStr) -> bool {
+        self.supported
+            .iter()
+            .any(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) } == name)
     }
 
     #[inline]
-    unsafe fn try_push(&mut self, name: *const c_char) -> bool {
-        if self
-            .supported
+    pub fn is_enabled(&self, name: &CStr) -> bool {
+        self.enabled
             .iter()
-            .any(|e| libc::strcmp(e.extension_name.as_ptr(), name) == 0)
-        {
-            self.enabled.push(name);
+            .any(|enabled| unsafe { CStr::from_ptr(*enabled) } == name)
+    }
+
+    /// Enables the extension if it is supported. Enabling an extension twice has no effect.
+    #[inline]
+    pub fn try_push_name(&mut self, name: &'static CStr) -> bool {
+        if self.is_enabled(name) {
+            true
+        } else if self.is_supported(name) {
+            self.enabled.push(name.as_ptr());
             true
         } else {
             false
         }
     }
 
+    #[inline]
+    pub fn push_name(&mut self, name: &'static CStr) {
+        assert!(
+            self.try_push_name(name),
+            "Device extension {name:?} is not supported"
+        );
+    }
+
+    #[inline]
+    pub fn enabled_names(&self) -> impl Iterator<Item = &CStr> {
+        self.enabled
+            .iter()
+            .map(|enabled| unsafe { CStr::from_ptr(*enabled) })
+    }
+
     #[inline]
     pub fn try_push_ext_mesh_shader(&mut self) -> bool {
-        if unsafe { self.try_push(MeshShader::name().as_ptr()) } {
-            self.ext_mesh_shader = true;
-            true
-        } else {
-            false
-        }
+        self.try_push_name(MeshShader::name())
     }
 
     #[inline]
     pub fn push_ext_mesh_shader(&mut self) {
-        assert!(self.try_push_ext_mesh_shader());
+        self.push_name(MeshShader::name());
+    }
+
+    #[inline]
+    pub fn ext_mesh_shader(&self) -> bool {
+        self.is_enabled(MeshShader::name())
     }
 
     #[inline]
     pub fn try_push_khr_dynamic_rendering(&mut self) -> bool {
-        if unsafe { self.try_push(DynamicRendering::name().as_ptr()) } {
-            self.khr_dynamic_rendering = true;
-            true
-        } else {
-            false
-        }
+        self.try_push_name(DynamicRendering::name())
     }
 
     #[inline]
     pub fn push_khr_dynamic_rendering(&mut self) {
-        assert!(self.try_push_khr_dynamic_rendering());
+        self.push_name(DynamicRendering::name());
     }
 
     #[inline]
     pub fn khr_dynamic_rendering(&self) -> bool {
-        self.khr_dynamic_rendering
+        self.is_enabled(DynamicRendering::name())
     }
 
     #[inline]
     pub fn try_push_khr_portability_subset(&mut self) -> bool {
-        if unsafe { self.try_push(b"VK_KHR_portability_subset\0".as_ptr().cast()) } {
-            self.khr_portability_subset = true;
-            true
-        } else {
-            false
-        }
+        self.try_push_name(KHR_PORTABILITY_SUBSET)
     }
 
     #[inline]
     pub fn push_khr_portability_subset(&mut self) {
-        assert!(self.try_push_khr_portability_subset());
+        self.push_name(KHR_PORTABILITY_SUBSET);
     }
 
     #[inline]
     pub fn khr_portability_subset(&self) -> bool {
-        self.khr_portability_subset
+        self.is_enabled(KHR_PORTABILITY_SUBSET)
     }
 
     #[inline]
     pub fn try_push_khr_swapchain(&mut self) -> bool {
-        if unsafe { self.try_push(Swapchain::name().as_ptr()) } {
-            self.khr_swapchain = true;
-            true
-        } else {
-            false
-        }
+        self.try_push_name(Swapchain::name())
     }
 
     #[inline]
     pub fn push_khr_swapchain(&mut self) {
-        assert!(self.try_push_khr_swapchain());
+        self.push_name(Swapchain::name());
+    }
+
+    #[inline]
+    pub fn khr_swapchain(&self) -> bool {
+        self.is_enabled(Swapchain::name())
     }
 
     #[inline]
     pub fn try_push_khr_synchronization2(&mut self) -> bool {
-        if unsafe { self.try_push(Synchronization2::name().as_ptr()) } {
-            self.khr_synchronization2 = true;
-            true
-        } else {
-            false
-        }
+        self.try_push_name(Synchronization2::name())
     }
 
     #[inline]
     pub fn push_khr_synchronization2(&mut self) {
-        assert!(self.try_push_khr_synchronization2());
+        self.push_name(Synchronization2::name());
     }
 
     #[inline]
     pub fn khr_synchronization2(&self) -> bool {
-        self.khr_synchronization2
+        self.is_enabled(Synchronization2::name())
     }
 }
 
@@ -468,6 +608,7 @@ impl Device {
     pub unsafe fn new(
         instance: Instance,
         physical_device: vk::PhysicalDevice,
+        extension_features: &[ExtensionFeaturesDesc],
         callback: impl FnOnce(
             &DeviceProperties,
             &DeviceMemoryProperties,
@@ -483,8 +624,9 @@ impl Device {
         let memory_properties = DeviceMemoryProperties::new(&instance, physical_device);
         let queue_family_properties = DeviceQueueFamilyProperties::new(&instance, physical_device);
 
-        let supported_features = DeviceFeatures::new(&instance, physical_device);
-        let mut enabled_features = DeviceFeatures::default();
+        let supported_features =
+            DeviceFeatures::new(&instance, physical_device, &extensions, extension_features);
+        let mut enabled_features = supported_features.disabled();
 
         callback(
             &properties,
@@ -528,17 +670,20 @@ impl Device {
 
         let mut features_11 = enabled_features.features_11;
         let mut features_12 = enabled_features.features_12;
-        let mut dynamic_rendering_features = enabled_features.dynamic_rendering_features;
-        let mut mesh_shader_features = enabled_features.mesh_shader_features;
-        let mut synchronization2_features = enabled_features.synchronization2_features;
+
+        // Extension feature structs are only valid in the chain if their extension is enabled.
+        features_11.p_next = link_extension_features(
+            enabled_features
+                .extension_features
+                .iter_mut()
+                .filter(|(desc, _)| extensions.is_enabled(desc.extension))
+                .map(|(_, features)| features),
+        );
 
         let mut features = vk::PhysicalDeviceFeatures2::default()
             .features(enabled_features.features)
             .push_next(&mut features_11)
-            .push_next(&mut features_12)
-            .push_next(&mut dynamic_rendering_features)
-            .push_next(&mut mesh_shader_features)
-            .push_next(&mut synchronization2_features);
+            .push_next(&mut features_12);
 
         //Create device
         let device_create_info = vk::DeviceCreateInfo::default()
@@ -548,6 +693,12 @@ impl Device {
 
         let instance_loader = instance.loader();
         let loader = instance_loader.create_device(physical_device, &device_create_info, None)?;
+        unlink_extension_features(
+            enabled_features
+                .extension_features
+                .iter_mut()
+                .map(|(_, features)| features),
+        );
         let dynamic_rendering_loader = DynamicRendering::new(instance_loader, &loader);
         let mesh_shader_loader = MeshShader::new(instance_loader, &loader);
         let swapchain_loader = Swapchain::new(instance_loader, &loader);
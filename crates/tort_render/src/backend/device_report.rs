@@ -0,0 +1,350 @@
+use std::{
+    ffi::CStr,
+    fmt::{self, Display, Formatter},
+    os::raw::c_char,
+};
+
+use ash::vk;
+use serde::Serialize;
+
+use crate::{
+    backend::{Device, DeviceFeature, DeviceFeatures},
+    device_feature,
+};
+
+#[inline]
+fn format_version(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        vk::api_version_major(version),
+        vk::api_version_minor(version),
+        vk::api_version_patch(version)
+    )
+}
+
+#[inline]
+fn c_str_to_string(c_str: &[c_char]) -> String {
+    unsafe { CStr::from_ptr(c_str.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DeviceLimitsReport {
+    pub max_push_constants_size: u32,
+    pub max_bound_descriptor_sets: u32,
+    pub max_per_stage_descriptor_samplers: u32,
+    pub max_per_stage_descriptor_uniform_buffers: u32,
+    pub max_per_stage_descriptor_storage_buffers: u32,
+    pub max_per_stage_descriptor_sampled_images: u32,
+    pub max_per_stage_descriptor_storage_images: u32,
+    pub max_descriptor_set_samplers: u32,
+    pub max_descriptor_set_uniform_buffers: u32,
+    pub max_descriptor_set_storage_buffers: u32,
+    pub max_descriptor_set_sampled_images: u32,
+    pub max_descriptor_set_storage_images: u32,
+    pub max_update_after_bind_descriptors_in_all_pools: u32,
+    pub max_image_dimension_2d: u32,
+    pub max_color_attachments: u32,
+    pub max_compute_work_group_invocations: u32,
+    pub max_compute_work_group_size: [u32; 3],
+    pub timestamp_period: f32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MeshShaderLimitsReport {
+    pub max_task_work_group_invocations: u32,
+    pub max_task_work_group_size: [u32; 3],
+    pub max_task_payload_size: u32,
+    pub max_mesh_work_group_invocations: u32,
+    pub max_mesh_work_group_size: [u32; 3],
+    pub max_mesh_output_vertices: u32,
+    pub max_mesh_output_primitives: u32,
+    pub max_preferred_mesh_work_group_invocations: u32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MemoryHeapReport {
+    pub size: u64,
+    pub device_local: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct QueueFamilyReport {
+    pub index: u32,
+    pub flags: String,
+    pub queue_count: u32,
+    pub timestamp_valid_bits: u32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ExtensionReport {
+    pub name: String,
+    pub spec_version: u32,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FeatureReport {
+    pub name: &'static str,
+    pub supported: bool,
+    pub enabled: bool,
+}
+
+/// A readable snapshot of the capabilities of a [`Device`], meant to be attached to bug reports.
+#[derive(Clone, Debug, Serialize)]
+pub struct DeviceReport {
+    pub device_name: String,
+    pub device_type: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_name: String,
+    pub driver_info: String,
+    pub driver_version: u32,
+    pub api_version: String,
+    pub limits: DeviceLimitsReport,
+    pub mesh_shader_limits: Option<MeshShaderLimitsReport>,
+    pub memory_heaps: Vec<MemoryHeapReport>,
+    pub queue_families: Vec<QueueFamilyReport>,
+    pub extensions: Vec<ExtensionReport>,
+    pub features: Vec<FeatureReport>,
+}
+
+fn feature_reports(
+    supported_features: &DeviceFeatures,
+    enabled_features: &DeviceFeatures,
+    features: &[DeviceFeature],
+) -> Vec<FeatureReport> {
+    features
+        .iter()
+        .map(|feature| {
+            FeatureReport {
+                name: feature.name,
+                supported: (feature.get)(supported_features) == vk::TRUE,
+                enabled: (feature.get)(enabled_features) == vk::TRUE,
+            }
+        })
+        .collect()
+}
+
+impl DeviceReport {
+    pub fn new(device: &Device) -> Self {
+        let properties = device.properties();
+        let limits = &properties.properties.limits;
+        let mesh_shader_properties = &properties.mesh_shader_properties;
+        let memory_properties = &device.memory_properties().memory_properties;
+        let supported_features = device.supported_features();
+        let enabled_features = device.enabled_features();
+
+        let mesh_shader_limits = device.extensions().ext_mesh_shader().then(|| {
+            MeshShaderLimitsReport {
+                max_task_work_group_invocations: mesh_shader_properties
+                    .max_task_work_group_invocations,
+                max_task_work_group_size: mesh_shader_properties.max_task_work_group_size,
+                max_task_payload_size: mesh_shader_properties.max_task_payload_size,
+                max_mesh_work_group_invocations: mesh_shader_properties
+                    .max_mesh_work_group_invocations,
+                max_mesh_work_group_size: mesh_shader_properties.max_mesh_work_group_size,
+                max_mesh_output_vertices: mesh_shader_properties.max_mesh_output_vertices,
+                max_mesh_output_primitives: mesh_shader_properties.max_mesh_output_primitives,
+                max_preferred_mesh_work_group_invocations: mesh_shader_properties
+                    .max_preferred_mesh_work_group_invocations,
+            }
+        });
+
+        let mut extensions = device
+            .extensions()
+            .supported()
+            .iter()
+            .map(|extension| {
+                let name = unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) };
+
+                ExtensionReport {
+                    name: name.to_string_lossy().into_owned(),
+                    spec_version: extension.spec_version,
+                    enabled: device.extensions().is_enabled(name),
+                }
+            })
+            .collect::<Vec<_>>();
+        extensions.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self {
+            device_name: c_str_to_string(&properties.properties.device_name),
+            device_type: format!("{:?}", properties.properties.device_type),
+            vendor_id: properties.properties.vendor_id,
+            device_id: properties.properties.device_id,
+            driver_name: c_str_to_string(&properties.properties_12.driver_name),
+            driver_info: c_str_to_string(&properties.properties_12.driver_info),
+            driver_version: properties.properties.driver_version,
+            api_version: format_version(properties.properties.api_version),
+            limits: DeviceLimitsReport {
+                max_push_constants_size: limits.max_push_constants_size,
+                max_bound_descriptor_sets: limits.max_bound_descriptor_sets,
+                max_per_stage_descriptor_samplers: limits.max_per_stage_descriptor_samplers,
+                max_per_stage_descriptor_uniform_buffers: limits
+                    .max_per_stage_descriptor_uniform_buffers,
+                max_per_stage_descriptor_storage_buffers: limits
+                    .max_per_stage_descriptor_storage_buffers,
+                max_per_stage_descriptor_sampled_images: limits
+                    .max_per_stage_descriptor_sampled_images,
+                max_per_stage_descriptor_storage_images: limits
+                    .max_per_stage_descriptor_storage_images,
+                max_descriptor_set_samplers: limits.max_descriptor_set_samplers,
+                max_descriptor_set_uniform_buffers: limits.max_descriptor_set_uniform_buffers,
+                max_descriptor_set_storage_buffers: limits.max_descriptor_set_storage_buffers,
+                max_descriptor_set_sampled_images: limits.max_descriptor_set_sampled_images,
+                max_descriptor_set_storage_images: limits.max_descriptor_set_storage_images,
+                max_update_after_bind_descriptors_in_all_pools: properties
+                    .properties_12
+                    .max_update_after_bind_descriptors_in_all_pools,
+                max_image_dimension_2d: limits.max_image_dimension2_d,
+                max_color_attachments: limits.max_color_attachments,
+                max_compute_work_group_invocations: limits.max_compute_work_group_invocations,
+                max_compute_work_group_size: limits.max_compute_work_group_size,
+                timestamp_period: limits.timestamp_period,
+            },
+            mesh_shader_limits,
+            memory_heaps: memory_properties.memory_heaps
+                [..memory_properties.memory_heap_count as usize]
+                .iter()
+                .map(|heap| {
+                    MemoryHeapReport {
+                        size: heap.size,
+                        device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                    }
+                })
+                .collect(),
+            queue_families: device
+                .queue_family_properties()
+                .queue_family_properties
+                .iter()
+                .enumerate()
+                .map(|(index, properties)| {
+                    QueueFamilyReport {
+                        index: index as u32,
+                        flags: format!("{:?}", properties.queue_flags),
+                        queue_count: properties.queue_count,
+                        timestamp_valid_bits: properties.timestamp_valid_bits,
+                    }
+                })
+                .collect(),
+            extensions,
+            features: feature_reports(
+                supported_features,
+                enabled_features,
+                &[
+                    device_feature!(features.multi_draw_indirect),
+                    device_feature!(features.sampler_anisotropy),
+                    device_feature!(features.shader_int64),
+                    device_feature!(features.shader_int16),
+                    device_feature!(features_11.shader_draw_parameters),
+                    device_feature!(features_11.storage_buffer16_bit_access),
+                    device_feature!(features_12.buffer_device_address),
+                    device_feature!(features_12.descriptor_indexing),
+                    device_feature!(features_12.descriptor_binding_partially_bound),
+                    device_feature!(features_12.descriptor_binding_variable_descriptor_count),
+                    device_feature!(features_12.runtime_descriptor_array),
+                    device_feature!(features_12.shader_sampled_image_array_non_uniform_indexing),
+                    device_feature!(features_12.draw_indirect_count),
+                    device_feature!(features_12.scalar_block_layout),
+                    device_feature!(features_12.timeline_semaphore),
+                    device_feature!(
+                        vk::PhysicalDeviceDynamicRenderingFeatures,
+                        dynamic_rendering_features.dynamic_rendering
+                    ),
+                    device_feature!(
+                        vk::PhysicalDeviceMeshShaderFeaturesEXT,
+                        mesh_shader_features.task_shader
+                    ),
+                    device_feature!(
+                        vk::PhysicalDeviceMeshShaderFeaturesEXT,
+                        mesh_shader_features.mesh_shader
+                    ),
+                    device_feature!(
+                        vk::PhysicalDeviceSynchronization2Features,
+                        synchronization2_features.synchronization2
+                    ),
+                ],
+            ),
+        }
+    }
+
+    #[inline]
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+impl Display for DeviceReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ({}, vendor {:#06x}, device {:#06x})",
+            self.device_name, self.device_type, self.vendor_id, self.device_id
+        )?;
+        writeln!(
+            f,
+            "  Driver: {} {} ({:#x})",
+            self.driver_name, self.driver_info, self.driver_version
+        )?;
+        writeln!(f, "  Vulkan: {}", self.api_version)?;
+
+        writeln!(f, "  Limits: {:?}", self.limits)?;
+        match &self.mesh_shader_limits {
+            Some(mesh_shader_limits) => {
+                writeln!(f, "  Mesh shader limits: {mesh_shader_limits:?}")?
+            }
+            None => writeln!(f, "  Mesh shader limits: unavailable")?,
+        }
+
+        writeln!(f, "  Memory heaps:")?;
+        for (index, heap) in self.memory_heaps.iter().enumerate() {
+            writeln!(
+                f,
+                "    {index}: {} MiB{}",
+                heap.size / (1024 * 1024),
+                if heap.device_local {
+                    " (device local)"
+                } else {
+                    ""
+                }
+            )?;
+        }
+
+        writeln!(f, "  Queue families:")?;
+        for queue_family in &self.queue_families {
+            writeln!(
+                f,
+                "    {}: {} x {}, {} timestamp bits",
+                queue_family.index,
+                queue_family.queue_count,
+                queue_family.flags,
+                queue_family.timestamp_valid_bits
+            )?;
+        }
+
+        writeln!(f, "  Features:")?;
+        for feature in &self.features {
+            writeln!(
+                f,
+                "    {}: {}",
+                feature.name,
+                match (feature.supported, feature.enabled) {
+                    (_, true) => "enabled",
+                    (true, false) => "supported",
+                    (false, false) => "unsupported",
+                }
+            )?;
+        }
+
+        write!(
+            f,
+            "  Extensions: {} supported, {} enabled",
+            self.extensions.len(),
+            self.extensions
+                .iter()
+                .filter(|extension| extension.enabled)
+                .count()
+        )
+    }
+}
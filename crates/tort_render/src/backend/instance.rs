@@ -1,11 +1,12 @@
 use std::{
+    cmp::Reverse,
     env,
     ffi::CStr,
     os::raw::{c_char, c_void},
     sync::Arc,
 };
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use ash::{
     extensions::{
         ext,
@@ -16,11 +17,16 @@ use ash::{
     prelude::VkResult,
     vk, Entry,
 };
-use log::log;
+use log::{info, log};
 use tort_ecs::{self as bevy_ecs, system::Resource};
 
 use crate::backend::utils::message_severity;
 
+const EXT_VALIDATION_FEATURES: &CStr =
+    unsafe { CStr::from_bytes_with_nul_unchecked(b"VK_EXT_validation_features\0") };
+const KHR_PORTABILITY_ENUMERATION: &CStr =
+    unsafe { CStr::from_bytes_with_nul_unchecked(b"VK_KHR_portability_enumeration\0") };
+
 #[inline]
 fn application_info_from_cargo_toml(api_version: u32) -> vk::ApplicationInfo<'static> {
     let version = vk::make_api_version(
@@ -105,12 +111,6 @@ pub struct InstanceExtensions {
     supported: Vec<vk::ExtensionProperties>,
     supported_khronos_validation: Vec<vk::ExtensionProperties>,
     enabled: Vec<*const c_char>,
-
-    ext_debug_utils: bool,
-    ext_validation_features: bool,
-    khr_get_surface_capabilities2: bool,
-    khr_portability_enumeration: bool,
-    khr_surface: bool,
 }
 
 impl InstanceExtensions {
@@ -129,137 +129,221 @@ impl InstanceExtensions {
             supported,
             supported_khronos_validation,
             enabled: Vec::new(),
-
-            ext_debug_utils: false,
-            ext_validation_features: false,
-            khr_get_surface_capabilities2: false,
-            khr_portability_enumeration: false,
-            khr_surface: false,
         })
     }
 
     #[inline]
-    unsafe fn try_push(&mut self, name: *const c_char) -> bool {
-        if self
-            .supported
+    pub fn is_supported(&self, name: &CStr) -> bool {
+        self.supported
             .iter()
-            .any(|e| libc::strcmp(e.extension_name.as_ptr(), name) == 0)
-            || self
-                .supported_khronos_validation
-                .iter()
-                .any(|e| libc::strcmp(e.extension_name.as_ptr(), name) == 0)
-        {
-            self.enabled.push(name);
+            .chain(&self.supported_khronos_validation)
+            .any(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) } == name)
+    }
+
+    #[inline]
+    pub fn is_enabled(&self, name: &CStr) -> bool {
+        self.enabled
+            .iter()
+            .any(|enabled| unsafe { CStr::from_ptr(*enabled) } == name)
+    }
+
+    /// Enables the extension if it is supported. Enabling an extension twice has no effect.
+    #[inline]
+    pub fn try_push_name(&mut self, name: &'static CStr) -> bool {
+        if self.is_enabled(name) {
+            true
+        } else if self.is_supported(name) {
+            self.enabled.push(name.as_ptr());
             true
         } else {
             false
         }
     }
 
+    #[inline]
+    pub fn push_name(&mut self, name: &'static CStr) {
+        assert!(
+            self.try_push_name(name),
+            "Instance extension {name:?} is not supported"
+        );
+    }
+
+    #[inline]
+    pub fn enabled_names(&self) -> impl Iterator<Item = &CStr> {
+        self.enabled
+            .iter()
+            .map(|enabled| unsafe { CStr::from_ptr(*enabled) })
+    }
+
     #[inline]
     pub fn try_push_ext_debug_utils(&mut self) -> bool {
-        if unsafe { self.try_push(DebugUtils::name().as_ptr()) } {
-            self.ext_debug_utils = true;
-            true
-        } else {
-            false
-        }
+        self.try_push_name(DebugUtils::name())
     }
 
     #[inline]
     pub fn push_ext_debug_utils(&mut self) {
-        assert!(self.try_push_ext_debug_utils())
+        self.push_name(DebugUtils::name())
     }
 
     #[inline]
     pub fn ext_debug_utils(&self) -> bool {
-        self.ext_debug_utils
+        self.is_enabled(DebugUtils::name())
     }
 
     #[inline]
     pub fn try_push_ext_validation_features(&mut self) -> bool {
-        if unsafe { self.try_push(b"VK_EXT_validation_features\0".as_ptr().cast()) } {
-            self.ext_validation_features = true;
-            true
-        } else {
-            false
-        }
+        self.try_push_name(EXT_VALIDATION_FEATURES)
     }
 
     #[inline]
     pub fn push_ext_validation_features(&mut self) {
-        assert!(self.try_push_ext_validation_features())
+        self.push_name(EXT_VALIDATION_FEATURES)
     }
 
     #[inline]
     pub fn ext_validation_features(&self) -> bool {
-        self.ext_validation_features
+        self.is_enabled(EXT_VALIDATION_FEATURES)
     }
 
     #[inline]
     pub fn try_push_khr_get_surface_capabilities2(&mut self) -> bool {
-        if unsafe { self.try_push(GetSurfaceCapabilities2::name().as_ptr()) } {
-            self.khr_get_surface_capabilities2 = true;
-            true
-        } else {
-            false
-        }
+        self.try_push_name(GetSurfaceCapabilities2::name())
     }
 
     #[inline]
     pub fn push_khr_get_surface_capabilities2(&mut self) {
-        assert!(self.try_push_khr_get_surface_capabilities2())
+        self.push_name(GetSurfaceCapabilities2::name())
     }
 
     #[inline]
     pub fn khr_get_surface_capabilities2(&self) -> bool {
-        self.khr_get_surface_capabilities2
+        self.is_enabled(GetSurfaceCapabilities2::name())
     }
 
     #[inline]
     pub fn try_push_khr_portability_enumeration(&mut self) -> bool {
-        if unsafe { self.try_push(b"VK_KHR_portability_enumeration\0".as_ptr().cast()) } {
-            self.khr_portability_enumeration = true;
-            true
-        } else {
-            false
-        }
+        self.try_push_name(KHR_PORTABILITY_ENUMERATION)
     }
 
     #[inline]
     pub fn push_khr_portability_enumeration(&mut self) {
-        assert!(self.try_push_khr_portability_enumeration());
+        self.push_name(KHR_PORTABILITY_ENUMERATION)
     }
 
     #[inline]
     pub fn khr_portability_enumeration(&self) -> bool {
-        self.khr_portability_enumeration
+        self.is_enabled(KHR_PORTABILITY_ENUMERATION)
     }
 
     #[inline]
     pub fn try_push_khr_surface(&mut self) -> bool {
-        if unsafe { self.try_push(Surface::name().as_ptr()) } {
-            self.khr_surface = true;
-            true
-        } else {
-            false
-        }
+        self.try_push_name(Surface::name())
     }
 
     #[inline]
     pub fn push_khr_surface(&mut self) {
-        assert!(self.try_push_khr_surface())
+        self.push_name(Surface::name())
     }
 
     #[inline]
     pub fn khr_surface(&self) -> bool {
-        self.khr_surface
+        self.is_enabled(Surface::name())
+    }
+
+    /// Pushes `VK_KHR_surface` and every platform surface extension that is supported.
+    /// Only needed when rendering to windows.
+    pub fn push_surface_extensions(&mut self) {
+        if cfg!(all(
+            unix,
+            not(target_os = "android"),
+            not(target_os = "macos")
+        )) {
+            self.try_push_name(khr::XlibSurface::name());
+            self.try_push_name(khr::XcbSurface::name());
+            self.try_push_name(khr::WaylandSurface::name());
+        }
+        if cfg!(target_os = "android") {
+            self.try_push_name(khr::AndroidSurface::name());
+        }
+        if cfg!(target_os = "windows") {
+            self.try_push_name(khr::Win32Surface::name());
+        }
+        if cfg!(target_os = "macos") {
+            self.try_push_name(ext::MetalSurface::name());
+        }
+
+        self.push_khr_surface();
     }
 }
 
 unsafe impl Send for InstanceExtensions {}
 unsafe impl Sync for InstanceExtensions {}
 
+/// How [`Instance::select_physical_device`] chooses between the available physical devices.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PhysicalDeviceSelector {
+    /// Prefers discrete, integrated, virtual and CPU devices in that order,
+    /// then the device with the most device local memory.
+    #[default]
+    Optimal,
+    /// A device whose name contains the substring, ignoring case.
+    Name(String),
+    /// A device of the earliest listed type that is available.
+    DeviceTypes(Vec<vk::PhysicalDeviceType>),
+    /// The device at this index in enumeration order.
+    Index(usize),
+}
+
+impl PhysicalDeviceSelector {
+    pub const ENV_VAR: &'static str = "TORT_PHYSICAL_DEVICE";
+
+    /// Reads the selector from the `TORT_PHYSICAL_DEVICE` environment variable, see
+    /// [`PhysicalDeviceSelector::parse`].
+    pub fn from_env() -> Option<Self> {
+        env::var(Self::ENV_VAR)
+            .ok()
+            .map(|value| Self::parse(&value))
+    }
+
+    /// Parses an index (`1`), a comma separated list of device types (`integrated,cpu`) or else a
+    /// name substring (`llvmpipe`).
+    pub fn parse(value: &str) -> Self {
+        if let Ok(index) = value.trim().parse() {
+            return Self::Index(index)
+        }
+
+        let device_types = value
+            .split(',')
+            .map(|device_type| {
+                match device_type.trim().to_lowercase().as_str() {
+                    "discrete" => Some(vk::PhysicalDeviceType::DISCRETE_GPU),
+                    "integrated" => Some(vk::PhysicalDeviceType::INTEGRATED_GPU),
+                    "virtual" => Some(vk::PhysicalDeviceType::VIRTUAL_GPU),
+                    "cpu" => Some(vk::PhysicalDeviceType::CPU),
+                    "other" => Some(vk::PhysicalDeviceType::OTHER),
+                    _ => None,
+                }
+            })
+            .collect::<Option<Vec<_>>>();
+
+        match device_types {
+            Some(device_types) => Self::DeviceTypes(device_types),
+            None => Self::Name(value.to_owned()),
+        }
+    }
+
+    #[inline]
+    fn device_type_rank(device_type: vk::PhysicalDeviceType) -> usize {
+        match device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 0,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+            vk::PhysicalDeviceType::CPU => 3,
+            _ => 4,
+        }
+    }
+}
+
 struct Inner {
     entry_loader: Entry,
 
@@ -313,27 +397,6 @@ impl Instance {
 
             let mut extensions = InstanceExtensions::new(&entry_loader, &layers)?;
 
-            if cfg!(all(
-                unix,
-                not(target_os = "android"),
-                not(target_os = "macos")
-            )) {
-                extensions.try_push(khr::XlibSurface::name().as_ptr());
-                extensions.try_push(khr::XcbSurface::name().as_ptr());
-                extensions.try_push(khr::WaylandSurface::name().as_ptr());
-            }
-            if cfg!(target_os = "android") {
-                extensions.try_push(khr::AndroidSurface::name().as_ptr());
-            }
-            if cfg!(target_os = "windows") {
-                extensions.try_push(khr::Win32Surface::name().as_ptr());
-            }
-            if cfg!(target_os = "macos") {
-                extensions.try_push(ext::MetalSurface::name().as_ptr());
-            }
-
-            extensions.push_khr_surface();
-
             let application_info = application_info_from_cargo_toml(callback(
                 &entry_loader,
                 &layers,
@@ -411,49 +474,129 @@ impl Instance {
         }
     }
 
-    pub fn find_optimal_physical_device(&self) -> vk::PhysicalDevice {
-        let mut heap_size: u64 = 0;
-        let mut physical_device = vk::PhysicalDevice::null();
+    /// Selects a physical device according to `selector`.
+    ///
+    /// Devices that don't support all of `required_extensions` are never selected. Every rejected
+    /// device is logged together with the reason it was rejected.
+    pub fn select_physical_device(
+        &self,
+        selector: &PhysicalDeviceSelector,
+        required_extensions: &[&CStr],
+    ) -> Result<vk::PhysicalDevice> {
+        let mut candidates = Vec::new();
 
-        for current_physical_device in self.0.physical_devices.iter() {
+        for (index, physical_device) in self.0.physical_devices.iter().copied().enumerate() {
             let properties = unsafe {
                 self.0
                     .loader
-                    .get_physical_device_properties(*current_physical_device)
+                    .get_physical_device_properties(physical_device)
             };
+            let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }.to_string_lossy();
 
-            if properties.device_type != vk::PhysicalDeviceType::DISCRETE_GPU {
+            let supported_extensions = unsafe {
+                self.0
+                    .loader
+                    .enumerate_device_extension_properties(physical_device)
+            }?;
+            let missing_extensions = required_extensions
+                .iter()
+                .filter(|required| {
+                    !supported_extensions.iter().any(|supported| {
+                        (unsafe { CStr::from_ptr(supported.extension_name.as_ptr()) }) == **required
+                    })
+                })
+                .map(|missing| missing.to_string_lossy())
+                .collect::<Vec<_>>();
+
+            if !missing_extensions.is_empty() {
+                info!(
+                    "Rejected physical device {index} \"{name}\" ({:?}): missing extensions {}",
+                    properties.device_type,
+                    missing_extensions.join(", ")
+                );
                 continue
             }
 
+            let type_rank = match selector {
+                PhysicalDeviceSelector::Optimal => {
+                    PhysicalDeviceSelector::device_type_rank(properties.device_type)
+                }
+                PhysicalDeviceSelector::Name(substring) => {
+                    if !name.to_lowercase().contains(&substring.to_lowercase()) {
+                        info!(
+                            "Rejected physical device {index} \"{name}\" ({:?}): name doesn't contain \"{substring}\"",
+                            properties.device_type
+                        );
+                        continue
+                    }
+
+                    PhysicalDeviceSelector::device_type_rank(properties.device_type)
+                }
+                PhysicalDeviceSelector::DeviceTypes(device_types) => {
+                    match device_types
+                        .iter()
+                        .position(|device_type| *device_type == properties.device_type)
+                    {
+                        Some(position) => position,
+                        None => {
+                            info!(
+                                "Rejected physical device {index} \"{name}\" ({:?}): device type not in {device_types:?}",
+                                properties.device_type
+                            );
+                            continue
+                        }
+                    }
+                }
+                PhysicalDeviceSelector::Index(selected_index) => {
+                    if index != *selected_index {
+                        info!(
+                            "Rejected physical device {index} \"{name}\" ({:?}): index {selected_index} was requested",
+                            properties.device_type
+                        );
+                        continue
+                    }
+
+                    0
+                }
+            };
+
             let memory_properties = unsafe {
                 self.0
                     .loader
-                    .get_physical_device_memory_properties(*current_physical_device)
+                    .get_physical_device_memory_properties(physical_device)
             };
-            let mut current_heap_size: u64 = 0;
+            let heap_size = memory_properties.memory_heaps
+                [..memory_properties.memory_heap_count as usize]
+                .iter()
+                .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+                .map(|heap| heap.size)
+                .sum::<u64>();
+
+            // Lower device type rank first, then more device local memory.
+            candidates.push((
+                (type_rank, Reverse(heap_size)),
+                index,
+                name.into_owned(),
+                properties.device_type,
+                physical_device,
+            ));
+        }
 
-            for i in 0..memory_properties.memory_heap_count as usize {
-                let current_heap = &memory_properties.memory_heaps[i];
+        candidates.sort_by_key(|(rank, ..)| *rank);
 
-                if (current_heap.flags & vk::MemoryHeapFlags::DEVICE_LOCAL)
-                    == vk::MemoryHeapFlags::DEVICE_LOCAL
-                {
-                    current_heap_size += current_heap.size;
-                }
-            }
+        let Some((_, index, name, device_type, physical_device)) = candidates.first() else {
+            bail!("No physical device matches {selector:?}");
+        };
 
-            if current_heap_size > heap_size {
-                heap_size = current_heap_size;
-                physical_device = *current_physical_device;
-            }
+        for (_, index, name, device_type, _) in &candidates[1..] {
+            info!(
+                "Rejected physical device {index} \"{name}\" ({device_type:?}): a better device is available"
+            );
         }
 
-        if physical_device == vk::PhysicalDevice::null() {
-            physical_device = self.0.physical_devices[0];
-        }
+        info!("Selected physical device {index} \"{name}\" ({device_type:?})");
 
-        physical_device
+        Ok(*physical_device)
     }
 
     #[inline]
@@ -1,6 +1,8 @@
 pub mod command;
 mod device;
+mod device_report;
 mod instance;
+mod requirements;
 pub mod resource;
 mod surface;
 mod swapchain;
@@ -8,7 +10,9 @@ pub mod sync;
 pub mod utils;
 
 pub use device::*;
+pub use device_report::*;
 pub use instance::*;
+pub use requirements::*;
 pub use surface::*;
 pub use swapchain::*;
 
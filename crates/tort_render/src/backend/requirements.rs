@@ -0,0 +1,319 @@
+use std::{
+    any::TypeId,
+    ffi::CStr,
+    fmt::{self, Debug, Formatter},
+};
+
+use anyhow::{bail, Result};
+use ash::vk;
+use tort_ecs::{self as bevy_ecs, system::Resource};
+
+use crate::backend::{
+    DeviceExtensions, DeviceFeatures, ExtensionFeatures, ExtensionFeaturesDesc, InstanceExtensions,
+};
+
+/// A single device feature, identified by its field in [`DeviceFeatures`].
+/// Created with [`device_feature!`](crate::device_feature).
+#[derive(Copy, Clone)]
+pub struct DeviceFeature {
+    pub name: &'static str,
+    /// The extension feature struct the feature is in, `None` for core features. Its extension
+    /// is the one it was registered with, see [`DeviceRequirements::register_extension_features`].
+    pub extension_features: Option<TypeId>,
+    pub get: fn(&DeviceFeatures) -> vk::Bool32,
+    pub set: fn(&mut DeviceFeatures, vk::Bool32),
+}
+
+impl Debug for DeviceFeature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl PartialEq for DeviceFeature {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for DeviceFeature {}
+
+/// Creates a [`DeviceFeature`] from a field of the core features of [`DeviceFeatures`],
+/// e.g. `device_feature!(features_12.timeline_semaphore)`, or from a field of an extension
+/// feature struct named `group`, e.g.
+/// `device_feature!(vk::PhysicalDeviceMeshShaderFeaturesEXT, mesh_shader_features.mesh_shader)`.
+#[macro_export]
+macro_rules! device_feature {
+    ($group:ident . $field:ident) => {
+        $crate::backend::DeviceFeature {
+            name: concat!(stringify!($group), ".", stringify!($field)),
+            extension_features: None,
+            get: |features: &$crate::backend::DeviceFeatures| features.$group.$field,
+            set: |features: &mut $crate::backend::DeviceFeatures, value| {
+                features.$group.$field = value
+            },
+        }
+    };
+    ($ty:ty, $group:ident . $field:ident) => {
+        $crate::backend::DeviceFeature {
+            name: concat!(stringify!($group), ".", stringify!($field)),
+            extension_features: Some(::std::any::TypeId::of::<$ty>()),
+            get: |features: &$crate::backend::DeviceFeatures| {
+                features
+                    .get::<$ty>()
+                    .map_or($crate::backend::vk::vk::FALSE, |features| features.$field)
+            },
+            set: |features: &mut $crate::backend::DeviceFeatures, value| {
+                if let Some(features) = features.get_mut::<$ty>() {
+                    features.$field = value
+                }
+            },
+        }
+    };
+}
+
+/// Extensions and features the renderer needs, resolved against the device when it is created.
+///
+/// Plugins contribute their requirements by modifying this resource before the
+/// [`RenderPlugin`](crate::RenderPlugin) is built, features of extension feature structs also
+/// need the struct to be registered with [`DeviceRequirements::register_extension_features`].
+#[derive(Clone, Debug, Default, Resource)]
+pub struct DeviceRequirements {
+    /// The extension feature structs that are queried and chained into the device creation.
+    pub extension_features: Vec<ExtensionFeaturesDesc>,
+    pub required_instance_extensions: Vec<&'static CStr>,
+    pub optional_instance_extensions: Vec<&'static CStr>,
+    pub required_extensions: Vec<&'static CStr>,
+    pub optional_extensions: Vec<&'static CStr>,
+    pub required_features: Vec<DeviceFeature>,
+    pub optional_features: Vec<DeviceFeature>,
+}
+
+/// What [`DeviceRequirements::negotiate`] enabled, and which optional requirements were missing.
+#[derive(Clone, Debug, Default, Resource)]
+pub struct NegotiatedCapabilities {
+    pub instance_extensions: Vec<String>,
+    pub extensions: Vec<String>,
+    pub features: Vec<&'static str>,
+    pub missing_optional_instance_extensions: Vec<&'static CStr>,
+    pub missing_optional_extensions: Vec<&'static CStr>,
+    pub missing_optional_features: Vec<&'static str>,
+}
+
+impl NegotiatedCapabilities {
+    #[inline]
+    pub fn has_feature(&self, feature: &DeviceFeature) -> bool {
+        self.features.contains(&feature.name)
+    }
+}
+
+impl DeviceRequirements {
+    /// Registers the feature struct `T` of `extension`, registering a struct twice has no
+    /// effect.
+    pub fn register_extension_features<T: ExtensionFeatures + Default>(
+        &mut self,
+        extension: &'static CStr,
+    ) -> &mut Self {
+        let desc = ExtensionFeaturesDesc::new::<T>(extension);
+        if !self
+            .extension_features
+            .iter()
+            .any(|registered| registered.type_id == desc.type_id)
+        {
+            self.extension_features.push(desc);
+        }
+        self
+    }
+
+    /// The extension of the feature struct of `feature`, `None` for core features and
+    /// unregistered feature structs.
+    pub fn extension(&self, feature: &DeviceFeature) -> Option<&'static CStr> {
+        let type_id = feature.extension_features?;
+
+        self.extension_features
+            .iter()
+            .find(|desc| desc.type_id == type_id)
+            .map(|desc| desc.extension)
+    }
+
+    #[inline]
+    pub fn require_instance_extension(&mut self, name: &'static CStr) -> &mut Self {
+        self.required_instance_extensions.push(name);
+        self
+    }
+
+    #[inline]
+    pub fn request_instance_extension(&mut self, name: &'static CStr) -> &mut Self {
+        self.optional_instance_extensions.push(name);
+        self
+    }
+
+    #[inline]
+    pub fn require_extension(&mut self, name: &'static CStr) -> &mut Self {
+        self.required_extensions.push(name);
+        self
+    }
+
+    #[inline]
+    pub fn request_extension(&mut self, name: &'static CStr) -> &mut Self {
+        self.optional_extensions.push(name);
+        self
+    }
+
+    #[inline]
+    pub fn require_feature(&mut self, feature: DeviceFeature) -> &mut Self {
+        self.required_features.push(feature);
+        self
+    }
+
+    #[inline]
+    pub fn request_feature(&mut self, feature: DeviceFeature) -> &mut Self {
+        self.optional_features.push(feature);
+        self
+    }
+
+    pub fn extend(&mut self, other: DeviceRequirements) -> &mut Self {
+        for desc in other.extension_features {
+            if !self
+                .extension_features
+                .iter()
+                .any(|registered| registered.type_id == desc.type_id)
+            {
+                self.extension_features.push(desc);
+            }
+        }
+        self.required_instance_extensions
+            .extend(other.required_instance_extensions);
+        self.optional_instance_extensions
+            .extend(other.optional_instance_extensions);
+        self.required_extensions.extend(other.required_extensions);
+        self.optional_extensions.extend(other.optional_extensions);
+        self.required_features.extend(other.required_features);
+        self.optional_features.extend(other.optional_features);
+        self
+    }
+
+    /// Every device extension a physical device has to support, including the extensions of the
+    /// required features.
+    pub fn required_extension_names(&self) -> Vec<&'static CStr> {
+        let mut names = self.required_extensions.clone();
+
+        for extension in self
+            .required_features
+            .iter()
+            .filter_map(|feature| self.extension(feature))
+        {
+            if !names.contains(&extension) {
+                names.push(extension);
+            }
+        }
+
+        names
+    }
+
+    /// Enables the instance extensions, failing if a required one is unsupported.
+    pub fn negotiate_instance(
+        &self,
+        extensions: &mut InstanceExtensions,
+        negotiated: &mut NegotiatedCapabilities,
+    ) -> Result<()> {
+        let missing = self
+            .required_instance_extensions
+            .iter()
+            .copied()
+            .filter(|name| !extensions.try_push_name(name))
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            bail!("Missing required instance extensions {missing:?}");
+        }
+
+        negotiated.missing_optional_instance_extensions = self
+            .optional_instance_extensions
+            .iter()
+            .copied()
+            .filter(|name| !extensions.try_push_name(name))
+            .collect();
+
+        negotiated.instance_extensions = extensions
+            .enabled_names()
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+
+        Ok(())
+    }
+
+    /// Enables the device extensions and features, failing if a required one is unsupported.
+    /// Features of extension structs also enable their extension.
+    pub fn negotiate(
+        &self,
+        extensions: &mut DeviceExtensions,
+        supported_features: &DeviceFeatures,
+        enabled_features: &mut DeviceFeatures,
+        negotiated: &mut NegotiatedCapabilities,
+    ) -> Result<()> {
+        let mut try_enable_feature = |feature: &DeviceFeature| {
+            let supported = (feature.get)(supported_features) == vk::TRUE
+                && self
+                    .extension(feature)
+                    .map_or(true, |extension| extensions.try_push_name(extension));
+
+            if supported {
+                (feature.set)(enabled_features, vk::TRUE);
+            }
+
+            supported
+        };
+
+        let missing_features = self
+            .required_features
+            .iter()
+            .filter(|feature| !try_enable_feature(feature))
+            .map(|feature| feature.name)
+            .collect::<Vec<_>>();
+
+        negotiated.missing_optional_features = self
+            .optional_features
+            .iter()
+            .filter(|feature| !try_enable_feature(feature))
+            .map(|feature| feature.name)
+            .collect();
+
+        let missing_extensions = self
+            .required_extensions
+            .iter()
+            .copied()
+            .filter(|name| !extensions.try_push_name(name))
+            .collect::<Vec<_>>();
+
+        if !missing_extensions.is_empty() || !missing_features.is_empty() {
+            bail!(
+                "Missing required device extensions {missing_extensions:?} and features {missing_features:?}"
+            );
+        }
+
+        negotiated.missing_optional_extensions = self
+            .optional_extensions
+            .iter()
+            .copied()
+            .filter(|name| !extensions.try_push_name(name))
+            .collect();
+
+        negotiated.extensions = extensions
+            .enabled_names()
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        negotiated.features = self
+            .required_features
+            .iter()
+            .chain(&self.optional_features)
+            .filter(|feature| (feature.get)(enabled_features) == vk::TRUE)
+            .map(|feature| feature.name)
+            .collect();
+        negotiated.features.sort_unstable();
+        negotiated.features.dedup();
+
+        Ok(())
+    }
+}
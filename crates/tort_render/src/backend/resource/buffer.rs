@@ -1,4 +1,4 @@
-use std::{borrow::Cow, ops::Deref};
+use std::{borrow::Cow, ops::Deref, ptr, slice, sync::Arc};
 
 use ash::vk;
 use vk_mem_alloc::{
@@ -6,6 +6,7 @@ use vk_mem_alloc::{
 };
 
 use crate::backend::{
+    resource::MemoryBlock,
     utils::{debug_utils, BackendError},
     Device,
 };
@@ -20,20 +21,34 @@ pub struct BufferDesc {
     pub memory_usage: MemoryUsage,
 }
 
+enum BufferMemory {
+    Dedicated {
+        allocation: Allocation,
+        allocation_info: AllocationInfo,
+    },
+    Aliased {
+        block: Arc<MemoryBlock>,
+        offset: vk::DeviceSize,
+    },
+}
+
 pub struct Buffer {
     buffer: vk::Buffer,
-    allocation: Allocation,
-    allocation_info: AllocationInfo,
+    memory: BufferMemory,
     desc: BufferDesc,
     device: Device,
 }
 
 impl Buffer {
-    pub fn new(device: Device, desc: &BufferDesc) -> Result<Self, BackendError> {
-        let buffer_create_info = vk::BufferCreateInfo::default()
+    fn create_info(desc: &BufferDesc) -> vk::BufferCreateInfo<'static> {
+        vk::BufferCreateInfo::default()
             .flags(desc.flags)
             .size(desc.size)
-            .usage(desc.usage);
+            .usage(desc.usage)
+    }
+
+    pub fn new(device: Device, desc: &BufferDesc) -> Result<Self, BackendError> {
+        let buffer_create_info = Self::create_info(desc);
 
         let allocation_create_info = AllocationCreateInfo {
             flags: desc.allocation_flags,
@@ -55,8 +70,69 @@ impl Buffer {
 
         Ok(Self {
             buffer,
-            allocation,
-            allocation_info,
+            memory: BufferMemory::Dedicated {
+                allocation,
+                allocation_info,
+            },
+            desc: desc.clone(),
+            device,
+        })
+    }
+
+    /// Returns the memory requirements of a buffer created with `desc`.
+    ///
+    /// Vulkan 1.2 can't query them without a buffer, so a temporary one is created.
+    pub fn memory_requirements(
+        device: &Device,
+        desc: &BufferDesc,
+    ) -> Result<vk::MemoryRequirements, BackendError> {
+        let device_loader = device.loader();
+
+        unsafe {
+            let buffer = device_loader.create_buffer(&Self::create_info(desc), None)?;
+            let memory_requirements = device_loader.get_buffer_memory_requirements(buffer);
+            device_loader.destroy_buffer(buffer, None);
+
+            Ok(memory_requirements)
+        }
+    }
+
+    /// Creates a buffer placed at `offset` into `block`, which may be shared with other
+    /// resources.
+    ///
+    /// The allocation fields of `desc` are ignored.
+    pub fn new_aliased(
+        device: Device,
+        desc: &BufferDesc,
+        block: Arc<MemoryBlock>,
+        offset: vk::DeviceSize,
+    ) -> Result<Self, BackendError> {
+        let buffer = unsafe {
+            device
+                .loader()
+                .create_buffer(&Self::create_info(desc), None)
+        }?;
+
+        if let Err(e) = unsafe {
+            vk_mem_alloc::bind_buffer_memory2(
+                *device.allocator(),
+                *block.allocation(),
+                offset,
+                buffer,
+                ptr::null(),
+            )
+        } {
+            unsafe { device.loader().destroy_buffer(buffer, None) };
+            return Err(e.into())
+        }
+
+        if let Some(label) = &desc.label {
+            unsafe { debug_utils::set_object_name(&device, buffer, label) }?;
+        }
+
+        Ok(Self {
+            buffer,
+            memory: BufferMemory::Aliased { block, offset },
             desc: desc.clone(),
             device,
         })
@@ -64,18 +140,129 @@ impl Buffer {
 
     #[inline]
     pub fn allocation(&self) -> &Allocation {
-        &self.allocation
+        match &self.memory {
+            BufferMemory::Dedicated { allocation, .. } => allocation,
+            BufferMemory::Aliased { block, .. } => block.allocation(),
+        }
     }
 
     #[inline]
     pub fn allocation_info(&self) -> &AllocationInfo {
-        &self.allocation_info
+        match &self.memory {
+            BufferMemory::Dedicated {
+                allocation_info, ..
+            } => allocation_info,
+            BufferMemory::Aliased { block, .. } => block.allocation_info(),
+        }
+    }
+
+    /// Returns the block the buffer is placed into and the offset into it, if it was created with
+    /// [`Buffer::new_aliased`].
+    #[inline]
+    pub fn aliased_memory(&self) -> Option<(&Arc<MemoryBlock>, vk::DeviceSize)> {
+        match &self.memory {
+            BufferMemory::Dedicated { .. } => None,
+            BufferMemory::Aliased { block, offset } => Some((block, *offset)),
+        }
     }
 
     #[inline]
     pub fn desc(&self) -> &BufferDesc {
         &self.desc
     }
+
+    /// Returns the persistently mapped memory of the buffer, or `None` if it wasn't created
+    /// with [`AllocationCreateFlags::MAPPED`].
+    #[inline]
+    pub fn mapped_slice(&self) -> Option<&[u8]> {
+        let BufferMemory::Dedicated {
+            allocation_info, ..
+        } = &self.memory
+        else {
+            return None
+        };
+        let mapped_data = allocation_info.mapped_data;
+
+        if mapped_data.is_null() {
+            None
+        } else {
+            Some(unsafe { slice::from_raw_parts(mapped_data.cast(), self.desc.size as usize) })
+        }
+    }
+
+    /// Returns the persistently mapped memory of the buffer for writing, see
+    /// [`Buffer::mapped_slice`].
+    #[inline]
+    pub fn mapped_slice_mut(&mut self) -> Option<&mut [u8]> {
+        let BufferMemory::Dedicated {
+            allocation_info, ..
+        } = &self.memory
+        else {
+            return None
+        };
+        let mapped_data = allocation_info.mapped_data;
+
+        if mapped_data.is_null() {
+            None
+        } else {
+            Some(unsafe { slice::from_raw_parts_mut(mapped_data.cast(), self.desc.size as usize) })
+        }
+    }
+
+    /// Returns the allocation range of `offset` and `size` relative to the buffer.
+    #[inline]
+    fn allocation_range(
+        &self,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> (Allocation, vk::DeviceSize, vk::DeviceSize) {
+        match &self.memory {
+            BufferMemory::Dedicated { allocation, .. } => (*allocation, offset, size),
+            // `WHOLE_SIZE` would reach past the buffer into the rest of the block.
+            BufferMemory::Aliased {
+                block,
+                offset: block_offset,
+            } => {
+                (
+                    *block.allocation(),
+                    block_offset + offset,
+                    if size == vk::WHOLE_SIZE {
+                        self.desc.size - offset
+                    } else {
+                        size
+                    },
+                )
+            }
+        }
+    }
+
+    /// Makes writes of the device visible to the host for memory that isn't host coherent.
+    #[inline]
+    pub fn invalidate(
+        &self,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Result<(), BackendError> {
+        let (allocation, offset, size) = self.allocation_range(offset, size);
+
+        unsafe {
+            vk_mem_alloc::invalidate_allocation(*self.device.allocator(), allocation, offset, size)
+        }?;
+
+        Ok(())
+    }
+
+    /// Makes writes of the host visible to the device for memory that isn't host coherent.
+    #[inline]
+    pub fn flush(&self, offset: vk::DeviceSize, size: vk::DeviceSize) -> Result<(), BackendError> {
+        let (allocation, offset, size) = self.allocation_range(offset, size);
+
+        unsafe {
+            vk_mem_alloc::flush_allocation(*self.device.allocator(), allocation, offset, size)
+        }?;
+
+        Ok(())
+    }
 }
 
 impl Deref for Buffer {
@@ -90,8 +277,14 @@ impl Deref for Buffer {
 impl Drop for Buffer {
     #[inline]
     fn drop(&mut self) {
-        unsafe {
-            vk_mem_alloc::destroy_buffer(*self.device.allocator(), self.buffer, self.allocation);
+        match &self.memory {
+            BufferMemory::Dedicated { allocation, .. } => unsafe {
+                vk_mem_alloc::destroy_buffer(*self.device.allocator(), self.buffer, *allocation);
+            },
+            // The block is freed once the last resource placed into it is dropped.
+            BufferMemory::Aliased { .. } => unsafe {
+                self.device.loader().destroy_buffer(self.buffer, None);
+            },
         }
     }
 }
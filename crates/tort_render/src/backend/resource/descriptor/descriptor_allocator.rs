@@ -0,0 +1,132 @@
+use std::{borrow::Cow, sync::Arc};
+
+use ash::vk;
+use tort_ecs::{
+    system::{Res, ResMut, Resource},
+    {self as bevy_ecs},
+};
+
+use crate::{
+    backend::{
+        resource::descriptor::{
+            DescriptorPool, DescriptorPoolDesc, DescriptorSet, DescriptorSetLayout,
+        },
+        utils::BackendError,
+        Device,
+    },
+    renderer::FrameCtx,
+};
+
+const SETS_PER_POOL: u32 = 256;
+/// The number of descriptors of each type per set a pool is created with.
+const DESCRIPTORS_PER_SET: &[(vk::DescriptorType, u32)] = &[
+    (vk::DescriptorType::SAMPLER, 1),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4),
+    (vk::DescriptorType::SAMPLED_IMAGE, 4),
+    (vk::DescriptorType::STORAGE_IMAGE, 1),
+    (vk::DescriptorType::UNIFORM_BUFFER, 2),
+    (vk::DescriptorType::STORAGE_BUFFER, 2),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1),
+    (vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, 1),
+    (vk::DescriptorType::INPUT_ATTACHMENT, 1),
+];
+
+#[derive(Default)]
+struct FramePools {
+    pools: Vec<DescriptorPool>,
+    /// The pool sets are allocated from, the ones before it are full.
+    current: usize,
+}
+
+/// Allocates descriptor sets that are only valid for the current frame.
+///
+/// Every frame in flight has its own growing list of pools, which are reset at the beginning
+/// of the frame by [`DescriptorAllocator::begin_frame_system`].
+#[derive(Resource)]
+pub struct DescriptorAllocator {
+    frames: Vec<FramePools>,
+    frame_offset: usize,
+    device: Device,
+}
+
+impl DescriptorAllocator {
+    pub fn new(device: Device, num_frames: usize) -> Self {
+        Self {
+            frames: (0..num_frames).map(|_| FramePools::default()).collect(),
+            frame_offset: 0,
+            device,
+        }
+    }
+
+    fn create_pool(device: &Device) -> Result<DescriptorPool, BackendError> {
+        DescriptorPool::new(
+            device.clone(),
+            &DescriptorPoolDesc {
+                label: Some(Cow::Borrowed("frame_descriptor_pool")),
+                max_sets: SETS_PER_POOL,
+                pool_sizes: DESCRIPTORS_PER_SET
+                    .iter()
+                    .map(|(ty, descriptor_count)| (*ty, descriptor_count * SETS_PER_POOL))
+                    .collect(),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Resets the pools of `frame_offset` and allocates from them until the next call.
+    ///
+    /// The last submission of the frame must have completed.
+    pub unsafe fn begin_frame(&mut self, frame_offset: usize) -> Result<(), BackendError> {
+        let frame = &mut self.frames[frame_offset];
+
+        for pool in &frame.pools[..(frame.current + 1).min(frame.pools.len())] {
+            pool.reset()?;
+        }
+
+        frame.current = 0;
+        self.frame_offset = frame_offset;
+
+        Ok(())
+    }
+
+    /// Allocates a set with `layout`, e.g. one of the layouts of
+    /// [`PipelineLayout::descriptor_set_layouts`](crate::backend::resource::pipeline::PipelineLayout::descriptor_set_layouts).
+    pub fn allocate(
+        &mut self,
+        layout: &Arc<DescriptorSetLayout>,
+    ) -> Result<DescriptorSet, BackendError> {
+        let frame = &mut self.frames[self.frame_offset];
+
+        loop {
+            let created = frame.current == frame.pools.len();
+
+            if created {
+                frame.pools.push(Self::create_pool(&self.device)?);
+            }
+
+            match frame.pools[frame.current].allocate(layout) {
+                Ok(descriptor_set) => return Ok(DescriptorSet::new(descriptor_set, layout.clone())),
+                // A new pool can't fit the set either.
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL)
+                    if !created =>
+                {
+                    frame.current += 1
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub fn begin_frame_system(
+        frame_ctx: Res<FrameCtx>,
+        mut descriptor_allocator: ResMut<DescriptorAllocator>,
+    ) {
+        unsafe {
+            // The sets of the frame may still be used by its last submission.
+            frame_ctx.current().fence().wait_for(u64::MAX).unwrap();
+            descriptor_allocator
+                .begin_frame(frame_ctx.frame_offset())
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::{borrow::Cow, ops::Deref, slice};
+
+use ash::{prelude::VkResult, vk};
+
+use crate::backend::{
+    resource::descriptor::DescriptorSetLayout,
+    utils::{debug_utils, BackendError},
+    Device,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DescriptorPoolDesc {
+    pub label: Option<Cow<'static, str>>,
+    pub flags: vk::DescriptorPoolCreateFlags,
+    pub max_sets: u32,
+    pub pool_sizes: Vec<(vk::DescriptorType, u32)>,
+}
+
+pub struct DescriptorPool {
+    descriptor_pool: vk::DescriptorPool,
+    desc: DescriptorPoolDesc,
+    device: Device,
+}
+
+impl DescriptorPool {
+    pub fn new(device: Device, desc: &DescriptorPoolDesc) -> Result<Self, BackendError> {
+        let pool_sizes = desc
+            .pool_sizes
+            .iter()
+            .map(|(ty, descriptor_count)| {
+                vk::DescriptorPoolSize::default()
+                    .ty(*ty)
+                    .descriptor_count(*descriptor_count)
+            })
+            .collect::<Vec<_>>();
+
+        let descriptor_pool = unsafe {
+            device.loader().create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .flags(desc.flags)
+                    .max_sets(desc.max_sets)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )
+        }?;
+
+        if let Some(label) = &desc.label {
+            unsafe { debug_utils::set_object_name(&device, descriptor_pool, label) }?;
+        }
+
+        Ok(Self {
+            descriptor_pool,
+            desc: desc.clone(),
+            device,
+        })
+    }
+
+    #[inline]
+    pub fn desc(&self) -> &DescriptorPoolDesc {
+        &self.desc
+    }
+
+    /// Allocates a set with `layout`. Bindings with a variable descriptor count get the count
+    /// of the layout.
+    pub fn allocate(&self, layout: &DescriptorSetLayout) -> VkResult<vk::DescriptorSet> {
+        let variable_descriptor_count = layout
+            .desc()
+            .bindings
+            .iter()
+            .find(|binding_desc| {
+                layout
+                    .binding_flags(binding_desc.binding)
+                    .contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT)
+            })
+            .map(|binding_desc| binding_desc.descriptor_count);
+
+        let set_layout = **layout;
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(slice::from_ref(&set_layout));
+
+        let descriptor_sets = match variable_descriptor_count {
+            Some(descriptor_count) => {
+                let mut variable_descriptor_count_info =
+                    vk::DescriptorSetVariableDescriptorCountAllocateInfo::default()
+                        .descriptor_counts(slice::from_ref(&descriptor_count));
+
+                unsafe {
+                    self.device.loader().allocate_descriptor_sets(
+                        &allocate_info.push_next(&mut variable_descriptor_count_info),
+                    )
+                }?
+            }
+            None => {
+                unsafe {
+                    self.device
+                        .loader()
+                        .allocate_descriptor_sets(&allocate_info)
+                }?
+            }
+        };
+
+        Ok(descriptor_sets[0])
+    }
+
+    /// Frees all sets allocated from the pool. None of them may be in use by the device.
+    #[inline]
+    pub unsafe fn reset(&self) -> VkResult<()> {
+        self.device
+            .loader()
+            .reset_descriptor_pool(self.descriptor_pool, vk::DescriptorPoolResetFlags::empty())
+    }
+}
+
+impl Deref for DescriptorPool {
+    type Target = vk::DescriptorPool;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.descriptor_pool
+    }
+}
+
+impl Drop for DescriptorPool {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .loader()
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use std::{ops::Deref, slice, sync::Arc};
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::backend::{
+    resource::{
+        descriptor::{DescriptorSetLayout, DescriptorSetLayoutBindingDesc},
+        pipeline::PipelineLayout,
+    },
+    utils::BackendError,
+    Device,
+};
+
+#[derive(Debug, Error)]
+pub enum DescriptorError {
+    #[error("Binding {binding} doesn't exist in the descriptor set layout")]
+    MissingBinding { binding: u32 },
+    #[error("Binding {binding} is a {descriptor_type:?} descriptor, but was written as {write}")]
+    TypeMismatch {
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        write: &'static str,
+    },
+    #[error(
+        "Array element {array_element} is out of bounds of binding {binding} with {descriptor_count} descriptors"
+    )]
+    ArrayElementOutOfBounds {
+        binding: u32,
+        array_element: u32,
+        descriptor_count: u32,
+    },
+    #[error("Binding {binding} uses immutable samplers, which can't be written")]
+    ImmutableSamplers { binding: u32 },
+    #[error("No free {descriptor_type:?} slot left in the bindless table")]
+    TableFull { descriptor_type: vk::DescriptorType },
+    #[error("Set {set} isn't compatible with the pipeline layout")]
+    IncompatibleLayout { set: u32 },
+    #[error("Expected {expected} dynamic offsets, got {actual}")]
+    DynamicOffsetCount { expected: usize, actual: usize },
+    #[error("Dynamic offset {offset} of binding {binding} isn't aligned to {alignment}")]
+    DynamicOffsetAlignment {
+        binding: u32,
+        offset: u32,
+        alignment: vk::DeviceSize,
+    },
+}
+
+/// A descriptor set allocated from a [`DescriptorPool`](super::DescriptorPool).
+///
+/// Sets allocated by the [`DescriptorAllocator`](super::DescriptorAllocator) are only valid for
+/// the frame they were allocated in.
+#[derive(Clone)]
+pub struct DescriptorSet {
+    descriptor_set: vk::DescriptorSet,
+    layout: Arc<DescriptorSetLayout>,
+}
+
+impl DescriptorSet {
+    #[inline]
+    pub(crate) fn new(descriptor_set: vk::DescriptorSet, layout: Arc<DescriptorSetLayout>) -> Self {
+        Self {
+            descriptor_set,
+            layout,
+        }
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.layout
+    }
+
+    /// Returns the writes of the set, which are validated against the bindings of its layout
+    /// when they are applied.
+    #[inline]
+    pub fn writes(&self) -> DescriptorSetWrites {
+        DescriptorSetWrites {
+            set: self,
+            writes: Vec::new(),
+        }
+    }
+
+    /// Binds the set at `set` of `pipeline_layout`. `dynamic_offsets` holds an offset for every
+    /// dynamic buffer descriptor, ordered by binding and array element.
+    pub unsafe fn bind(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pipeline_bind_point: vk::PipelineBindPoint,
+        pipeline_layout: &PipelineLayout,
+        set: u32,
+        dynamic_offsets: &[u32],
+    ) -> Result<(), BackendError> {
+        // Layouts are cached, so compatible layouts are the same object.
+        if !pipeline_layout
+            .descriptor_set_layouts()
+            .get(set as usize)
+            .map_or(false, |layout| Arc::ptr_eq(layout, &self.layout))
+        {
+            return Err(DescriptorError::IncompatibleLayout { set }.into())
+        }
+
+        self.validate_dynamic_offsets(device, dynamic_offsets)?;
+
+        device.loader().cmd_bind_descriptor_sets(
+            command_buffer,
+            pipeline_bind_point,
+            **pipeline_layout,
+            set,
+            slice::from_ref(&self.descriptor_set),
+            dynamic_offsets,
+        );
+
+        Ok(())
+    }
+
+    fn validate_dynamic_offsets(
+        &self,
+        device: &Device,
+        dynamic_offsets: &[u32],
+    ) -> Result<(), DescriptorError> {
+        let limits = &device.properties().properties.limits;
+
+        let mut dynamic_bindings = self
+            .layout
+            .desc()
+            .bindings
+            .iter()
+            .filter_map(|binding_desc| {
+                match binding_desc.descriptor_type {
+                    vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC => {
+                        Some((binding_desc, limits.min_uniform_buffer_offset_alignment))
+                    }
+                    vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => {
+                        Some((binding_desc, limits.min_storage_buffer_offset_alignment))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        dynamic_bindings.sort_by_key(|(binding_desc, _)| binding_desc.binding);
+
+        let expected = dynamic_bindings
+            .iter()
+            .map(|(binding_desc, _)| binding_desc.descriptor_count as usize)
+            .sum();
+
+        if dynamic_offsets.len() != expected {
+            return Err(DescriptorError::DynamicOffsetCount {
+                expected,
+                actual: dynamic_offsets.len(),
+            })
+        }
+
+        let mut offsets = dynamic_offsets.iter();
+
+        for (binding_desc, alignment) in dynamic_bindings {
+            for offset in offsets
+                .by_ref()
+                .take(binding_desc.descriptor_count as usize)
+            {
+                if *offset as vk::DeviceSize % alignment != 0 {
+                    return Err(DescriptorError::DynamicOffsetAlignment {
+                        binding: binding_desc.binding,
+                        offset: *offset,
+                        alignment,
+                    })
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Deref for DescriptorSet {
+    type Target = vk::DescriptorSet;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.descriptor_set
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum DescriptorWriteKind {
+    Buffer,
+    DynamicBuffer,
+    Image,
+    Sampler,
+    CombinedImageSampler,
+}
+
+impl DescriptorWriteKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Buffer => "a buffer",
+            Self::DynamicBuffer => "a dynamic buffer",
+            Self::Image => "an image",
+            Self::Sampler => "a sampler",
+            Self::CombinedImageSampler => "a combined image sampler",
+        }
+    }
+
+    fn accepts(self, binding_desc: &DescriptorSetLayoutBindingDesc) -> bool {
+        match self {
+            Self::Buffer => {
+                matches!(
+                    binding_desc.descriptor_type,
+                    vk::DescriptorType::UNIFORM_BUFFER | vk::DescriptorType::STORAGE_BUFFER
+                )
+            }
+            Self::DynamicBuffer => {
+                matches!(
+                    binding_desc.descriptor_type,
+                    vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+                        | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC
+                )
+            }
+            Self::Image => {
+                matches!(
+                    binding_desc.descriptor_type,
+                    vk::DescriptorType::SAMPLED_IMAGE
+                        | vk::DescriptorType::STORAGE_IMAGE
+                        | vk::DescriptorType::INPUT_ATTACHMENT
+                )
+            }
+            Self::Sampler => binding_desc.descriptor_type == vk::DescriptorType::SAMPLER,
+            Self::CombinedImageSampler => {
+                binding_desc.descriptor_type == vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+            }
+        }
+    }
+}
+
+enum DescriptorInfo {
+    Buffer(vk::DescriptorBufferInfo),
+    Image(vk::DescriptorImageInfo),
+}
+
+struct DescriptorWrite {
+    binding: u32,
+    array_element: u32,
+    kind: DescriptorWriteKind,
+    info: DescriptorInfo,
+}
+
+/// Typed writes of a [`DescriptorSet`], applied by [`DescriptorSetWrites::update`].
+pub struct DescriptorSetWrites<'a> {
+    set: &'a DescriptorSet,
+    writes: Vec<DescriptorWrite>,
+}
+
+impl<'a> DescriptorSetWrites<'a> {
+    #[inline]
+    fn push(
+        mut self,
+        binding: u32,
+        array_element: u32,
+        kind: DescriptorWriteKind,
+        info: DescriptorInfo,
+    ) -> Self {
+        self.writes.push(DescriptorWrite {
+            binding,
+            array_element,
+            kind,
+            info,
+        });
+        self
+    }
+
+    /// Writes a uniform or storage buffer.
+    #[inline]
+    pub fn buffer(
+        self,
+        binding: u32,
+        array_element: u32,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> Self {
+        self.push(
+            binding,
+            array_element,
+            DescriptorWriteKind::Buffer,
+            DescriptorInfo::Buffer(
+                vk::DescriptorBufferInfo::default()
+                    .buffer(buffer)
+                    .offset(offset)
+                    .range(range),
+            ),
+        )
+    }
+
+    /// Writes a dynamic uniform or storage buffer, whose offset is added to the dynamic offset
+    /// passed to [`DescriptorSet::bind`].
+    #[inline]
+    pub fn dynamic_buffer(
+        self,
+        binding: u32,
+        array_element: u32,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> Self {
+        self.push(
+            binding,
+            array_element,
+            DescriptorWriteKind::DynamicBuffer,
+            DescriptorInfo::Buffer(
+                vk::DescriptorBufferInfo::default()
+                    .buffer(buffer)
+                    .offset(offset)
+                    .range(range),
+            ),
+        )
+    }
+
+    /// Writes a sampled image, storage image or input attachment.
+    #[inline]
+    pub fn image(
+        self,
+        binding: u32,
+        array_element: u32,
+        image_view: vk::ImageView,
+        image_layout: vk::ImageLayout,
+    ) -> Self {
+        self.push(
+            binding,
+            array_element,
+            DescriptorWriteKind::Image,
+            DescriptorInfo::Image(
+                vk::DescriptorImageInfo::default()
+                    .image_view(image_view)
+                    .image_layout(image_layout),
+            ),
+        )
+    }
+
+    #[inline]
+    pub fn sampler(self, binding: u32, array_element: u32, sampler: vk::Sampler) -> Self {
+        self.push(
+            binding,
+            array_element,
+            DescriptorWriteKind::Sampler,
+            DescriptorInfo::Image(vk::DescriptorImageInfo::default().sampler(sampler)),
+        )
+    }
+
+    /// Writes a combined image sampler. `sampler` is ignored if the binding uses immutable
+    /// samplers.
+    #[inline]
+    pub fn combined_image_sampler(
+        self,
+        binding: u32,
+        array_element: u32,
+        image_view: vk::ImageView,
+        image_layout: vk::ImageLayout,
+        sampler: vk::Sampler,
+    ) -> Self {
+        self.push(
+            binding,
+            array_element,
+            DescriptorWriteKind::CombinedImageSampler,
+            DescriptorInfo::Image(
+                vk::DescriptorImageInfo::default()
+                    .image_view(image_view)
+                    .image_layout(image_layout)
+                    .sampler(sampler),
+            ),
+        )
+    }
+
+    fn validate(&self, write: &DescriptorWrite) -> Result<vk::DescriptorType, DescriptorError> {
+        let binding_desc =
+            self.set
+                .layout
+                .binding(write.binding)
+                .ok_or(DescriptorError::MissingBinding {
+                    binding: write.binding,
+                })?;
+
+        if !write.kind.accepts(binding_desc) {
+            return Err(DescriptorError::TypeMismatch {
+                binding: write.binding,
+                descriptor_type: binding_desc.descriptor_type,
+                write: write.kind.name(),
+            })
+        }
+
+        if write.array_element >= binding_desc.descriptor_count {
+            return Err(DescriptorError::ArrayElementOutOfBounds {
+                binding: write.binding,
+                array_element: write.array_element,
+                descriptor_count: binding_desc.descriptor_count,
+            })
+        }
+
+        if write.kind == DescriptorWriteKind::Sampler && !binding_desc.immutable_samplers.is_empty()
+        {
+            return Err(DescriptorError::ImmutableSamplers {
+                binding: write.binding,
+            })
+        }
+
+        Ok(binding_desc.descriptor_type)
+    }
+
+    /// Validates and applies the writes. Nothing is written if any of them is invalid.
+    ///
+    /// The set must not be in use by the device, unless its bindings were created with
+    /// `UPDATE_AFTER_BIND`.
+    pub fn update(self, device: &Device) -> Result<(), BackendError> {
+        let descriptor_writes = self
+            .writes
+            .iter()
+            .map(|write| {
+                let descriptor_write = vk::WriteDescriptorSet::default()
+                    .dst_set(self.set.descriptor_set)
+                    .dst_binding(write.binding)
+                    .dst_array_element(write.array_element)
+                    .descriptor_type(self.validate(write)?);
+
+                Ok(match &write.info {
+                    DescriptorInfo::Buffer(buffer_info) => {
+                        descriptor_write.buffer_info(slice::from_ref(buffer_info))
+                    }
+                    DescriptorInfo::Image(image_info) => {
+                        descriptor_write.image_info(slice::from_ref(image_info))
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, DescriptorError>>()?;
+
+        unsafe {
+            device
+                .loader()
+                .update_descriptor_sets(&descriptor_writes, &[]);
+        }
+
+        Ok(())
+    }
+}
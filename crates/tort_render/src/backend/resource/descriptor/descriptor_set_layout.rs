@@ -36,6 +36,7 @@ impl From<&DescriptorSetLayoutDesc> for DescriptorSetLayoutDesc {
 pub struct DescriptorSetLayout {
     descriptor_set_layout: vk::DescriptorSetLayout,
     immutable_samplers: Vec<Arc<Sampler>>,
+    desc: DescriptorSetLayoutDesc,
     device: Device,
 }
 
@@ -104,6 +105,7 @@ impl DescriptorSetLayout {
         Ok(Self {
             descriptor_set_layout,
             immutable_samplers,
+            desc: desc.clone(),
             device,
         })
     }
@@ -112,6 +114,30 @@ impl DescriptorSetLayout {
     pub fn immutable_samplers(&self) -> &Vec<Arc<Sampler>> {
         &self.immutable_samplers
     }
+
+    #[inline]
+    pub fn desc(&self) -> &DescriptorSetLayoutDesc {
+        &self.desc
+    }
+
+    #[inline]
+    pub fn binding(&self, binding: u32) -> Option<&DescriptorSetLayoutBindingDesc> {
+        self.desc
+            .bindings
+            .iter()
+            .find(|binding_desc| binding_desc.binding == binding)
+    }
+
+    /// Returns the flags of `binding`, or empty flags if the layout has none.
+    #[inline]
+    pub fn binding_flags(&self, binding: u32) -> vk::DescriptorBindingFlags {
+        self.desc
+            .bindings
+            .iter()
+            .position(|binding_desc| binding_desc.binding == binding)
+            .and_then(|index| self.desc.binding_flags.get(index).copied())
+            .unwrap_or_default()
+    }
 }
 
 impl Deref for DescriptorSetLayout {
@@ -1,2 +1,9 @@
+mod descriptor_allocator;
+mod descriptor_pool;
+mod descriptor_set;
 mod descriptor_set_layout;
+
+pub use descriptor_allocator::*;
+pub use descriptor_pool::*;
+pub use descriptor_set::*;
 pub use descriptor_set_layout::*;
@@ -1,4 +1,4 @@
-use std::{borrow::Cow, ops::Deref};
+use std::{borrow::Cow, ops::Deref, ptr, sync::Arc};
 
 use ash::vk;
 use vk_mem_alloc::{
@@ -6,6 +6,7 @@ use vk_mem_alloc::{
 };
 
 use crate::backend::{
+    resource::MemoryBlock,
     utils::{debug_utils, BackendError, Extent3D},
     Device,
 };
@@ -27,17 +28,27 @@ pub struct ImageDesc {
     pub memory_usage: MemoryUsage,
 }
 
+enum ImageMemory {
+    Dedicated {
+        allocation: Allocation,
+        allocation_info: AllocationInfo,
+    },
+    Aliased {
+        block: Arc<MemoryBlock>,
+        offset: vk::DeviceSize,
+    },
+}
+
 pub struct Image {
     image: vk::Image,
-    allocation: Allocation,
-    allocation_info: AllocationInfo,
+    memory: ImageMemory,
     desc: ImageDesc,
     device: Device,
 }
 
 impl Image {
-    pub fn new(device: Device, desc: &ImageDesc) -> Result<Self, BackendError> {
-        let image_create_info = vk::ImageCreateInfo::default()
+    fn create_info(desc: &ImageDesc) -> vk::ImageCreateInfo<'static> {
+        vk::ImageCreateInfo::default()
             .flags(desc.flags)
             .image_type(desc.image_type)
             .format(desc.format)
@@ -47,7 +58,11 @@ impl Image {
             .samples(desc.samples)
             .tiling(desc.tiling)
             .usage(desc.usage)
-            .initial_layout(desc.initial_layout);
+            .initial_layout(desc.initial_layout)
+    }
+
+    pub fn new(device: Device, desc: &ImageDesc) -> Result<Self, BackendError> {
+        let image_create_info = Self::create_info(desc);
 
         let allocation_create_info = AllocationCreateInfo {
             flags: desc.allocation_flags,
@@ -69,8 +84,64 @@ impl Image {
 
         Ok(Self {
             image,
-            allocation,
-            allocation_info,
+            memory: ImageMemory::Dedicated {
+                allocation,
+                allocation_info,
+            },
+            desc: desc.clone(),
+            device,
+        })
+    }
+
+    /// Returns the memory requirements of an image created with `desc`.
+    ///
+    /// Vulkan 1.2 can't query them without an image, so a temporary one is created.
+    pub fn memory_requirements(
+        device: &Device,
+        desc: &ImageDesc,
+    ) -> Result<vk::MemoryRequirements, BackendError> {
+        let device_loader = device.loader();
+
+        unsafe {
+            let image = device_loader.create_image(&Self::create_info(desc), None)?;
+            let memory_requirements = device_loader.get_image_memory_requirements(image);
+            device_loader.destroy_image(image, None);
+
+            Ok(memory_requirements)
+        }
+    }
+
+    /// Creates an image placed at `offset` into `block`, which may be shared with other resources.
+    ///
+    /// The allocation fields of `desc` are ignored.
+    pub fn new_aliased(
+        device: Device,
+        desc: &ImageDesc,
+        block: Arc<MemoryBlock>,
+        offset: vk::DeviceSize,
+    ) -> Result<Self, BackendError> {
+        let image = unsafe { device.loader().create_image(&Self::create_info(desc), None) }?;
+
+        if let Err(e) = unsafe {
+            vk_mem_alloc::bind_image_memory2(
+                *device.allocator(),
+                *block.allocation(),
+                offset,
+                image,
+                ptr::null(),
+            )
+        } {
+            unsafe { device.loader().destroy_image(image, None) };
+            return Err(e.into())
+        }
+
+        if let Some(label) = &desc.label {
+            unsafe { debug_utils::set_object_name(&device, image, label) }?;
+        }
+
+        Ok(Self {
+            image,
+            memory: ImageMemory::Aliased { block, offset },
             desc: desc.clone(),
             device,
         })
@@ -78,12 +149,30 @@ impl Image {
 
     #[inline]
     pub fn allocation(&self) -> &Allocation {
-        &self.allocation
+        match &self.memory {
+            ImageMemory::Dedicated { allocation, .. } => allocation,
+            ImageMemory::Aliased { block, .. } => block.allocation(),
+        }
     }
 
     #[inline]
     pub fn allocation_info(&self) -> &AllocationInfo {
-        &self.allocation_info
+        match &self.memory {
+            ImageMemory::Dedicated {
+                allocation_info, ..
+            } => allocation_info,
+            ImageMemory::Aliased { block, .. } => block.allocation_info(),
+        }
+    }
+
+    /// Returns the block the image is placed into and the offset into it, if it was created with
+    /// [`Image::new_aliased`].
+    #[inline]
+    pub fn aliased_memory(&self) -> Option<(&Arc<MemoryBlock>, vk::DeviceSize)> {
+        match &self.memory {
+            ImageMemory::Dedicated { .. } => None,
+            ImageMemory::Aliased { block, offset } => Some((block, *offset)),
+        }
     }
 
     #[inline]
@@ -104,8 +193,14 @@ impl Deref for Image {
 impl Drop for Image {
     #[inline]
     fn drop(&mut self) {
-        unsafe {
-            vk_mem_alloc::destroy_image(*self.device.allocator(), self.image, self.allocation);
+        match &self.memory {
+            ImageMemory::Dedicated { allocation, .. } => unsafe {
+                vk_mem_alloc::destroy_image(*self.device.allocator(), self.image, *allocation);
+            },
+            // The block is freed once the last resource placed into it is dropped.
+            ImageMemory::Aliased { .. } => unsafe {
+                self.device.loader().destroy_image(self.image, None);
+            },
         }
     }
 }
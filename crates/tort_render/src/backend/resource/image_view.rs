@@ -0,0 +1,91 @@
+use std::{borrow::Cow, ops::Deref};
+
+use ash::vk;
+
+use crate::backend::{
+    utils::{debug_utils, BackendError},
+    Device,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ImageViewDesc {
+    pub label: Option<Cow<'static, str>>,
+    pub flags: vk::ImageViewCreateFlags,
+    pub view_type: vk::ImageViewType,
+    pub format: vk::Format,
+    pub aspect_mask: vk::ImageAspectFlags,
+    pub base_mip_level: u32,
+    pub level_count: u32,
+    pub base_array_layer: u32,
+    pub layer_count: u32,
+}
+
+pub struct ImageView {
+    image_view: vk::ImageView,
+    desc: ImageViewDesc,
+    device: Device,
+}
+
+impl ImageView {
+    pub fn new(
+        device: Device,
+        image: vk::Image,
+        desc: &ImageViewDesc,
+    ) -> Result<Self, BackendError> {
+        let image_view_create_info = vk::ImageViewCreateInfo::default()
+            .flags(desc.flags)
+            .image(image)
+            .view_type(desc.view_type)
+            .format(desc.format)
+            .components(Default::default())
+            .subresource_range(
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(desc.aspect_mask)
+                    .base_mip_level(desc.base_mip_level)
+                    .level_count(desc.level_count)
+                    .base_array_layer(desc.base_array_layer)
+                    .layer_count(desc.layer_count),
+            );
+
+        let image_view = unsafe {
+            device
+                .loader()
+                .create_image_view(&image_view_create_info, None)
+        }?;
+
+        if let Some(label) = &desc.label {
+            unsafe { debug_utils::set_object_name(&device, image_view, label) }?;
+        }
+
+        Ok(Self {
+            image_view,
+            desc: desc.clone(),
+            device,
+        })
+    }
+
+    #[inline]
+    pub fn desc(&self) -> &ImageViewDesc {
+        &self.desc
+    }
+}
+
+impl Deref for ImageView {
+    type Target = vk::ImageView;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.image_view
+    }
+}
+
+impl Drop for ImageView {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .loader()
+                .destroy_image_view(self.image_view, None);
+        }
+    }
+}
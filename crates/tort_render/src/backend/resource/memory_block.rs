@@ -0,0 +1,93 @@
+use std::borrow::Cow;
+
+use ash::vk;
+use vk_mem_alloc::{
+    Allocation, AllocationCreateFlags, AllocationCreateInfo, AllocationInfo, MemoryUsage,
+};
+
+use crate::backend::{
+    utils::{debug_utils, BackendError},
+    Device,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MemoryBlockDesc {
+    pub label: Option<Cow<'static, str>>,
+    pub requirements: vk::MemoryRequirements,
+    pub allocation_flags: AllocationCreateFlags,
+    pub memory_usage: MemoryUsage,
+    pub required_flags: vk::MemoryPropertyFlags,
+}
+
+/// Memory that isn't bound to a single resource.
+///
+/// Images and buffers can be placed at offsets into the block with [`Image::new_aliased`] and
+/// [`Buffer::new_aliased`], which lets resources whose lifetimes don't overlap share memory.
+///
+/// [`Image::new_aliased`]: super::Image::new_aliased
+/// [`Buffer::new_aliased`]: super::Buffer::new_aliased
+pub struct MemoryBlock {
+    allocation: Allocation,
+    allocation_info: AllocationInfo,
+    desc: MemoryBlockDesc,
+    device: Device,
+}
+
+impl MemoryBlock {
+    pub fn new(device: Device, desc: &MemoryBlockDesc) -> Result<Self, BackendError> {
+        let allocation_create_info = AllocationCreateInfo {
+            flags: desc.allocation_flags,
+            usage: desc.memory_usage,
+            required_flags: desc.required_flags,
+            ..Default::default()
+        };
+
+        let (allocation, allocation_info) = unsafe {
+            vk_mem_alloc::allocate_memory(
+                *device.allocator(),
+                &desc.requirements,
+                &allocation_create_info,
+            )
+        }?;
+
+        if let Some(label) = &desc.label {
+            unsafe { debug_utils::set_object_name(&device, allocation_info.device_memory, label) }?;
+        }
+
+        Ok(Self {
+            allocation,
+            allocation_info,
+            desc: desc.clone(),
+            device,
+        })
+    }
+
+    #[inline]
+    pub fn allocation(&self) -> &Allocation {
+        &self.allocation
+    }
+
+    #[inline]
+    pub fn allocation_info(&self) -> &AllocationInfo {
+        &self.allocation_info
+    }
+
+    #[inline]
+    pub fn desc(&self) -> &MemoryBlockDesc {
+        &self.desc
+    }
+
+    #[inline]
+    pub fn size(&self) -> vk::DeviceSize {
+        self.desc.requirements.size
+    }
+}
+
+impl Drop for MemoryBlock {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe {
+            vk_mem_alloc::free_memory(*self.device.allocator(), self.allocation);
+        }
+    }
+}
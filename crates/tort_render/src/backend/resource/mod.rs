@@ -1,9 +1,13 @@
 mod buffer;
 pub mod descriptor;
 mod image;
+mod image_view;
+mod memory_block;
 pub mod pipeline;
 mod sampler;
 
 pub use buffer::*;
 pub use image::*;
+pub use image_view::*;
+pub use memory_block::*;
 pub use sampler::*;
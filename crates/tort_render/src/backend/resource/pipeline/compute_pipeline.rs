@@ -5,8 +5,8 @@ use tort_utils::Uuid;
 
 use crate::backend::{
     resource::pipeline::{
-        Pipeline, PipelineLayout, PipelineLayoutDesc, PipelineLayoutModifier, ShaderModule,
-        ShaderStageDesc,
+        check_push_constant_size, Pipeline, PipelineLayout, PipelineLayoutDesc,
+        PipelineLayoutModifier, ShaderModule, ShaderStageDesc,
     },
     utils::{debug_utils, BackendError},
     Device,
@@ -28,6 +28,9 @@ pub struct ComputePipelineDesc {
     pub flags: vk::PipelineCreateFlags,
     pub stage: ShaderStageDesc,
     pub layout_modifers: Vec<PipelineLayoutModifier>,
+    /// The size of the push constants the pipeline is used with, creating it fails if the
+    /// shader declares a different size.
+    pub push_constant_size: Option<u32>,
 }
 
 impl From<&ComputePipelineDesc> for ComputePipelineDesc {
@@ -40,6 +43,7 @@ impl From<&ComputePipelineDesc> for ComputePipelineDesc {
 struct Inner {
     pipeline: vk::Pipeline,
     pipeline_layout: Arc<PipelineLayout>,
+    workgroup_size: [u32; 3],
     desc: ComputePipelineDesc,
     id: ComputePipelineId,
     device: Device,
@@ -63,6 +67,7 @@ impl ComputePipeline {
         desc: &ComputePipelineDesc,
         id: ComputePipelineId,
         shader_module: &ShaderModule,
+        pipeline_cache: vk::PipelineCache,
         pipeline_layout_provider: impl Fn(
             &PipelineLayoutDesc,
         ) -> Result<Arc<PipelineLayout>, BackendError>,
@@ -70,8 +75,9 @@ impl ComputePipeline {
         let pipeline_layout_desc = PipelineLayoutDesc::from_spirv(
             iter::once((desc.stage.stage, shader_module)),
             &desc.layout_modifers,
-        );
+        )?;
         let pipeline_layout = pipeline_layout_provider(&pipeline_layout_desc)?;
+        check_push_constant_size(&pipeline_layout, desc.push_constant_size)?;
 
         let name = CString::new(&desc.stage.entry_point as &str)?;
 
@@ -104,7 +110,7 @@ impl ComputePipeline {
 
         let pipeline = unsafe {
             device.loader().create_compute_pipelines(
-                vk::PipelineCache::null(),
+                pipeline_cache,
                 slice::from_ref(&compute_pipeline_create_info),
                 None,
             )
@@ -118,6 +124,10 @@ impl ComputePipeline {
         Ok(Self(Arc::new(Inner {
             pipeline,
             pipeline_layout,
+            workgroup_size: shader_module
+                .interface(&desc.stage.entry_point, vk::ShaderStageFlags::COMPUTE)
+                .and_then(|interface| interface.workgroup_size)
+                .unwrap_or([1, 1, 1]),
             desc: desc.clone(),
             id,
             device,
@@ -129,6 +139,12 @@ impl ComputePipeline {
         &self.0.pipeline_layout
     }
 
+    /// The reflected workgroup size, with the default values of specialization constants.
+    #[inline]
+    pub fn workgroup_size(&self) -> [u32; 3] {
+        self.0.workgroup_size
+    }
+
     #[inline]
     pub fn desc(&self) -> &ComputePipelineDesc {
         &self.0.desc
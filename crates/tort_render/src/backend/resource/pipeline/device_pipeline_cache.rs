@@ -0,0 +1,189 @@
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use ash::vk;
+use log::{debug, info, warn};
+use parking_lot::Mutex;
+
+use crate::backend::{
+    utils::{debug_utils, BackendError},
+    Device,
+};
+
+/// Written before the Vulkan cache data, which doesn't contain the driver version.
+const FILE_MAGIC: [u8; 4] = *b"TPLC";
+const FILE_HEADER_SIZE: usize = 8;
+/// The size of `VkPipelineCacheHeaderVersionOne`.
+const VK_HEADER_SIZE: usize = 32;
+
+/// A [`vk::PipelineCache`] shared by all pipelines, loaded from and saved to `path`.
+///
+/// The file is only used if its header matches the vendor ID, device ID, pipeline cache UUID
+/// and driver version of the device, otherwise the cache starts empty and overwrites it.
+pub struct DevicePipelineCache {
+    pipeline_cache: vk::PipelineCache,
+    path: Option<PathBuf>,
+    /// The size of the data last loaded or saved, the cache only grows.
+    saved_size: Mutex<usize>,
+    device: Device,
+}
+
+impl DevicePipelineCache {
+    pub fn new(device: Device, path: Option<&Path>) -> Result<Self, BackendError> {
+        let data = path
+            .and_then(|path| {
+                match fs::read(path) {
+                    Ok(data) => Some(data),
+                    Err(e) => {
+                        debug!("Failed to read pipeline cache {}: {e}", path.display());
+                        None
+                    }
+                }
+            })
+            .and_then(|data| {
+                match Self::validate(&device, &data) {
+                    Ok(()) => Some(data),
+                    Err(reason) => {
+                        info!("Discarding pipeline cache: {reason}");
+                        None
+                    }
+                }
+            });
+
+        let initial_data = data
+            .as_deref()
+            .map_or(&[][..], |data| &data[FILE_HEADER_SIZE..]);
+
+        let pipeline_cache = unsafe {
+            device.loader().create_pipeline_cache(
+                &vk::PipelineCacheCreateInfo::default().initial_data(initial_data),
+                None,
+            )
+        }?;
+
+        unsafe { debug_utils::set_object_name(&device, pipeline_cache, "pipeline_cache") }?;
+
+        if !initial_data.is_empty() {
+            info!(
+                "Loaded pipeline cache {} ({} bytes)",
+                path.unwrap().display(),
+                initial_data.len()
+            );
+        }
+
+        Ok(Self {
+            pipeline_cache,
+            path: path.map(Path::to_path_buf),
+            saved_size: Mutex::new(initial_data.len()),
+            device,
+        })
+    }
+
+    fn validate(device: &Device, data: &[u8]) -> Result<(), &'static str> {
+        if data.len() < FILE_HEADER_SIZE + VK_HEADER_SIZE || data[..4] != FILE_MAGIC {
+            return Err("invalid header")
+        }
+
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        let properties = &device.properties().properties;
+        let vk_header = FILE_HEADER_SIZE;
+
+        if read_u32(4) != properties.driver_version {
+            return Err("driver version mismatch")
+        }
+        if (read_u32(vk_header) as usize) < VK_HEADER_SIZE
+            || read_u32(vk_header + 4) != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        {
+            return Err("unsupported header version")
+        }
+        if read_u32(vk_header + 8) != properties.vendor_id
+            || read_u32(vk_header + 12) != properties.device_id
+        {
+            return Err("device mismatch")
+        }
+        if data[vk_header + 16..vk_header + VK_HEADER_SIZE] != properties.pipeline_cache_uuid {
+            return Err("pipeline cache UUID mismatch")
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Writes the cache to its path if it has grown since it was loaded or last saved.
+    pub fn save(&self) -> Result<(), BackendError> {
+        let Some(path) = &self.path else {
+            return Ok(())
+        };
+
+        let data = unsafe {
+            self.device
+                .loader()
+                .get_pipeline_cache_data(self.pipeline_cache)
+        }?;
+
+        let mut saved_size = self.saved_size.lock();
+        if data.len() <= *saved_size {
+            return Ok(())
+        }
+
+        let mut file = Vec::with_capacity(FILE_HEADER_SIZE + data.len());
+        file.extend_from_slice(&FILE_MAGIC);
+        file.extend_from_slice(
+            &self
+                .device
+                .properties()
+                .properties
+                .driver_version
+                .to_le_bytes(),
+        );
+        file.extend_from_slice(&data);
+
+        // Write to a temporary file first so an interrupted save doesn't leave a truncated cache.
+        let tmp_path = path.with_extension("tmp");
+        match fs::write(&tmp_path, &file).and_then(|()| fs::rename(&tmp_path, path)) {
+            Ok(()) => {
+                debug!(
+                    "Pipeline cache written to {} ({} bytes)",
+                    path.display(),
+                    data.len()
+                );
+                *saved_size = data.len();
+            }
+            Err(e) => warn!("Failed to write pipeline cache {}: {e}", path.display()),
+        }
+
+        Ok(())
+    }
+}
+
+impl Deref for DevicePipelineCache {
+    type Target = vk::PipelineCache;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.pipeline_cache
+    }
+}
+
+impl Drop for DevicePipelineCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            warn!("Failed to save pipeline cache: {e}");
+        }
+
+        unsafe {
+            self.device
+                .loader()
+                .destroy_pipeline_cache(self.pipeline_cache, None);
+        }
+    }
+}
@@ -14,8 +14,8 @@ use tort_utils::{
 
 use crate::backend::{
     resource::pipeline::{
-        Pipeline, PipelineLayout, PipelineLayoutDesc, PipelineLayoutModifier, ShaderModule,
-        ShaderStageDesc,
+        check_push_constant_size, validate_stage_interfaces, Pipeline, PipelineLayout,
+        PipelineLayoutDesc, PipelineLayoutModifier, ShaderModule, ShaderStageDesc,
     },
     utils::{debug_utils, BackendError, Rect2D},
     Device,
@@ -46,6 +46,9 @@ pub struct GraphicsPipelineDesc {
     pub dynamic_state: DynamicStateDesc,
     pub rendering_state: RenderingStateDesc,
     pub layout_modifiers: Vec<PipelineLayoutModifier>,
+    /// The size of the push constants the pipeline is used with, creating it fails if the
+    /// shaders declare a different size.
+    pub push_constant_size: Option<u32>,
 }
 
 impl From<&GraphicsPipelineDesc> for GraphicsPipelineDesc {
@@ -230,10 +233,21 @@ impl GraphicsPipeline {
         desc: &GraphicsPipelineDesc,
         id: GraphicsPipelineId,
         shader_modules: &[Arc<ShaderModule>],
+        pipeline_cache: vk::PipelineCache,
         pipeline_layout_provider: impl Fn(
             &PipelineLayoutDesc,
         ) -> Result<Arc<PipelineLayout>, BackendError>,
     ) -> Result<Self, BackendError> {
+        validate_stage_interfaces(
+            desc.stages
+                .iter()
+                .zip(shader_modules)
+                .filter_map(|(stage_desc, shader_module)| {
+                    shader_module.interface(&stage_desc.entry_point, stage_desc.stage)
+                }),
+            desc.vertex_input_state.as_ref(),
+        )?;
+
         let pipeline_layout_desc = PipelineLayoutDesc::from_spirv(
             desc.stages.iter().map(|stage_desc| stage_desc.stage).zip(
                 shader_modules
@@ -241,8 +255,9 @@ impl GraphicsPipeline {
                     .map(|shader_module| shader_module.deref()),
             ),
             &desc.layout_modifiers,
-        );
+        )?;
         let pipeline_layout = pipeline_layout_provider(&pipeline_layout_desc)?;
+        check_push_constant_size(&pipeline_layout, desc.push_constant_size)?;
 
         let num_stages = desc.stages.len();
 
@@ -419,7 +434,7 @@ impl GraphicsPipeline {
 
         let pipeline = unsafe {
             device.loader().create_graphics_pipelines(
-                vk::PipelineCache::null(),
+                pipeline_cache,
                 slice::from_ref(&graphics_pipeline_create_info),
                 None,
             )
@@ -0,0 +1,627 @@
+use std::{collections::HashMap, fmt, ops::Range};
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::backend::resource::pipeline::{
+    spirv::{
+        self, BUILT_IN_WORKGROUP_SIZE, DECORATION_BUILT_IN, DECORATION_COMPONENT,
+        DECORATION_LOCATION, DECORATION_PATCH, EXECUTION_MODE_LOCAL_SIZE,
+        EXECUTION_MODE_LOCAL_SIZE_ID, OP_CONSTANT, OP_CONSTANT_COMPOSITE, OP_DECORATE,
+        OP_ENTRY_POINT, OP_EXECUTION_MODE, OP_MEMBER_DECORATE, OP_SPEC_CONSTANT,
+        OP_SPEC_CONSTANT_COMPOSITE, OP_TYPE_ARRAY, OP_TYPE_FLOAT, OP_TYPE_INT, OP_TYPE_MATRIX,
+        OP_TYPE_POINTER, OP_TYPE_STRUCT, OP_TYPE_VECTOR, OP_VARIABLE, STORAGE_CLASS_INPUT,
+        STORAGE_CLASS_OUTPUT,
+    },
+    VertexInputStateDesc,
+};
+
+#[derive(Debug, Error)]
+pub enum InterfaceError {
+    #[error(
+        "The {consumer:?} shader reads location {location} component {component}, which the \
+         {producer:?} shader doesn't write"
+    )]
+    MissingOutput {
+        producer: vk::ShaderStageFlags,
+        consumer: vk::ShaderStageFlags,
+        location: u32,
+        component: u32,
+    },
+    #[error(
+        "The {producer:?} shader writes {output} to location {location}, but the {consumer:?} \
+         shader reads {input}"
+    )]
+    TypeMismatch {
+        producer: vk::ShaderStageFlags,
+        consumer: vk::ShaderStageFlags,
+        location: u32,
+        output: InterfaceType,
+        input: InterfaceType,
+    },
+    #[error("The vertex shader reads location {location}, which has no vertex attribute")]
+    MissingVertexAttribute { location: u32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InterfaceComponentType {
+    Float,
+    SInt,
+    UInt,
+}
+
+/// The type of a single location of a stage input or output, e.g. a `vec3` or a column of a
+/// `mat4`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InterfaceType {
+    pub component_type: InterfaceComponentType,
+    pub width: u32,
+    pub components: u32,
+}
+
+impl fmt::Display for InterfaceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match self.component_type {
+            InterfaceComponentType::Float => "f",
+            InterfaceComponentType::SInt => "i",
+            InterfaceComponentType::UInt => "u",
+        };
+
+        match self.components {
+            1 => write!(f, "{prefix}{}", self.width),
+            components => write!(f, "{prefix}{}x{components}", self.width),
+        }
+    }
+}
+
+/// A stage input or output at a single location, arrays and matrices are split into one
+/// variable per location.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InterfaceVariable {
+    pub location: u32,
+    pub component: u32,
+    pub ty: InterfaceType,
+}
+
+impl InterfaceVariable {
+    /// The components that the variable occupies, 64 bit types take two components per value
+    /// and smaller types take a whole component.
+    #[inline]
+    pub fn components(&self) -> Range<u32> {
+        let components = self.ty.components * self.ty.width.max(32) / 32;
+        self.component..self.component + components
+    }
+}
+
+/// The inputs and outputs of an entry point of a shader module, built-ins are omitted.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderInterface {
+    pub entry_point: String,
+    pub stage: vk::ShaderStageFlags,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+    /// The workgroup size of compute, task and mesh shaders, with the default values of
+    /// specialization constants.
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Scalar(InterfaceComponentType, u32),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Array(u32, u32),
+    Struct(Vec<u32>),
+    Pointer(u32, u32),
+}
+
+#[derive(Default)]
+struct Module<'a> {
+    entry_points: Vec<(u32, u32, String, &'a [u32])>,
+    local_sizes: Vec<(u32, [u32; 3])>,
+    local_size_ids: Vec<(u32, [u32; 3])>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    composites: HashMap<u32, &'a [u32]>,
+    variables: HashMap<u32, u32>,
+    decorations: HashMap<(u32, u32), u32>,
+    built_in_structs: Vec<u32>,
+}
+
+impl<'a> Module<'a> {
+    fn parse(code: &'a [u32]) -> Self {
+        let mut module = Self::default();
+
+        for (opcode, operands) in spirv::instructions(code) {
+            match (opcode, operands) {
+                (OP_ENTRY_POINT, [execution_model, id, rest @ ..]) => {
+                    // The name is a nul terminated string padded to whole words.
+                    let name_words = rest
+                        .iter()
+                        .position(|word| word.to_le_bytes().contains(&0))
+                        .map_or(rest.len(), |i| i + 1);
+                    let name = spirv::decode_string(&rest[..name_words]);
+                    module
+                        .entry_points
+                        .push((*execution_model, *id, name, &rest[name_words..]))
+                }
+                (OP_EXECUTION_MODE, [entry, EXECUTION_MODE_LOCAL_SIZE, x, y, z]) => {
+                    module.local_sizes.push((*entry, [*x, *y, *z]))
+                }
+                (OP_EXECUTION_MODE, [entry, EXECUTION_MODE_LOCAL_SIZE_ID, x, y, z]) => {
+                    module.local_size_ids.push((*entry, [*x, *y, *z]))
+                }
+                (OP_TYPE_INT, [result, width, signed]) => {
+                    let component_type = if *signed == 0 {
+                        InterfaceComponentType::UInt
+                    } else {
+                        InterfaceComponentType::SInt
+                    };
+                    module
+                        .types
+                        .insert(*result, Type::Scalar(component_type, *width));
+                }
+                (OP_TYPE_FLOAT, [result, width, ..]) => {
+                    module
+                        .types
+                        .insert(*result, Type::Scalar(InterfaceComponentType::Float, *width));
+                }
+                (OP_TYPE_VECTOR, [result, component, count]) => {
+                    module
+                        .types
+                        .insert(*result, Type::Vector(*component, *count));
+                }
+                (OP_TYPE_MATRIX, [result, column, count]) => {
+                    module.types.insert(*result, Type::Matrix(*column, *count));
+                }
+                (OP_TYPE_ARRAY, [result, element, length]) => {
+                    module.types.insert(*result, Type::Array(*element, *length));
+                }
+                (OP_TYPE_STRUCT, [result, members @ ..]) => {
+                    module.types.insert(*result, Type::Struct(members.to_vec()));
+                }
+                (OP_TYPE_POINTER, [result, storage_class, pointee]) => {
+                    module
+                        .types
+                        .insert(*result, Type::Pointer(*storage_class, *pointee));
+                }
+                (OP_CONSTANT | OP_SPEC_CONSTANT, [_, result, value]) => {
+                    module.constants.insert(*result, *value);
+                }
+                (
+                    OP_CONSTANT_COMPOSITE | OP_SPEC_CONSTANT_COMPOSITE,
+                    [_, result, constituents @ ..],
+                ) => {
+                    module.composites.insert(*result, constituents);
+                }
+                (OP_VARIABLE, [result_type, result, ..]) => {
+                    module.variables.insert(*result, *result_type);
+                }
+                (OP_DECORATE, [target, decoration, value @ ..]) => {
+                    module
+                        .decorations
+                        .insert((*target, *decoration), value.first().copied().unwrap_or(0));
+                }
+                (OP_MEMBER_DECORATE, [struct_type, _, DECORATION_BUILT_IN, ..]) => {
+                    module.built_in_structs.push(*struct_type)
+                }
+                _ => {}
+            }
+        }
+
+        module
+    }
+
+    #[inline]
+    fn decoration(&self, target: u32, decoration: u32) -> Option<u32> {
+        self.decorations.get(&(target, decoration)).copied()
+    }
+
+    fn workgroup_size(&self, entry: u32) -> Option<[u32; 3]> {
+        // The `WorkgroupSize` built-in takes precedence over the execution modes.
+        let built_in = self
+            .composites
+            .iter()
+            .find(|(id, _)| {
+                self.decoration(**id, DECORATION_BUILT_IN) == Some(BUILT_IN_WORKGROUP_SIZE)
+            })
+            .and_then(|(_, constituents)| self.resolve_size(constituents));
+
+        built_in
+            .or_else(|| {
+                self.local_sizes
+                    .iter()
+                    .find(|(id, _)| *id == entry)
+                    .map(|(_, size)| *size)
+            })
+            .or_else(|| {
+                self.local_size_ids
+                    .iter()
+                    .find(|(id, _)| *id == entry)
+                    .and_then(|(_, ids)| self.resolve_size(ids))
+            })
+    }
+
+    fn resolve_size(&self, ids: &[u32]) -> Option<[u32; 3]> {
+        match ids {
+            [x, y, z] => {
+                Some([
+                    *self.constants.get(x)?,
+                    *self.constants.get(y)?,
+                    *self.constants.get(z)?,
+                ])
+            }
+            _ => None,
+        }
+    }
+
+    /// Appends one variable per location of `ty`, returns the number of locations.
+    fn push_locations(
+        &self,
+        ty: u32,
+        location: u32,
+        component: u32,
+        variables: &mut Vec<InterfaceVariable>,
+    ) -> u32 {
+        match self.types.get(&ty) {
+            Some(Type::Scalar(component_type, width)) => {
+                variables.push(InterfaceVariable {
+                    location,
+                    component,
+                    ty: InterfaceType {
+                        component_type: *component_type,
+                        width: *width,
+                        components: 1,
+                    },
+                });
+                1
+            }
+            Some(Type::Vector(component_type, count)) => {
+                let Some(Type::Scalar(component_type, width)) = self.types.get(component_type)
+                else {
+                    return 1
+                };
+
+                variables.push(InterfaceVariable {
+                    location,
+                    component,
+                    ty: InterfaceType {
+                        component_type: *component_type,
+                        width: *width,
+                        components: *count,
+                    },
+                });
+
+                // 64 bit vectors with more than 2 components span 2 locations.
+                if *width == 64 && *count > 2 {
+                    2
+                } else {
+                    1
+                }
+            }
+            Some(Type::Matrix(column, count)) => {
+                self.push_repeated(*column, *count, location, component, variables)
+            }
+            Some(Type::Array(element, length)) => {
+                let length = self.constants.get(length).copied().unwrap_or(1);
+                self.push_repeated(*element, length, location, component, variables)
+            }
+            Some(Type::Struct(members)) => {
+                members.iter().fold(0, |locations, member| {
+                    locations + self.push_locations(*member, location + locations, 0, variables)
+                })
+            }
+            _ => 1,
+        }
+    }
+
+    fn push_repeated(
+        &self,
+        ty: u32,
+        count: u32,
+        location: u32,
+        component: u32,
+        variables: &mut Vec<InterfaceVariable>,
+    ) -> u32 {
+        (0..count).fold(0, |locations, _| {
+            locations + self.push_locations(ty, location + locations, component, variables)
+        })
+    }
+
+    fn interface(
+        &self,
+        execution_model: u32,
+        entry: u32,
+        name: &str,
+        ids: &[u32],
+    ) -> Option<ShaderInterface> {
+        let stage = stage_from_execution_model(execution_model)?;
+
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+
+        for id in ids {
+            let Some(Type::Pointer(storage_class, pointee)) =
+                self.variables.get(id).and_then(|ty| self.types.get(ty))
+            else {
+                continue
+            };
+
+            let (variables, arrayed) = match *storage_class {
+                STORAGE_CLASS_INPUT => (&mut inputs, is_arrayed_input(stage)),
+                STORAGE_CLASS_OUTPUT => (&mut outputs, is_arrayed_output(stage)),
+                _ => continue,
+            };
+
+            if self.decoration(*id, DECORATION_BUILT_IN).is_some()
+                || self.decoration(*id, DECORATION_PATCH).is_some()
+                || self.built_in_structs.contains(pointee)
+            {
+                continue
+            }
+
+            // The per-vertex variables of some stages have an extra array level.
+            let ty = match self.types.get(pointee) {
+                Some(Type::Array(element, _)) if arrayed => *element,
+                _ => *pointee,
+            };
+
+            if let Some(location) = self.decoration(*id, DECORATION_LOCATION) {
+                let component = self.decoration(*id, DECORATION_COMPONENT).unwrap_or(0);
+                self.push_locations(ty, location, component, variables);
+            }
+        }
+
+        inputs.sort_by_key(|variable| (variable.location, variable.component));
+        outputs.sort_by_key(|variable| (variable.location, variable.component));
+
+        Some(ShaderInterface {
+            entry_point: name.to_owned(),
+            stage,
+            inputs,
+            outputs,
+            workgroup_size: has_workgroup(stage)
+                .then(|| self.workgroup_size(entry))
+                .flatten(),
+        })
+    }
+}
+
+fn stage_from_execution_model(execution_model: u32) -> Option<vk::ShaderStageFlags> {
+    Some(match execution_model {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        5364 => vk::ShaderStageFlags::TASK_EXT,
+        5365 => vk::ShaderStageFlags::MESH_EXT,
+        _ => return None,
+    })
+}
+
+#[inline]
+fn has_workgroup(stage: vk::ShaderStageFlags) -> bool {
+    stage == vk::ShaderStageFlags::COMPUTE
+        || stage == vk::ShaderStageFlags::TASK_EXT
+        || stage == vk::ShaderStageFlags::MESH_EXT
+}
+
+#[inline]
+fn is_arrayed_input(stage: vk::ShaderStageFlags) -> bool {
+    stage == vk::ShaderStageFlags::TESSELLATION_CONTROL
+        || stage == vk::ShaderStageFlags::TESSELLATION_EVALUATION
+        || stage == vk::ShaderStageFlags::GEOMETRY
+}
+
+#[inline]
+fn is_arrayed_output(stage: vk::ShaderStageFlags) -> bool {
+    stage == vk::ShaderStageFlags::TESSELLATION_CONTROL || stage == vk::ShaderStageFlags::MESH_EXT
+}
+
+/// Reflects the interfaces of the entry points of a SPIR-V module.
+pub(crate) fn reflect_shader_interfaces(code: &[u32]) -> Vec<ShaderInterface> {
+    let module = Module::parse(code);
+
+    module
+        .entry_points
+        .iter()
+        .filter_map(|(execution_model, entry, name, ids)| {
+            module.interface(*execution_model, *entry, name, ids)
+        })
+        .collect()
+}
+
+/// Checks that every input of a stage is written by the previous stage with a compatible type,
+/// and that the vertex shader inputs have attributes.
+pub(crate) fn validate_stage_interfaces<'a>(
+    interfaces: impl Iterator<Item = &'a ShaderInterface>,
+    vertex_input_state: Option<&VertexInputStateDesc>,
+) -> Result<(), InterfaceError> {
+    let mut interfaces = interfaces
+        // Task shaders pass their payload to mesh shaders, not locations.
+        .filter(|interface| interface.stage != vk::ShaderStageFlags::TASK_EXT)
+        .collect::<Vec<_>>();
+    interfaces.sort_by_key(|interface| stage_order(interface.stage));
+
+    if let Some(vertex) = interfaces
+        .iter()
+        .find(|interface| interface.stage == vk::ShaderStageFlags::VERTEX)
+    {
+        let attributes = vertex_input_state.map_or(&[][..], |state| &state.attributes);
+
+        if let Some(input) = vertex.inputs.iter().find(|input| {
+            !attributes
+                .iter()
+                .any(|attribute| attribute.location == input.location)
+        }) {
+            return Err(InterfaceError::MissingVertexAttribute {
+                location: input.location,
+            })
+        }
+    }
+
+    for stages in interfaces.windows(2) {
+        let (producer, consumer) = (stages[0], stages[1]);
+
+        for input in &consumer.inputs {
+            let Some(output) = producer.outputs.iter().find(|output| {
+                output.location == input.location && output.components().contains(&input.component)
+            }) else {
+                return Err(InterfaceError::MissingOutput {
+                    producer: producer.stage,
+                    consumer: consumer.stage,
+                    location: input.location,
+                    component: input.component,
+                })
+            };
+
+            // An output may have more components than the input reads.
+            let (outputs, inputs) = (output.components(), input.components());
+            if output.ty.component_type != input.ty.component_type
+                || output.ty.width != input.ty.width
+                || inputs.end > outputs.end
+            {
+                return Err(InterfaceError::TypeMismatch {
+                    producer: producer.stage,
+                    consumer: consumer.stage,
+                    location: input.location,
+                    output: output.ty,
+                    input: input.ty,
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn stage_order(stage: vk::ShaderStageFlags) -> u32 {
+    [
+        vk::ShaderStageFlags::VERTEX,
+        vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        vk::ShaderStageFlags::GEOMETRY,
+        vk::ShaderStageFlags::MESH_EXT,
+        vk::ShaderStageFlags::FRAGMENT,
+    ]
+    .iter()
+    .position(|order| *order == stage)
+    .unwrap_or_default() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use shaderc::{Compiler, ShaderKind};
+
+    use crate::backend::resource::pipeline::{
+        interface::{reflect_shader_interfaces, validate_stage_interfaces},
+        InterfaceError, ShaderInterface, VertexInputAttributeDesc, VertexInputStateDesc,
+    };
+
+    const VERTEX: &str = r"
+        #version 450
+        layout(location = 0) in vec3 position;
+        layout(location = 0) out vec4 color;
+        layout(location = 1) out vec2 uv;
+        void main() {
+            color = vec4(1.0);
+            uv = vec2(0.0);
+            gl_Position = vec4(position, 1.0);
+        }
+    ";
+
+    fn interface(source: &str, kind: ShaderKind) -> ShaderInterface {
+        let compiler = Compiler::new().unwrap();
+        let artifact = compiler
+            .compile_into_spirv(source, kind, "test.glsl", "main", None)
+            .unwrap();
+        reflect_shader_interfaces(artifact.as_binary()).remove(0)
+    }
+
+    fn fragment(inputs: &str) -> ShaderInterface {
+        let source = format!(
+            "#version 450\n{inputs}\nlayout(location = 0) out vec4 target;\nvoid main() {{ \
+             target = vec4(0.0); }}"
+        );
+        interface(&source, ShaderKind::Fragment)
+    }
+
+    fn vertex_input_state() -> VertexInputStateDesc {
+        VertexInputStateDesc {
+            attributes: vec![VertexInputAttributeDesc {
+                location: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn validate(fragment: &ShaderInterface) -> Result<(), InterfaceError> {
+        let vertex = interface(VERTEX, ShaderKind::Vertex);
+        validate_stage_interfaces([&vertex, fragment].into_iter(), Some(&vertex_input_state()))
+    }
+
+    #[test]
+    fn matching_interfaces() {
+        let fragment =
+            fragment("layout(location = 0) in vec4 color;\nlayout(location = 1) in vec2 uv;");
+        assert!(validate(&fragment).is_ok());
+    }
+
+    #[test]
+    fn inputs_may_read_fewer_components() {
+        assert!(validate(&fragment("layout(location = 0) in vec3 color;")).is_ok());
+        assert!(validate(&fragment(
+            "layout(location = 0, component = 2) in vec2 color;"
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn missing_output() {
+        assert!(matches!(
+            validate(&fragment("layout(location = 2) in vec4 normal;")),
+            Err(InterfaceError::MissingOutput {
+                location: 2,
+                component: 0,
+                ..
+            })
+        ));
+        assert!(matches!(
+            validate(&fragment("layout(location = 1, component = 2) in vec2 uv;")),
+            Err(InterfaceError::MissingOutput {
+                location: 1,
+                component: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn type_mismatch() {
+        assert!(matches!(
+            validate(&fragment("layout(location = 0) flat in ivec4 color;")),
+            Err(InterfaceError::TypeMismatch { location: 0, .. })
+        ));
+        assert!(matches!(
+            validate(&fragment("layout(location = 1) in vec4 uv;")),
+            Err(InterfaceError::TypeMismatch { location: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn missing_vertex_attribute() {
+        let vertex = interface(VERTEX, ShaderKind::Vertex);
+        assert!(matches!(
+            validate_stage_interfaces([&vertex].into_iter(), None),
+            Err(InterfaceError::MissingVertexAttribute { location: 0 })
+        ));
+        assert!(
+            validate_stage_interfaces([&vertex].into_iter(), Some(&vertex_input_state())).is_ok()
+        );
+    }
+}
@@ -1,18 +1,31 @@
 mod compute_pipeline;
+mod device_pipeline_cache;
 mod graphics_pipeline;
+mod interface;
 mod pipeline_cache;
 mod pipeline_layout;
+mod pipeline_state;
+mod push_constants;
 mod shader;
 mod shader_module;
+mod specialization;
+mod spirv;
+mod spirv_cache;
 
 use std::hash::Hash;
 
 pub use compute_pipeline::*;
+pub use device_pipeline_cache::*;
 pub use graphics_pipeline::*;
+pub use interface::*;
 pub use pipeline_cache::*;
 pub use pipeline_layout::*;
+pub use pipeline_state::*;
+pub use push_constants::*;
 pub use shader::*;
 pub use shader_module::*;
+pub use specialization::*;
+pub(crate) use spirv_cache::*;
 use tort_utils::Uuid;
 
 pub trait Pipeline {
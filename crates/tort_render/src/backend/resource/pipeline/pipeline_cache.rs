@@ -1,13 +1,27 @@
-use std::{borrow::Cow, path::PathBuf, sync::Arc};
+use std::{
+    borrow::Cow,
+    mem,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use ash::vk;
 use concurrent_queue::ConcurrentQueue;
+use log::{error, warn};
+use naga::{
+    back::spv,
+    valid::{Capabilities, ValidationFlags, Validator},
+};
 use parking_lot::{Mutex, RwLock};
-use shaderc::{CompileOptions, Compiler, ResolvedInclude, ShaderKind, SpirvVersion};
+use shaderc::{
+    CompileOptions, Compiler, OptimizationLevel, ResolvedInclude, ShaderKind, SourceLanguage,
+    SpirvVersion,
+};
 use tort_asset::{AssetEvent, AssetPath, Assets, Handle};
 use tort_ecs::{
     self as bevy_ecs,
-    event::EventReader,
+    event::{EventReader, EventWriter},
     system::{Res, ResMut, Resource},
 };
 use tort_tasks::AsyncComputeTaskPool;
@@ -18,21 +32,24 @@ use crate::{
         resource::{
             descriptor::{DescriptorSetLayout, DescriptorSetLayoutDesc},
             pipeline::{
-                ComputePipeline, ComputePipelineDesc, ComputePipelineId, GraphicsPipeline,
-                GraphicsPipelineDesc, GraphicsPipelineId, Pipeline, PipelineLayout,
-                PipelineLayoutDesc, Shader, ShaderModule, ShaderModuleDesc, ShaderSource,
-                ShaderStageDesc,
+                shader_stage_name, specialize, ComputePipeline, ComputePipelineDesc,
+                ComputePipelineId, DevicePipelineCache, GraphicsPipeline, GraphicsPipelineDesc,
+                GraphicsPipelineId, Pipeline, PipelineError, PipelineEvent, PipelineId,
+                PipelineLayout, PipelineLayoutDesc, PipelineState, PipelineStateSender, Shader,
+                ShaderModule, ShaderModuleDesc, ShaderSource, ShaderStageDesc, ShadercOptions,
+                SpirvCache,
             },
             Sampler, SamplerDesc,
         },
         utils::BackendError,
         Device,
     },
+    renderer::{DeletionQueue, FrameCtx},
     Extract,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-struct GlslStageDesc {
+struct CompiledStageDesc {
     shader: Handle<Shader>,
     stage: vk::ShaderStageFlags,
     entry_point: Cow<'static, str>,
@@ -47,18 +64,30 @@ struct Inner {
     shaders: RwLock<HashMap<Handle<Shader>, Shader>>,
     shader_paths: RwLock<HashMap<AssetPath<'static>, Handle<Shader>>>,
 
-    glsl_modules: Mutex<HashMap<GlslStageDesc, Arc<ShaderModule>>>,
+    compiled_modules: Mutex<HashMap<CompiledStageDesc, Arc<ShaderModule>>>,
     spirv_modules: Mutex<HashMap<Handle<Shader>, Arc<ShaderModule>>>,
 
-    ready_graphics_pipelines: ConcurrentQueue<GraphicsPipeline>,
-    ready_compute_pipelines: ConcurrentQueue<ComputePipeline>,
+    /// Finished compilations, tagged with the generation they were spawned with.
+    ready_graphics_pipelines: ConcurrentQueue<(
+        GraphicsPipelineId,
+        u64,
+        Result<GraphicsPipeline, PipelineError>,
+    )>,
+    ready_compute_pipelines: ConcurrentQueue<(
+        ComputePipelineId,
+        u64,
+        Result<ComputePipeline, PipelineError>,
+    )>,
+
+    device_pipeline_cache: DevicePipelineCache,
+    spirv_cache: Option<SpirvCache>,
 
     device: Device,
 }
 
 impl Inner {
-    fn new(device: Device) -> Self {
-        Self {
+    fn new(device: Device, desc: &PipelineCacheDesc) -> Result<Self, BackendError> {
+        Ok(Self {
             immutable_samplers: Mutex::new(HashMap::new()),
             descriptor_set_layouts: Mutex::new(HashMap::new()),
             pipeline_layouts: Mutex::new(HashMap::new()),
@@ -66,14 +95,17 @@ impl Inner {
             shaders: RwLock::new(HashMap::new()),
             shader_paths: RwLock::new(HashMap::new()),
 
-            glsl_modules: Mutex::new(HashMap::new()),
+            compiled_modules: Mutex::new(HashMap::new()),
             spirv_modules: Mutex::new(HashMap::new()),
 
             ready_graphics_pipelines: ConcurrentQueue::unbounded(),
             ready_compute_pipelines: ConcurrentQueue::unbounded(),
 
+            device_pipeline_cache: DevicePipelineCache::new(device.clone(), desc.path.as_deref())?,
+            spirv_cache: desc.spirv_cache_path.as_deref().map(SpirvCache::new),
+
             device,
-        }
+        })
     }
 
     fn get_immutable_sampler(&self, desc: &SamplerDesc) -> Result<Arc<Sampler>, BackendError> {
@@ -167,107 +199,304 @@ impl Inner {
                 }
             }
             ShaderSource::Glsl(source) => {
-                let stage_desc = GlslStageDesc {
-                    shader: stage_desc.shader.clone_weak(),
-                    stage: stage_desc.stage,
-                    entry_point: stage_desc.entry_point.clone(),
-                    defines: stage_desc.defines.clone(),
-                };
+                self.get_compiled_shader_module(stage_desc, shader, |stage_desc| {
+                    self.compile_shaderc(stage_desc, shader, source, SourceLanguage::GLSL)
+                })
+            }
+            ShaderSource::Hlsl(source) => {
+                self.get_compiled_shader_module(stage_desc, shader, |stage_desc| {
+                    self.compile_shaderc(stage_desc, shader, source, SourceLanguage::HLSL)
+                })
+            }
+            ShaderSource::Wgsl(source) => {
+                self.get_compiled_shader_module(stage_desc, shader, |stage_desc| {
+                    Self::translate_wgsl(stage_desc, source)
+                })
+            }
+        }
+    }
 
-                if let Some(shader_module) = {
-                    let glsl_modules = self.glsl_modules.lock();
-                    glsl_modules.get(&stage_desc).cloned()
-                } {
-                    Ok(shader_module)
-                } else {
-                    let compiler = Compiler::new().unwrap();
-
-                    let mut compile_options = CompileOptions::new().unwrap();
-                    compile_options.set_target_spirv(SpirvVersion::V1_4);
-
-                    let inner = self.clone();
-
-                    compile_options.set_include_callback(move |requested_source, _, _, _| {
-                        let path = AssetPath::from(tort_utils::normalize_path(&PathBuf::from(
-                            requested_source,
-                        )));
-
-                        let shader_paths = inner.shader_paths.read();
-                        let handle = shader_paths
-                            .get(&path)
-                            .ok_or("Failed to get include".to_owned())?;
-
-                        let shaders = inner.shaders.read();
-                        let shader = shaders
-                            .get(handle)
-                            .ok_or("Failed to get include".to_owned())?;
-
-                        match shader.source() {
-                            ShaderSource::Glsl(source) => {
-                                Ok(ResolvedInclude {
-                                    resolved_name: path.path().to_str().unwrap().to_owned(),
-                                    content: source.to_string(),
-                                })
-                            }
-                            _ => Err("Failed to get include".to_owned()),
-                        }
-                    });
-
-                    for (name, value) in &stage_desc.defines {
-                        compile_options.add_macro_definition(name, value.as_deref());
-                    }
+    /// Returns the cached module of a shader compiled from source, calling `compile` if needed.
+    fn get_compiled_shader_module(
+        &self,
+        stage_desc: &ShaderStageDesc,
+        shader: &Shader,
+        compile: impl FnOnce(&CompiledStageDesc) -> Result<Vec<u32>, BackendError>,
+    ) -> Result<Arc<ShaderModule>, BackendError> {
+        let stage_desc = CompiledStageDesc {
+            shader: stage_desc.shader.clone_weak(),
+            stage: stage_desc.stage,
+            entry_point: stage_desc.entry_point.clone(),
+            defines: stage_desc.defines.clone(),
+        };
+
+        if let Some(shader_module) = {
+            let compiled_modules = self.compiled_modules.lock();
+            compiled_modules.get(&stage_desc).cloned()
+        } {
+            Ok(shader_module)
+        } else {
+            let code = compile(&stage_desc)?;
 
-                    let shader_kind = match stage_desc.stage {
-                        vk::ShaderStageFlags::VERTEX => ShaderKind::Vertex,
-                        vk::ShaderStageFlags::TESSELLATION_CONTROL => ShaderKind::TessControl,
-                        vk::ShaderStageFlags::TESSELLATION_EVALUATION => ShaderKind::TessEvaluation,
-                        vk::ShaderStageFlags::GEOMETRY => ShaderKind::Geometry,
-                        vk::ShaderStageFlags::FRAGMENT => ShaderKind::Fragment,
-                        vk::ShaderStageFlags::COMPUTE => ShaderKind::Compute,
-                        vk::ShaderStageFlags::TASK_EXT => ShaderKind::Task,
-                        vk::ShaderStageFlags::MESH_EXT => ShaderKind::Mesh,
-                        vk::ShaderStageFlags::RAYGEN_KHR => ShaderKind::RayGeneration,
-                        vk::ShaderStageFlags::ANY_HIT_KHR => ShaderKind::AnyHit,
-                        vk::ShaderStageFlags::CLOSEST_HIT_KHR => ShaderKind::ClosestHit,
-                        vk::ShaderStageFlags::MISS_KHR => ShaderKind::Miss,
-                        vk::ShaderStageFlags::INTERSECTION_KHR => ShaderKind::Intersection,
-                        vk::ShaderStageFlags::CALLABLE_KHR => ShaderKind::Callable,
-                        _ => {
-                            panic!(
-                                "Invalid {}: {}",
-                                "vk::ShaderStageFlags",
-                                stage_desc.stage.as_raw()
-                            )
-                        }
-                    };
-
-                    let input_file_name = shader.path().path().to_str().unwrap();
-
-                    let artifact = compiler.compile_into_spirv(
-                        source,
-                        shader_kind,
-                        input_file_name,
-                        &stage_desc.entry_point,
-                        Some(&compile_options),
-                    )?;
+            let shader_module = Arc::new(ShaderModule::new(
+                self.device.clone(),
+                &ShaderModuleDesc {
+                    label: Some(shader.path().path().to_str().unwrap()),
+                    code: &code,
+                    ..Default::default()
+                },
+            )?);
 
-                    let shader_module = Arc::new(ShaderModule::new(
-                        self.device.clone(),
-                        &ShaderModuleDesc {
-                            label: Some(input_file_name),
-                            code: artifact.as_binary(),
-                            ..Default::default()
-                        },
-                    )?);
+            self.compiled_modules
+                .lock()
+                .insert(stage_desc, shader_module.clone());
 
-                    self.glsl_modules
-                        .lock()
-                        .insert(stage_desc, shader_module.clone());
+            Ok(shader_module)
+        }
+    }
 
-                    Ok(shader_module)
+    fn compile_shaderc(
+        self: &Arc<Self>,
+        stage_desc: &CompiledStageDesc,
+        shader: &Shader,
+        source: &str,
+        source_language: SourceLanguage,
+    ) -> Result<Vec<u32>, BackendError> {
+        let compiler = Compiler::new().unwrap();
+
+        let options = ShadercOptions {
+            source_language,
+            target_spirv_version: SpirvVersion::V1_4,
+            optimization_level: OptimizationLevel::Zero,
+            generate_debug_info: false,
+        };
+        let mut compile_options = CompileOptions::new().unwrap();
+        options.apply(&mut compile_options);
+
+        let inner = self.clone();
+
+        compile_options.set_include_callback(move |requested_source, _, _, _| {
+            let path =
+                AssetPath::from(tort_utils::normalize_path(&PathBuf::from(requested_source)));
+
+            let shader_paths = inner.shader_paths.read();
+            let handle = shader_paths
+                .get(&path)
+                .ok_or("Failed to get include".to_owned())?;
+
+            let shaders = inner.shaders.read();
+            let shader = shaders
+                .get(handle)
+                .ok_or("Failed to get include".to_owned())?;
+
+            match shader.source() {
+                ShaderSource::Glsl(source) | ShaderSource::Hlsl(source) => {
+                    Ok(ResolvedInclude {
+                        resolved_name: path.path().to_str().unwrap().to_owned(),
+                        content: source.to_string(),
+                    })
                 }
+                _ => Err("Failed to get include".to_owned()),
             }
+        });
+
+        for (name, value) in &stage_desc.defines {
+            compile_options.add_macro_definition(name, value.as_deref());
         }
+
+        // Lets files with several entry points exclude the code of other stages.
+        if let Some(stage_name) = shader_stage_name(stage_desc.stage) {
+            compile_options
+                .add_macro_definition(&format!("TORT_STAGE_{}", stage_name.to_uppercase()), None);
+        }
+
+        // GLSL requires the entry point to be called `main` in the source, the module still
+        // uses the entry point's name.
+        if source_language == SourceLanguage::GLSL && stage_desc.entry_point != "main" {
+            compile_options.add_macro_definition(&stage_desc.entry_point, Some("main"));
+        }
+
+        let shader_kind = match stage_desc.stage {
+            vk::ShaderStageFlags::VERTEX => ShaderKind::Vertex,
+            vk::ShaderStageFlags::TESSELLATION_CONTROL => ShaderKind::TessControl,
+            vk::ShaderStageFlags::TESSELLATION_EVALUATION => ShaderKind::TessEvaluation,
+            vk::ShaderStageFlags::GEOMETRY => ShaderKind::Geometry,
+            vk::ShaderStageFlags::FRAGMENT => ShaderKind::Fragment,
+            vk::ShaderStageFlags::COMPUTE => ShaderKind::Compute,
+            vk::ShaderStageFlags::TASK_EXT => ShaderKind::Task,
+            vk::ShaderStageFlags::MESH_EXT => ShaderKind::Mesh,
+            vk::ShaderStageFlags::RAYGEN_KHR => ShaderKind::RayGeneration,
+            vk::ShaderStageFlags::ANY_HIT_KHR => ShaderKind::AnyHit,
+            vk::ShaderStageFlags::CLOSEST_HIT_KHR => ShaderKind::ClosestHit,
+            vk::ShaderStageFlags::MISS_KHR => ShaderKind::Miss,
+            vk::ShaderStageFlags::INTERSECTION_KHR => ShaderKind::Intersection,
+            vk::ShaderStageFlags::CALLABLE_KHR => ShaderKind::Callable,
+            _ => {
+                panic!(
+                    "Invalid {}: {}",
+                    "vk::ShaderStageFlags",
+                    stage_desc.stage.as_raw()
+                )
+            }
+        };
+
+        let input_file_name = shader.path().path().to_str().unwrap();
+
+        let spirv_cache = self
+            .spirv_cache
+            .as_ref()
+            .map(|spirv_cache| -> Result<_, BackendError> {
+                // Includes are resolved by the preprocessor, so a modified include changes the
+                // key.
+                let preprocessed = compiler.preprocess(
+                    source,
+                    input_file_name,
+                    &stage_desc.entry_point,
+                    Some(&compile_options),
+                )?;
+
+                let key = SpirvCache::key(
+                    &options,
+                    stage_desc.stage,
+                    &stage_desc.entry_point,
+                    &stage_desc.defines,
+                    &preprocessed.as_text(),
+                );
+                Ok((spirv_cache, key))
+            })
+            .transpose()?;
+
+        if let Some(code) = spirv_cache
+            .as_ref()
+            .and_then(|(spirv_cache, key)| spirv_cache.get(key))
+        {
+            return Ok(code)
+        }
+
+        let artifact = compiler.compile_into_spirv(
+            source,
+            shader_kind,
+            input_file_name,
+            &stage_desc.entry_point,
+            Some(&compile_options),
+        )?;
+
+        if let Some((spirv_cache, key)) = &spirv_cache {
+            spirv_cache.insert(key, artifact.as_binary());
+        }
+
+        Ok(artifact.as_binary().to_vec())
+    }
+
+    /// Translates the entry point of a WGSL shader with naga. Defines aren't supported.
+    fn translate_wgsl(
+        stage_desc: &CompiledStageDesc,
+        source: &str,
+    ) -> Result<Vec<u32>, BackendError> {
+        let shader_stage = match stage_desc.stage {
+            vk::ShaderStageFlags::VERTEX => naga::ShaderStage::Vertex,
+            vk::ShaderStageFlags::FRAGMENT => naga::ShaderStage::Fragment,
+            vk::ShaderStageFlags::COMPUTE => naga::ShaderStage::Compute,
+            stage => return Err(BackendError::Wgsl(format!("Unsupported stage: {stage:?}"))),
+        };
+
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| BackendError::Wgsl(e.emit_to_string(source)))?;
+
+        let module_info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|e| BackendError::Wgsl(e.to_string()))?;
+
+        let mut options = spv::Options {
+            lang_version: (1, 4),
+            ..Default::default()
+        };
+        // The shaders share the clip space of the GLSL and HLSL shaders.
+        options
+            .flags
+            .remove(spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+
+        spv::write_vec(
+            &module,
+            &module_info,
+            &options,
+            Some(&spv::PipelineOptions {
+                shader_stage,
+                entry_point: stage_desc.entry_point.to_string(),
+            }),
+        )
+        .map_err(|e| BackendError::Wgsl(e.to_string()))
+    }
+
+    fn create_graphics_pipeline(
+        self: &Arc<Self>,
+        desc: &GraphicsPipelineDesc,
+        id: GraphicsPipelineId,
+        shaders: &[Shader],
+    ) -> Result<GraphicsPipeline, BackendError> {
+        let desc = GraphicsPipelineDesc {
+            stages: desc
+                .stages
+                .iter()
+                .zip(shaders)
+                .map(|(stage_desc, shader)| stage_desc.resolve(shader))
+                .collect::<Result<_, _>>()?,
+            ..desc.clone()
+        };
+
+        let shader_modules = desc
+            .stages
+            .iter()
+            .zip(shaders)
+            .map(|(stage_desc, shader)| self.get_shader_module(stage_desc, shader))
+            .collect::<Result<SmallVec4<_>, _>>()?;
+
+        let mut desc = desc;
+        for (stage_desc, shader_module) in desc.stages.iter_mut().zip(&shader_modules) {
+            stage_desc.specialization_info = specialize(
+                shader_module.specialization_constants(),
+                stage_desc.specialization_info.as_ref(),
+                &stage_desc.specialization_constants,
+            )?;
+        }
+
+        GraphicsPipeline::new(
+            self.device.clone(),
+            &desc,
+            id,
+            &shader_modules,
+            *self.device_pipeline_cache,
+            |layout_desc| self.get_pipeline_layout(layout_desc),
+        )
+    }
+
+    fn create_compute_pipeline(
+        self: &Arc<Self>,
+        desc: &ComputePipelineDesc,
+        id: ComputePipelineId,
+        shader: &Shader,
+    ) -> Result<ComputePipeline, BackendError> {
+        let mut desc = ComputePipelineDesc {
+            stage: desc.stage.resolve(shader)?,
+            ..desc.clone()
+        };
+
+        let shader_module = self.get_shader_module(&desc.stage, shader)?;
+
+        desc.stage.specialization_info = specialize(
+            shader_module.specialization_constants(),
+            desc.stage.specialization_info.as_ref(),
+            &desc.stage.specialization_constants,
+        )?;
+
+        ComputePipeline::new(
+            self.device.clone(),
+            &desc,
+            id,
+            &shader_module,
+            *self.device_pipeline_cache,
+            |layout_desc| self.get_pipeline_layout(layout_desc),
+        )
     }
 
     #[inline]
@@ -294,7 +523,13 @@ impl Inner {
 struct Pipelines<P: Pipeline> {
     pipelines: HashMap<P::Id, P>,
     ids: HashMap<P::Desc, P::Id>,
+    descs: HashMap<P::Id, P::Desc>,
+    states: HashMap<P::Id, PipelineState>,
+    /// The state changes not yet forwarded to the main world, in order.
+    state_changes: Vec<(P::Id, PipelineState)>,
     queued: Vec<P::Desc>,
+    /// Incremented every time a compilation is spawned, only the latest result is kept.
+    generations: HashMap<P::Id, u64>,
 
     shader_to_pipeline: HashMap<Handle<Shader>, HashSet<P::Id>>,
 }
@@ -304,7 +539,11 @@ impl<P: Pipeline> Pipelines<P> {
         Self {
             pipelines: HashMap::new(),
             ids: HashMap::new(),
+            descs: HashMap::new(),
+            states: HashMap::new(),
+            state_changes: Vec::new(),
             queued: Vec::new(),
+            generations: HashMap::new(),
 
             shader_to_pipeline: HashMap::new(),
         }
@@ -317,94 +556,256 @@ impl<P: Pipeline> Pipelines<P> {
                 id = P::Id::from(Uuid::new_v4());
             }
 
+            self.descs.insert(id, desc.clone());
+            self.states.insert(id, PipelineState::Queued);
+            self.state_changes.push((id, PipelineState::Queued));
             self.queued.push(desc.clone());
             id
         })
     }
 
+    #[inline]
+    fn set_state(&mut self, id: P::Id, state: PipelineState) {
+        self.states.insert(id, state.clone());
+        self.state_changes.push((id, state));
+    }
+
+    /// Returns the generation a compilation spawned now is tagged with.
+    #[inline]
+    fn next_generation(&mut self, id: P::Id) -> u64 {
+        let generation = self.generations.entry(id).or_insert(0);
+        *generation += 1;
+        *generation
+    }
+
+    /// Queues the pipelines using any of `modified_shaders` again, each of them once.
+    fn requeue(&mut self, modified_shaders: &HashSet<Handle<Shader>>) {
+        let ids = modified_shaders
+            .iter()
+            .filter_map(|shader| self.shader_to_pipeline.get(shader))
+            .flatten()
+            .copied()
+            .collect::<HashSet<_>>();
+
+        for id in ids {
+            // The pipeline may have never been created if its first compilation failed.
+            let desc = &self.descs[&id];
+            if !self.queued.contains(desc) {
+                self.queued.push(desc.clone());
+            }
+
+            self.set_state(id, PipelineState::Queued);
+        }
+    }
+
+    /// Makes the pipeline available if it was created, otherwise the last good one stays active.
+    ///
+    /// Returns `None` if the result is stale because the pipeline was compiled again since.
+    fn complete(
+        &mut self,
+        id: P::Id,
+        generation: u64,
+        result: Result<P, PipelineError>,
+        frame_index: usize,
+        deletion_queue: &mut DeletionQueue,
+    ) -> Option<PipelineEvent>
+    where
+        P: Send + Sync + 'static,
+        P::Id: Into<PipelineId>,
+    {
+        if self.generations.get(&id) != Some(&generation) {
+            return None
+        }
+
+        Some(match result {
+            Ok(pipeline) => {
+                self.set_state(id, PipelineState::Ready);
+
+                // Frames in flight may still use the replaced pipeline.
+                if let Some(replaced) = self.pipelines.insert(id, pipeline) {
+                    deletion_queue.push(frame_index, replaced);
+                }
+
+                PipelineEvent::Ready { id: id.into() }
+            }
+            Err(e) => {
+                self.set_state(id, PipelineState::Failed(e.clone()));
+
+                let id = id.into();
+                error!("Failed to create pipeline {id:?}: {e}");
+
+                PipelineEvent::Failed { id, error: e }
+            }
+        })
+    }
+
     #[inline]
     fn get(&self, id: &P::Id) -> Option<&P> {
         self.pipelines.get(id)
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineCacheDesc {
+    /// The file the Vulkan pipeline cache is loaded from at startup and saved to, `None`
+    /// disables persisting it.
+    pub path: Option<PathBuf>,
+    /// How often the pipeline cache is saved while running, it is also saved on shutdown.
+    pub save_interval: Option<Duration>,
+    /// The directory compiled GLSL shaders are cached in, `None` disables the cache.
+    pub spirv_cache_path: Option<PathBuf>,
+}
+
+impl Default for PipelineCacheDesc {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            path: Some(PathBuf::from("pipeline_cache.bin")),
+            save_interval: Some(Duration::from_secs(60)),
+            spirv_cache_path: Some(PathBuf::from("shader_cache")),
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct PipelineCache {
     inner: Arc<Inner>,
 
-    modified_shaders: Vec<Handle<Shader>>,
+    save_interval: Option<Duration>,
+    last_save: Instant,
+
+    /// The modified shaders and the shaders including them, directly or transitively.
+    modified_shaders: HashSet<Handle<Shader>>,
+    /// The files each shader includes directly.
+    includes: HashMap<AssetPath<'static>, Vec<AssetPath<'static>>>,
+    /// The reverse of `includes`, the shaders including each file directly.
+    included_by: HashMap<AssetPath<'static>, HashSet<AssetPath<'static>>>,
 
     graphics_pipelines: Pipelines<GraphicsPipeline>,
     compute_pipelines: Pipelines<ComputePipeline>,
 }
 
 impl PipelineCache {
-    pub fn new(device: Device) -> Self {
-        Self {
-            inner: Arc::new(Inner::new(device)),
+    pub fn new(device: Device, desc: &PipelineCacheDesc) -> Result<Self, BackendError> {
+        Ok(Self {
+            inner: Arc::new(Inner::new(device, desc)?),
+
+            save_interval: desc.save_interval,
+            last_save: Instant::now(),
 
-            modified_shaders: Vec::new(),
+            modified_shaders: HashSet::new(),
+            includes: HashMap::new(),
+            included_by: HashMap::new(),
 
             graphics_pipelines: Pipelines::new(),
             compute_pipelines: Pipelines::new(),
-        }
+        })
     }
 
-    #[inline]
-    fn create_shader(&self, handle: &Handle<Shader>, shader: &Shader) {
+    fn create_shader(&mut self, handle: &Handle<Shader>, shader: &Shader) {
         self.inner.create_shader(handle, shader);
+        self.update_includes(shader);
+
+        // Shaders that failed to compile because the file was missing can be compiled now.
+        self.invalidate_dependents(shader.path());
     }
 
-    #[inline]
     fn modify_shader(&mut self, handle: &Handle<Shader>, shader: &Shader) {
         self.inner.modify_shader(handle, shader);
-        self.modified_shaders.push(handle.clone_weak());
+        self.update_includes(shader);
+
+        self.modified_shaders.insert(handle.clone_weak());
+        self.invalidate_dependents(shader.path());
     }
 
-    #[inline]
-    fn remove_shader(&self, handle: &Handle<Shader>) {
+    fn remove_shader(&mut self, handle: &Handle<Shader>) {
+        let path = self
+            .inner
+            .shaders
+            .read()
+            .get(handle)
+            .map(|shader| shader.path().clone());
+
+        if let Some(path) = path {
+            self.remove_includes(&path);
+        }
+
         self.inner.remove_shader(handle);
     }
 
-    fn process_graphics_pipelines(&mut self) {
-        let mut waited_idle = false;
+    fn update_includes(&mut self, shader: &Shader) {
+        let path = shader.path();
+        self.remove_includes(path);
 
-        while let Ok(graphics_pipeline) = self.inner.ready_graphics_pipelines.pop() {
-            if self
-                .graphics_pipelines
-                .pipelines
-                .contains_key(graphics_pipeline.id())
-            {
-                if !waited_idle {
-                    unsafe { self.inner.device.loader().device_wait_idle() }.unwrap();
-                    waited_idle = true;
-                }
+        for include in shader.includes() {
+            self.included_by
+                .entry(include.clone())
+                .or_insert_with(HashSet::new)
+                .insert(path.clone());
+        }
 
-                self.graphics_pipelines
-                    .pipelines
-                    .entry(*graphics_pipeline.id())
-                    .and_modify(|p| *p = graphics_pipeline);
-            } else {
-                self.graphics_pipelines
-                    .pipelines
-                    .insert(*graphics_pipeline.id(), graphics_pipeline);
+        self.includes
+            .insert(path.clone(), shader.includes().to_vec());
+    }
+
+    fn remove_includes(&mut self, path: &AssetPath<'static>) {
+        for include in self.includes.remove(path).into_iter().flatten() {
+            if let Some(included_by) = self.included_by.get_mut(&include) {
+                included_by.remove(path);
+
+                if included_by.is_empty() {
+                    self.included_by.remove(&include);
+                }
             }
         }
+    }
 
-        for modified_shader in &self.modified_shaders {
-            if let Some(graphics_pipelines) = self
-                .graphics_pipelines
-                .shader_to_pipeline
-                .get(modified_shader)
-            {
-                for graphics_pipeline in graphics_pipelines {
-                    let desc = self.graphics_pipelines.pipelines[graphics_pipeline].desc();
-                    self.graphics_pipelines.queued.push(desc.clone());
+    /// Marks every shader including `path`, directly or transitively, as modified.
+    fn invalidate_dependents(&mut self, path: &AssetPath<'static>) {
+        let mut dependents = HashSet::new();
+        let mut stack = vec![path];
+
+        while let Some(path) = stack.pop() {
+            for dependent in self.included_by.get(path).into_iter().flatten() {
+                // Includes can be cyclic, guarded by include guards.
+                if dependents.insert(dependent) {
+                    stack.push(dependent);
                 }
             }
         }
 
-        self.graphics_pipelines.queued.retain(|desc| {
+        let shader_paths = self.inner.shader_paths.read();
+        self.modified_shaders.extend(
+            dependents
+                .into_iter()
+                .filter_map(|dependent| shader_paths.get(dependent))
+                .map(Handle::clone_weak),
+        );
+    }
+
+    fn process_graphics_pipelines(
+        &mut self,
+        frame_index: usize,
+        deletion_queue: &mut DeletionQueue,
+        events: &mut EventWriter<PipelineEvent>,
+    ) {
+        while let Ok((id, generation, result)) = self.inner.ready_graphics_pipelines.pop() {
+            if let Some(event) = self.graphics_pipelines.complete(
+                id,
+                generation,
+                result,
+                frame_index,
+                deletion_queue,
+            ) {
+                events.send(event);
+            }
+        }
+
+        self.graphics_pipelines.requeue(&self.modified_shaders);
+
+        // The closure needs the whole `Pipelines`, so the queue is taken out while it's filtered.
+        let mut queued = mem::take(&mut self.graphics_pipelines.queued);
+        queued.retain(|desc| {
             let Some(shaders) = ({
                 let shaders = self.inner.shaders.read();
                 desc.stages.iter().map(|stage_desc| shaders.get(&stage_desc.shader).cloned()).collect::<Option<SmallVec4<_>>>()
@@ -421,58 +822,48 @@ impl PipelineCache {
                 pipelines.insert(id);
             }
 
+            self.graphics_pipelines
+                .set_state(id, PipelineState::Compiling);
+            let generation = self.graphics_pipelines.next_generation(id);
+
             AsyncComputeTaskPool::get()
                 .spawn(async move {
-                    let shader_modules = desc
-                        .stages
-                        .iter()
-                        .zip(shaders.into_iter())
-                        .map(|(stage_desc, shader)| inner.get_shader_module(stage_desc, &shader))
-                        .collect::<Result<SmallVec4<_>, _>>()
-                        .unwrap(); //TODO:
-
-                    let graphics_pipeline = GraphicsPipeline::new(inner.device.clone(), &desc, id, &shader_modules, |layout_desc| inner.get_pipeline_layout(layout_desc)).unwrap(); //TODO:
-                    inner.ready_graphics_pipelines.push(graphics_pipeline).plain_unwrap();
+                    let result = inner
+                        .create_graphics_pipeline(&desc, id, &shaders)
+                        .map_err(|e| PipelineError::new(&e));
+
+                    inner
+                        .ready_graphics_pipelines
+                        .push((id, generation, result))
+                        .plain_unwrap();
                 })
                 .detach();
 
             false
         });
+        self.graphics_pipelines.queued = queued;
     }
 
-    fn process_compute_pipelines(&mut self) {
-        let mut waited_idle = false;
-
-        while let Ok(compute_pipeline) = self.inner.ready_compute_pipelines.pop() {
-            if self
-                .compute_pipelines
-                .pipelines
-                .contains_key(compute_pipeline.id())
-                && !waited_idle
+    fn process_compute_pipelines(
+        &mut self,
+        frame_index: usize,
+        deletion_queue: &mut DeletionQueue,
+        events: &mut EventWriter<PipelineEvent>,
+    ) {
+        while let Ok((id, generation, result)) = self.inner.ready_compute_pipelines.pop() {
+            if let Some(event) =
+                self.compute_pipelines
+                    .complete(id, generation, result, frame_index, deletion_queue)
             {
-                unsafe { self.inner.device.loader().device_wait_idle() }.unwrap();
-                waited_idle = true;
+                events.send(event);
             }
-
-            self.compute_pipelines
-                .pipelines
-                .insert(*compute_pipeline.id(), compute_pipeline);
         }
 
-        for modified_shader in &self.modified_shaders {
-            if let Some(compute_pipelines) = self
-                .compute_pipelines
-                .shader_to_pipeline
-                .get(modified_shader)
-            {
-                for compute_pipeline in compute_pipelines {
-                    let desc = self.compute_pipelines.pipelines[compute_pipeline].desc();
-                    self.compute_pipelines.queued.push(desc.clone());
-                }
-            }
-        }
+        self.compute_pipelines.requeue(&self.modified_shaders);
 
-        self.compute_pipelines.queued.retain(|desc| {
+        // The closure needs the whole `Pipelines`, so the queue is taken out while it's filtered.
+        let mut queued = mem::take(&mut self.compute_pipelines.queued);
+        queued.retain(|desc| {
             let Some(shader) = self.inner.shaders.read().get(&desc.stage.shader).cloned() else {
                 return true;
             };
@@ -488,26 +879,26 @@ impl PipelineCache {
                 .or_insert_with(HashSet::new);
             pipelines.insert(id);
 
+            self.compute_pipelines
+                .set_state(id, PipelineState::Compiling);
+            let generation = self.compute_pipelines.next_generation(id);
+
             AsyncComputeTaskPool::get()
                 .spawn(async move {
-                    let shader_module = inner.get_shader_module(&desc.stage, &shader).unwrap(); //TODO:
-                    let compute_pipeline = ComputePipeline::new(
-                        inner.device.clone(),
-                        &desc,
-                        id,
-                        &shader_module,
-                        |layout_desc| inner.get_pipeline_layout(layout_desc),
-                    )
-                    .unwrap(); //TODO:
+                    let result = inner
+                        .create_compute_pipeline(&desc, id, &shader)
+                        .map_err(|e| PipelineError::new(&e));
+
                     inner
                         .ready_compute_pipelines
-                        .push(compute_pipeline)
+                        .push((id, generation, result))
                         .plain_unwrap();
                 })
                 .detach();
 
             false
-        })
+        });
+        self.compute_pipelines.queued = queued;
     }
 
     #[inline]
@@ -520,6 +911,31 @@ impl PipelineCache {
         self.compute_pipelines.queue(desc)
     }
 
+    /// Returns the cached layout for `desc`, creating it if needed.
+    #[inline]
+    pub fn get_descriptor_set_layout(
+        &self,
+        desc: &DescriptorSetLayoutDesc,
+    ) -> Result<Arc<DescriptorSetLayout>, BackendError> {
+        self.inner.get_descriptor_set_layout(desc)
+    }
+
+    /// Writes the Vulkan pipeline cache to [`PipelineCacheDesc::path`] if new pipelines were
+    /// added to it.
+    #[inline]
+    pub fn save(&self) -> Result<(), BackendError> {
+        self.inner.device_pipeline_cache.save()
+    }
+
+    /// Returns `None` if the pipeline was never queued.
+    #[inline]
+    pub fn pipeline_state(&self, id: impl Into<PipelineId>) -> Option<&PipelineState> {
+        match id.into() {
+            PipelineId::Graphics(id) => self.graphics_pipelines.states.get(&id),
+            PipelineId::Compute(id) => self.compute_pipelines.states.get(&id),
+        }
+    }
+
     #[inline]
     pub fn get_graphics_pipeline(&self, id: &GraphicsPipelineId) -> Option<&GraphicsPipeline> {
         self.graphics_pipelines.get(id)
@@ -552,11 +968,16 @@ impl PipelineCache {
         }
     }
 
-    pub fn process_pipelines_system(mut cache: ResMut<Self>) {
+    pub fn process_pipelines_system(
+        mut cache: ResMut<Self>,
+        frame_ctx: Res<FrameCtx>,
+        mut deletion_queue: ResMut<DeletionQueue>,
+        mut events: EventWriter<PipelineEvent>,
+    ) {
         for modified_shader in &cache.modified_shaders {
             cache
                 .inner
-                .glsl_modules
+                .compiled_modules
                 .lock()
                 .retain(|k, _| &k.shader != modified_shader);
 
@@ -567,9 +988,48 @@ impl PipelineCache {
                 .retain(|k, _| k != modified_shader);
         }
 
-        cache.process_graphics_pipelines();
-        cache.process_compute_pipelines();
+        let frame_index = frame_ctx.frame_index();
+        cache.process_graphics_pipelines(frame_index, &mut deletion_queue, &mut events);
+        cache.process_compute_pipelines(frame_index, &mut deletion_queue, &mut events);
 
         cache.modified_shaders.clear();
     }
+
+    /// Sends the state changes of this frame to the main world, see
+    /// [`PipelineStates`](crate::backend::resource::pipeline::PipelineStates).
+    pub fn forward_states_system(mut cache: ResMut<Self>, sender: Res<PipelineStateSender>) {
+        let cache = &mut *cache;
+        let changes = cache
+            .graphics_pipelines
+            .state_changes
+            .drain(..)
+            .map(|(id, state)| (PipelineId::from(id), state))
+            .chain(
+                cache
+                    .compute_pipelines
+                    .state_changes
+                    .drain(..)
+                    .map(|(id, state)| (PipelineId::from(id), state)),
+            );
+
+        for change in changes {
+            // The receiver is only gone while the app shuts down.
+            let _ = sender.0.try_send(change);
+        }
+    }
+
+    pub fn save_system(mut cache: ResMut<Self>) {
+        let Some(save_interval) = cache.save_interval else {
+            return
+        };
+
+        if cache.last_save.elapsed() < save_interval {
+            return
+        }
+
+        if let Err(e) = cache.save() {
+            warn!("Failed to save pipeline cache: {e}");
+        }
+        cache.last_save = Instant::now();
+    }
 }
@@ -9,7 +9,7 @@ use crate::backend::{
         descriptor::{
             DescriptorSetLayout, DescriptorSetLayoutBindingDesc, DescriptorSetLayoutDesc,
         },
-        pipeline::ShaderModule,
+        pipeline::{merge_push_constant_ranges, ShaderModule},
         SamplerDesc,
     },
     utils::{debug_utils, BackendError},
@@ -37,6 +37,12 @@ pub enum PipelineLayoutModifier {
         binding: u32,
         descriptor_count: u32,
     },
+    /// Replaces the reflected layout of a set, e.g. with the layout of the
+    /// [`BindlessTables`](crate::bindless::BindlessTables).
+    SetLayout {
+        set: u32,
+        layout: DescriptorSetLayoutDesc,
+    },
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
@@ -82,9 +88,10 @@ impl PipelineLayoutDesc {
     pub fn from_spirv<'a>(
         shader_stages: impl Iterator<Item = (vk::ShaderStageFlags, &'a ShaderModule)>,
         modifiers: &[PipelineLayoutModifier],
-    ) -> Self {
+    ) -> Result<Self, BackendError> {
         let mut desc = Self::default();
         let mut reflected_sets = BTreeMap::new();
+        let mut push_constant_ranges = SmallVec8::new();
 
         for (stage_flags, shader_module) in shader_stages {
             for (set_index, set) in shader_module.descriptor_sets() {
@@ -116,14 +123,19 @@ impl PipelineLayoutDesc {
             }
 
             if let Some(push_constant_range) = shader_module.push_constant_info() {
-                desc.push_constant_ranges.push(PushConstantRange {
-                    stage_flags,
-                    offset: push_constant_range.offset,
-                    size: push_constant_range.size,
-                });
+                push_constant_ranges.push((
+                    PushConstantRange {
+                        stage_flags,
+                        offset: push_constant_range.offset,
+                        size: push_constant_range.size,
+                    },
+                    shader_module.push_constant_members().to_vec(),
+                ));
             }
         }
 
+        desc.push_constant_ranges = merge_push_constant_ranges(&push_constant_ranges)?;
+
         for reflected_set in reflected_sets.values() {
             let mut set_layout_desc = DescriptorSetLayoutDesc::default();
             set_layout_desc.bindings = Vec::with_capacity(reflected_set.len());
@@ -176,10 +188,18 @@ impl PipelineLayoutDesc {
                     desc.set_layouts[*set as usize].bindings[*binding as usize].descriptor_count =
                         *descriptor_count
                 }
+                PipelineLayoutModifier::SetLayout { set, layout } => {
+                    if desc.set_layouts.len() <= *set as usize {
+                        desc.set_layouts
+                            .resize_with(*set as usize + 1, DescriptorSetLayoutDesc::default);
+                    }
+
+                    desc.set_layouts[*set as usize] = layout.clone()
+                }
             }
         }
 
-        desc
+        Ok(desc)
     }
 }
 
@@ -193,6 +213,7 @@ impl From<&PipelineLayoutDesc> for PipelineLayoutDesc {
 pub struct PipelineLayout {
     pipeline_layout: vk::PipelineLayout,
     descriptor_set_layouts: Vec<Arc<DescriptorSetLayout>>,
+    push_constant_ranges: Vec<PushConstantRange>,
     device: Device,
 }
 
@@ -236,6 +257,7 @@ impl PipelineLayout {
         Ok(Self {
             pipeline_layout,
             descriptor_set_layouts,
+            push_constant_ranges: desc.push_constant_ranges.clone(),
             device,
         })
     }
@@ -244,6 +266,22 @@ impl PipelineLayout {
     pub fn descriptor_set_layouts(&self) -> &Vec<Arc<DescriptorSetLayout>> {
         &self.descriptor_set_layouts
     }
+
+    /// The merged push constant ranges, no stage is in more than one range.
+    #[inline]
+    pub fn push_constant_ranges(&self) -> &[PushConstantRange] {
+        &self.push_constant_ranges
+    }
+
+    /// The size of the push constants, which start at offset 0.
+    #[inline]
+    pub fn push_constant_size(&self) -> u32 {
+        self.push_constant_ranges
+            .iter()
+            .map(|range| range.offset + range.size)
+            .max()
+            .unwrap_or_default()
+    }
 }
 
 impl Deref for PipelineLayout {
@@ -0,0 +1,242 @@
+use std::fmt;
+
+use async_channel::{Receiver, Sender};
+use tort_ecs::{
+    self as bevy_ecs,
+    event::EventWriter,
+    system::{Res, ResMut, Resource},
+};
+use tort_utils::HashMap;
+
+use crate::backend::{
+    resource::pipeline::{ComputePipelineId, GraphicsPipelineId},
+    utils::BackendError,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PipelineId {
+    Graphics(GraphicsPipelineId),
+    Compute(ComputePipelineId),
+}
+
+impl From<GraphicsPipelineId> for PipelineId {
+    #[inline]
+    fn from(id: GraphicsPipelineId) -> Self {
+        Self::Graphics(id)
+    }
+}
+
+impl From<ComputePipelineId> for PipelineId {
+    #[inline]
+    fn from(id: ComputePipelineId) -> Self {
+        Self::Compute(id)
+    }
+}
+
+/// A single message of the shader compiler, e.g. `shaders/mesh.frag:12: error: ...`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderDiagnostic {
+    pub file: String,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl ShaderDiagnostic {
+    fn parse(line: &str) -> Option<Self> {
+        let (location, message) =
+            [": error: ", ": warning: "]
+                .into_iter()
+                .find_map(|separator| {
+                    let (location, message) = line.split_once(separator)?;
+                    Some((location, format!("{}{message}", &separator[2..])))
+                })?;
+
+        let (file, line) = match location.rsplit_once(':') {
+            Some((file, line)) => {
+                match line.parse() {
+                    Ok(line) => (file, Some(line)),
+                    Err(_) => (location, None),
+                }
+            }
+            None => (location, None),
+        };
+
+        Some(Self {
+            file: file.to_owned(),
+            line,
+            message,
+        })
+    }
+}
+
+impl fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: {}", self.file, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+/// Why a pipeline couldn't be created, with the compiler messages if a shader failed to compile.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineError {
+    message: String,
+    diagnostics: Vec<ShaderDiagnostic>,
+}
+
+impl PipelineError {
+    pub(crate) fn new(error: &BackendError) -> Self {
+        let message = error.to_string();
+        let diagnostics = match error {
+            // The log of shaderc, without the prefixes added by the `Display` impls.
+            BackendError::Shaderc(shaderc::Error::CompilationError(_, log)) => {
+                log.lines().filter_map(ShaderDiagnostic::parse).collect()
+            }
+            _ => Vec::new(),
+        };
+
+        Self {
+            message,
+            diagnostics,
+        }
+    }
+
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    #[inline]
+    pub fn diagnostics(&self) -> &[ShaderDiagnostic] {
+        &self.diagnostics
+    }
+}
+
+impl fmt::Display for PipelineError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The state of a queued pipeline, see
+/// [`PipelineCache::pipeline_state`](crate::backend::resource::pipeline::PipelineCache::pipeline_state)
+/// in the render world and [`PipelineStates`] in the main world.
+///
+/// A pipeline that was ready once stays available while it is recompiled, and also if the
+/// recompilation fails.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PipelineState {
+    /// Waiting for its shaders to be loaded.
+    Queued,
+    Compiling,
+    Ready,
+    Failed(PipelineError),
+}
+
+/// Sent in the render world whenever a pipeline finished compiling, and forwarded to the main
+/// world a frame later.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PipelineEvent {
+    Ready {
+        id: PipelineId,
+    },
+    Failed {
+        id: PipelineId,
+        error: PipelineError,
+    },
+}
+
+/// Channel to send pipeline state changes from the render world to the main world.
+#[derive(Resource)]
+pub struct PipelineStateSender(pub Sender<(PipelineId, PipelineState)>);
+
+/// Channel to receive pipeline state changes from the render world in the main world.
+#[derive(Resource)]
+pub struct PipelineStateReceiver(pub Receiver<(PipelineId, PipelineState)>);
+
+pub fn create_pipeline_state_channels() -> (PipelineStateSender, PipelineStateReceiver) {
+    // Unbounded, a dropped change would leave the main world with a stale state.
+    let (sender, receiver) = async_channel::unbounded();
+    (PipelineStateSender(sender), PipelineStateReceiver(receiver))
+}
+
+/// The states of the pipelines in the main world, mirroring the render world's
+/// [`PipelineCache`](crate::backend::resource::pipeline::PipelineCache).
+#[derive(Resource, Default)]
+pub struct PipelineStates {
+    states: HashMap<PipelineId, PipelineState>,
+}
+
+impl PipelineStates {
+    /// Returns `None` if the render world hasn't reported the pipeline yet.
+    #[inline]
+    pub fn get(&self, id: impl Into<PipelineId>) -> Option<&PipelineState> {
+        self.states.get(&id.into())
+    }
+
+    /// Applies the changes received from the render world and sends the matching
+    /// [`PipelineEvent`]s in the main world.
+    pub fn receive_system(
+        mut states: ResMut<Self>,
+        receiver: Res<PipelineStateReceiver>,
+        mut events: EventWriter<PipelineEvent>,
+    ) {
+        while let Ok((id, state)) = receiver.0.try_recv() {
+            match &state {
+                PipelineState::Ready => events.send(PipelineEvent::Ready { id }),
+                PipelineState::Failed(error) => {
+                    events.send(PipelineEvent::Failed {
+                        id,
+                        error: error.clone(),
+                    })
+                }
+                PipelineState::Queued | PipelineState::Compiling => {}
+            }
+
+            states.states.insert(id, state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shaderc::{Compiler, ShaderKind};
+
+    use crate::backend::{
+        resource::pipeline::{PipelineError, ShaderDiagnostic},
+        utils::BackendError,
+    };
+
+    const SOURCE: &str = "#version 450
+layout(location = 0) out float color;
+void main() {
+    color = undefined_value;
+}
+";
+
+    #[test]
+    fn shaderc_diagnostics() {
+        let compiler = Compiler::new().unwrap();
+        let error = compiler
+            .compile_into_spirv(
+                SOURCE,
+                ShaderKind::Fragment,
+                "shaders/broken.frag",
+                "main",
+                None,
+            )
+            .unwrap_err();
+        let error = PipelineError::new(&BackendError::from(error));
+
+        assert_eq!(
+            error.diagnostics().first(),
+            Some(&ShaderDiagnostic {
+                file: "shaders/broken.frag".to_owned(),
+                line: Some(4),
+                message: "error: 'undefined_value' : undeclared identifier".to_owned(),
+            })
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use ash::vk;
+use thiserror::Error;
+
+use crate::backend::resource::pipeline::{
+    spirv::{
+        self, DECORATION_OFFSET, OP_MEMBER_DECORATE, OP_TYPE_POINTER, OP_VARIABLE,
+        STORAGE_CLASS_PUSH_CONSTANT,
+    },
+    PipelineLayout, PushConstantRange,
+};
+
+#[derive(Debug, Error)]
+pub enum PushConstantError {
+    #[error(
+        "The push constant blocks of {stages:?} overlap but have different members at offset \
+         {offset}"
+    )]
+    IncompatibleLayout {
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+    },
+    #[error("The push constants are {expected} bytes, but {found} bytes were given")]
+    SizeMismatch { expected: u32, found: usize },
+}
+
+/// A member of a push constant block, its size includes the padding up to the next member.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PushConstantMember {
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Finds the member offsets of the push constant block of a SPIR-V module, `end` is the end
+/// of the block as reflected by `rspirv_reflect`.
+pub(crate) fn reflect_push_constant_members(code: &[u32], end: u32) -> Vec<PushConstantMember> {
+    let mut pointers = Vec::new();
+    let mut offsets = Vec::new();
+    let mut block_pointer = None;
+
+    for (opcode, operands) in spirv::instructions(code) {
+        match (opcode, operands) {
+            (OP_TYPE_POINTER, [result, STORAGE_CLASS_PUSH_CONSTANT, pointee]) => {
+                pointers.push((*result, *pointee))
+            }
+            (OP_MEMBER_DECORATE, [struct_type, _, DECORATION_OFFSET, offset]) => {
+                offsets.push((*struct_type, *offset))
+            }
+            (OP_VARIABLE, [result_type, _, STORAGE_CLASS_PUSH_CONSTANT, ..]) => {
+                block_pointer = block_pointer.or(Some(*result_type))
+            }
+            _ => {}
+        }
+    }
+
+    let Some(block) = block_pointer.and_then(|block_pointer| {
+        pointers
+            .iter()
+            .find(|(result, _)| *result == block_pointer)
+            .map(|(_, pointee)| *pointee)
+    }) else {
+        return Vec::new()
+    };
+
+    let mut member_offsets = offsets
+        .into_iter()
+        .filter(|(struct_type, _)| *struct_type == block)
+        .map(|(_, offset)| offset)
+        .collect::<Vec<_>>();
+    member_offsets.sort_unstable();
+
+    member_offsets
+        .iter()
+        .enumerate()
+        .map(|(i, &offset)| {
+            let next = member_offsets.get(i + 1).copied().unwrap_or(end);
+            PushConstantMember {
+                offset,
+                size: next.saturating_sub(offset),
+            }
+        })
+        .collect()
+}
+
+/// Checks that the members both blocks declare in the bytes they share are the same.
+fn check_compatible(
+    (a_range, a_members): (&PushConstantRange, &[PushConstantMember]),
+    (b_range, b_members): (&PushConstantRange, &[PushConstantMember]),
+) -> Result<(), PushConstantError> {
+    let start = a_range.offset.max(b_range.offset);
+    let end = (a_range.offset + a_range.size).min(b_range.offset + b_range.size);
+
+    let in_overlap = |member: &&PushConstantMember| member.offset >= start && member.offset < end;
+    let mismatch = a_members
+        .iter()
+        .filter(in_overlap)
+        .find(|member| !b_members.contains(member))
+        .or_else(|| {
+            b_members
+                .iter()
+                .filter(in_overlap)
+                .find(|member| !a_members.contains(member))
+        });
+
+    match mismatch {
+        Some(member) => {
+            Err(PushConstantError::IncompatibleLayout {
+                stages: a_range.stage_flags | b_range.stage_flags,
+                offset: member.offset,
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+/// Checks that `size` is the size of the push constants of `pipeline_layout`, if given.
+pub(crate) fn check_push_constant_size(
+    pipeline_layout: &PipelineLayout,
+    size: Option<u32>,
+) -> Result<(), PushConstantError> {
+    match size {
+        Some(size) if size != pipeline_layout.push_constant_size() => {
+            Err(PushConstantError::SizeMismatch {
+                expected: pipeline_layout.push_constant_size(),
+                found: size as usize,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Merges the push constant blocks of the stages of a pipeline, so every stage is in a single
+/// range and blocks declared by several stages share one range with the union of their flags.
+pub(crate) fn merge_push_constant_ranges(
+    stage_ranges: &[(PushConstantRange, Vec<PushConstantMember>)],
+) -> Result<Vec<PushConstantRange>, PushConstantError> {
+    for (i, (a_range, a_members)) in stage_ranges.iter().enumerate() {
+        for (b_range, b_members) in &stage_ranges[i + 1..] {
+            let overlaps = a_range.offset < b_range.offset + b_range.size
+                && b_range.offset < a_range.offset + a_range.size;
+
+            if overlaps {
+                check_compatible((a_range, a_members), (b_range, b_members))?;
+            }
+        }
+    }
+
+    let mut ranges = stage_ranges
+        .iter()
+        .map(|(range, _)| range.clone())
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|range| range.offset);
+
+    let mut merged: Vec<PushConstantRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.offset < last.offset + last.size => {
+                let end = (last.offset + last.size).max(range.offset + range.size);
+                last.stage_flags |= range.stage_flags;
+                last.size = end - last.offset;
+            }
+            _ => merged.push(range),
+        }
+    }
+
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use crate::backend::resource::pipeline::{
+        push_constants::{check_compatible, merge_push_constant_ranges},
+        PushConstantError, PushConstantMember, PushConstantRange,
+    };
+
+    fn range(stage_flags: vk::ShaderStageFlags, offset: u32, size: u32) -> PushConstantRange {
+        PushConstantRange {
+            stage_flags,
+            offset,
+            size,
+        }
+    }
+
+    fn members(members: &[(u32, u32)]) -> Vec<PushConstantMember> {
+        members
+            .iter()
+            .map(|&(offset, size)| PushConstantMember { offset, size })
+            .collect()
+    }
+
+    #[test]
+    fn identical_blocks() {
+        let merged = merge_push_constant_ranges(&[
+            (
+                range(vk::ShaderStageFlags::VERTEX, 0, 64),
+                members(&[(0, 64)]),
+            ),
+            (
+                range(vk::ShaderStageFlags::FRAGMENT, 0, 64),
+                members(&[(0, 64)]),
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            merged,
+            [range(
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                64
+            )]
+        );
+    }
+
+    #[test]
+    fn overlapping_blocks() {
+        let merged = merge_push_constant_ranges(&[
+            (
+                range(vk::ShaderStageFlags::VERTEX, 0, 80),
+                members(&[(0, 64), (64, 16)]),
+            ),
+            (
+                range(vk::ShaderStageFlags::FRAGMENT, 64, 16),
+                members(&[(64, 16)]),
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            merged,
+            [range(
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                80
+            )]
+        );
+    }
+
+    #[test]
+    fn disjoint_blocks() {
+        let merged = merge_push_constant_ranges(&[
+            (
+                range(vk::ShaderStageFlags::FRAGMENT, 64, 16),
+                members(&[(64, 16)]),
+            ),
+            (
+                range(vk::ShaderStageFlags::VERTEX, 0, 64),
+                members(&[(0, 64)]),
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            merged,
+            [
+                range(vk::ShaderStageFlags::VERTEX, 0, 64),
+                range(vk::ShaderStageFlags::FRAGMENT, 64, 16),
+            ]
+        );
+    }
+
+    #[test]
+    fn mismatched_members() {
+        // A `mat4` in one stage and a `vec4` in the other.
+        let result = merge_push_constant_ranges(&[
+            (
+                range(vk::ShaderStageFlags::VERTEX, 0, 64),
+                members(&[(0, 64)]),
+            ),
+            (
+                range(vk::ShaderStageFlags::FRAGMENT, 0, 16),
+                members(&[(0, 16)]),
+            ),
+        ]);
+
+        assert!(matches!(
+            result,
+            Err(PushConstantError::IncompatibleLayout { stages, offset: 0 })
+                if stages == vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        ));
+    }
+
+    #[test]
+    fn members_outside_the_overlap_are_ignored() {
+        let vertex = range(vk::ShaderStageFlags::VERTEX, 0, 32);
+        let fragment = range(vk::ShaderStageFlags::FRAGMENT, 16, 32);
+
+        assert!(check_compatible(
+            (&vertex, &members(&[(0, 16), (16, 16)])),
+            (&fragment, &members(&[(16, 16), (32, 16)])),
+        )
+        .is_ok());
+        assert!(check_compatible(
+            (&vertex, &members(&[(0, 16), (16, 16)])),
+            (&fragment, &members(&[(16, 8), (24, 24)])),
+        )
+        .is_err());
+    }
+}
@@ -1,14 +1,56 @@
 use std::{borrow::Cow, path::Path, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ash::vk;
 use once_cell::sync::Lazy;
 use regex::Regex;
 use tort_asset::{AssetLoader, AssetPath, BoxedFuture, Handle, LoadContext, LoadedAsset};
 use tort_reflect::{self as bevy_reflect, TypeUuid};
 
+use crate::backend::{resource::pipeline::SpecializationValue, utils::BackendError};
+
 static INCLUDE_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new("^\\s*#include\\s+\"(.+)\"\\s*$").unwrap());
+static STAGE_PRAGMA_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new("^\\s*#pragma\\s+stage\\s*\\(\\s*(\\w+)\\s*(?:,\\s*(\\w+)\\s*)?\\)\\s*$").unwrap()
+});
+static WGSL_ENTRY_POINT_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new("@(vertex|fragment|compute)\\b[^{;]*?\\bfn\\s+(\\w+)").unwrap());
+
+/// The names of the stages in second-level extensions like `.frag.glsl` and in
+/// `#pragma stage(...)`, the same as glslang's.
+const STAGE_NAMES: &[(&str, vk::ShaderStageFlags)] = &[
+    ("vert", vk::ShaderStageFlags::VERTEX),
+    ("tesc", vk::ShaderStageFlags::TESSELLATION_CONTROL),
+    ("tese", vk::ShaderStageFlags::TESSELLATION_EVALUATION),
+    ("geom", vk::ShaderStageFlags::GEOMETRY),
+    ("frag", vk::ShaderStageFlags::FRAGMENT),
+    ("comp", vk::ShaderStageFlags::COMPUTE),
+    ("task", vk::ShaderStageFlags::TASK_EXT),
+    ("mesh", vk::ShaderStageFlags::MESH_EXT),
+    ("rgen", vk::ShaderStageFlags::RAYGEN_KHR),
+    ("rahit", vk::ShaderStageFlags::ANY_HIT_KHR),
+    ("rchit", vk::ShaderStageFlags::CLOSEST_HIT_KHR),
+    ("rmiss", vk::ShaderStageFlags::MISS_KHR),
+    ("rint", vk::ShaderStageFlags::INTERSECTION_KHR),
+    ("rcall", vk::ShaderStageFlags::CALLABLE_KHR),
+];
+
+#[inline]
+pub fn shader_stage_from_name(name: &str) -> Option<vk::ShaderStageFlags> {
+    STAGE_NAMES
+        .iter()
+        .find(|(stage_name, _)| *stage_name == name)
+        .map(|(_, stage)| *stage)
+}
+
+#[inline]
+pub fn shader_stage_name(stage: vk::ShaderStageFlags) -> Option<&'static str> {
+    STAGE_NAMES
+        .iter()
+        .find(|(_, s)| *s == stage)
+        .map(|(stage_name, _)| *stage_name)
+}
 
 fn parse_includes(parent_path: &Path, source: &str) -> Vec<AssetPath<'static>> {
     let mut includes = Vec::new();
@@ -27,11 +69,65 @@ fn parse_includes(parent_path: &Path, source: &str) -> Vec<AssetPath<'static>> {
     includes
 }
 
+/// Parses `#pragma stage(<stage>)` into a stage of the whole file and
+/// `#pragma stage(<stage>, <entry point>)` into entry points.
+fn parse_stage_pragmas(source: &str) -> (Option<vk::ShaderStageFlags>, Vec<ShaderEntryPoint>) {
+    let mut stage = None;
+    let mut entry_points = Vec::new();
+
+    for line in source.lines() {
+        if let Some(captures) = STAGE_PRAGMA_REGEX.captures(line) {
+            let Some(pragma_stage) = shader_stage_from_name(&captures[1]) else {
+                continue
+            };
+
+            match captures.get(2) {
+                Some(name) => {
+                    entry_points.push(ShaderEntryPoint {
+                        name: Cow::Owned(name.as_str().to_owned()),
+                        stage: pragma_stage,
+                    })
+                }
+                None => stage = Some(pragma_stage),
+            }
+        }
+    }
+
+    (stage, entry_points)
+}
+
+fn parse_wgsl_entry_points(source: &str) -> Vec<ShaderEntryPoint> {
+    WGSL_ENTRY_POINT_REGEX
+        .captures_iter(source)
+        .map(|captures| {
+            ShaderEntryPoint {
+                name: Cow::Owned(captures[2].to_owned()),
+                stage: match &captures[1] {
+                    "vertex" => vk::ShaderStageFlags::VERTEX,
+                    "fragment" => vk::ShaderStageFlags::FRAGMENT,
+                    _ => vk::ShaderStageFlags::COMPUTE,
+                },
+            }
+        })
+        .collect()
+}
+
+/// An entry point of a file with several stages, loaded as the labeled asset
+/// `path#<name>`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderEntryPoint {
+    pub name: Cow<'static, str>,
+    pub stage: vk::ShaderStageFlags,
+}
+
 #[derive(Debug)]
 struct Inner {
     source: ShaderSource,
     path: AssetPath<'static>,
     includes: Vec<AssetPath<'static>>,
+    stage: Option<vk::ShaderStageFlags>,
+    entry_point: Cow<'static, str>,
+    entry_points: Vec<ShaderEntryPoint>,
 }
 
 #[derive(Clone, Debug, TypeUuid)]
@@ -39,16 +135,51 @@ struct Inner {
 pub struct Shader(Arc<Inner>);
 
 impl Shader {
+    /// The stage is taken from `#pragma stage(...)`, a second-level extension like
+    /// `.frag.glsl` or the only entry point, in that order.
+    fn new(
+        path: AssetPath<'static>,
+        source: ShaderSource,
+        includes: Vec<AssetPath<'static>>,
+        pragma_stage: Option<vk::ShaderStageFlags>,
+        entry_points: Vec<ShaderEntryPoint>,
+    ) -> Self {
+        let extension_stage = path
+            .path()
+            .file_stem()
+            .map(Path::new)
+            .and_then(Path::extension)
+            .and_then(|extension| extension.to_str())
+            .and_then(shader_stage_from_name);
+
+        let (stage, entry_point) = match (pragma_stage.or(extension_stage), &entry_points[..]) {
+            (Some(stage), _) => (Some(stage), Cow::Borrowed("main")),
+            (None, [entry_point]) => (Some(entry_point.stage), entry_point.name.clone()),
+            (None, _) => (None, Cow::Borrowed("main")),
+        };
+
+        Self(Arc::new(Inner {
+            source,
+            path,
+            includes,
+            stage,
+            entry_point,
+            entry_points,
+        }))
+    }
+
     #[inline]
     pub fn from_spirv(
         path: impl Into<AssetPath<'static>>,
         source: impl Into<Cow<'static, [u32]>>,
     ) -> Self {
-        Self(Arc::new(Inner {
-            source: ShaderSource::SpirV(source.into()),
-            path: path.into(),
-            includes: Vec::new(),
-        }))
+        Self::new(
+            path.into(),
+            ShaderSource::SpirV(source.into()),
+            Vec::new(),
+            None,
+            Vec::new(),
+        )
     }
 
     #[inline]
@@ -59,11 +190,65 @@ impl Shader {
         let source = source.into();
         let path = path.into();
         let includes = parse_includes(path.path().parent().unwrap(), &source);
+        let (stage, entry_points) = parse_stage_pragmas(&source);
 
-        Self(Arc::new(Inner {
-            source: ShaderSource::Glsl(source),
+        Self::new(
             path,
+            ShaderSource::Glsl(source),
             includes,
+            stage,
+            entry_points,
+        )
+    }
+
+    #[inline]
+    pub fn from_hlsl(
+        path: impl Into<AssetPath<'static>>,
+        source: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        let source = source.into();
+        let path = path.into();
+        let includes = parse_includes(path.path().parent().unwrap(), &source);
+        let (stage, entry_points) = parse_stage_pragmas(&source);
+
+        Self::new(
+            path,
+            ShaderSource::Hlsl(source),
+            includes,
+            stage,
+            entry_points,
+        )
+    }
+
+    #[inline]
+    pub fn from_wgsl(
+        path: impl Into<AssetPath<'static>>,
+        source: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        let source = source.into();
+        let entry_points = parse_wgsl_entry_points(&source);
+
+        Self::new(
+            path.into(),
+            ShaderSource::Wgsl(source),
+            Vec::new(),
+            None,
+            entry_points,
+        )
+    }
+
+    /// The shader of a single entry point, with the path `path#<name>`.
+    pub fn with_entry_point(&self, entry_point: &ShaderEntryPoint) -> Self {
+        Self(Arc::new(Inner {
+            source: self.0.source.clone(),
+            path: AssetPath::new(
+                self.0.path.path().to_path_buf(),
+                Some(entry_point.name.to_string()),
+            ),
+            includes: self.0.includes.clone(),
+            stage: Some(entry_point.stage),
+            entry_point: entry_point.name.clone(),
+            entry_points: Vec::new(),
         }))
     }
 
@@ -76,12 +261,40 @@ impl Shader {
     pub fn path(&self) -> &AssetPath<'static> {
         &self.0.path
     }
+
+    /// The files included directly by the shader.
+    #[inline]
+    pub fn includes(&self) -> &[AssetPath<'static>] {
+        &self.0.includes
+    }
+
+    /// The stage used when [`ShaderStageDesc::stage`] is empty.
+    #[inline]
+    pub fn stage(&self) -> Option<vk::ShaderStageFlags> {
+        self.0.stage
+    }
+
+    /// The entry point used when [`ShaderStageDesc::entry_point`] is empty.
+    #[inline]
+    pub fn entry_point(&self) -> &Cow<'static, str> {
+        &self.0.entry_point
+    }
+
+    /// The entry points declared in the file, each loaded as a labeled asset.
+    #[inline]
+    pub fn entry_points(&self) -> &[ShaderEntryPoint] {
+        &self.0.entry_points
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum ShaderSource {
     SpirV(Cow<'static, [u32]>),
     Glsl(Cow<'static, str>),
+    /// Compiled by shaderc, the entry point is the one of the [`ShaderStageDesc`].
+    Hlsl(Cow<'static, str>),
+    /// Translated by naga, which doesn't support includes or defines.
+    Wgsl(Cow<'static, str>),
 }
 
 #[derive(Default)]
@@ -95,20 +308,34 @@ impl AssetLoader for ShaderLoader {
     ) -> BoxedFuture<'a, Result<()>> {
         Box::pin(async move {
             let path = tort_utils::normalize_path(load_context.path());
-            let ext = path.extension().unwrap().to_str().unwrap();
+            let ext = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or_default();
 
             let shader = match ext {
                 "glsl" => Shader::from_glsl(path.to_owned(), String::from_utf8(Vec::from(bytes))?),
+                "hlsl" => Shader::from_hlsl(path.to_owned(), String::from_utf8(Vec::from(bytes))?),
+                "wgsl" => Shader::from_wgsl(path.to_owned(), String::from_utf8(Vec::from(bytes))?),
                 "spv" => {
                     Shader::from_spirv(
                         path.to_owned(),
                         Vec::from(tort_utils::bytemuck::try_cast_slice(bytes)?),
                     )
                 }
-                _ => panic!("Unhandled extension: {ext}"),
+                _ => return Err(anyhow!("Unsupported shader extension: {}", path.display())),
             };
 
-            let includes = shader.0.includes.clone();
+            let includes = shader.includes().to_vec();
+
+            for entry_point in shader.entry_points() {
+                load_context.set_labeled_asset(
+                    &entry_point.name,
+                    LoadedAsset::new(shader.with_entry_point(entry_point))
+                        .with_dependencies(includes.clone()),
+                );
+            }
+
             load_context.set_default_asset(LoadedAsset::new(shader).with_dependencies(includes));
 
             Ok(())
@@ -117,7 +344,7 @@ impl AssetLoader for ShaderLoader {
 
     #[inline]
     fn extensions(&self) -> &[&str] {
-        &["spv", "glsl"]
+        &["spv", "glsl", "hlsl", "wgsl"]
     }
 }
 
@@ -135,6 +362,8 @@ pub struct SpecializationInfo {
     pub data: Vec<u8>,
 }
 
+/// An empty `stage` or `entry_point` is taken from the [`Shader`], which allows referencing the
+/// entry points of a file with several stages as `path#<entry point>`.
 #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct ShaderStageDesc {
     pub flags: vk::PipelineShaderStageCreateFlags,
@@ -143,6 +372,27 @@ pub struct ShaderStageDesc {
     pub entry_point: Cow<'static, str>,
     pub defines: Vec<(Cow<'static, str>, Option<Cow<'static, str>>)>,
     pub specialization_info: Option<SpecializationInfo>,
+    /// Specialization constants by their name in the shader, validated against the reflected
+    /// types and appended to `specialization_info`.
+    pub specialization_constants: Vec<(Cow<'static, str>, SpecializationValue)>,
+}
+
+impl ShaderStageDesc {
+    /// Returns the desc with an empty `stage` and `entry_point` taken from `shader`.
+    pub(crate) fn resolve(&self, shader: &Shader) -> Result<Self, BackendError> {
+        let mut desc = self.clone();
+
+        if desc.stage.is_empty() {
+            desc.stage = shader.stage().ok_or_else(|| {
+                BackendError::UnknownShaderStage(shader.path().path().display().to_string())
+            })?;
+        }
+        if desc.entry_point.is_empty() {
+            desc.entry_point = shader.entry_point().clone();
+        }
+
+        Ok(desc)
+    }
 }
 
 impl From<&ShaderStageDesc> for ShaderStageDesc {
@@ -4,6 +4,10 @@ use ash::vk;
 use rspirv_reflect::{DescriptorInfo, PushConstantInfo, Reflection};
 
 use crate::backend::{
+    resource::pipeline::{
+        reflect_push_constant_members, reflect_shader_interfaces, reflect_specialization_constants,
+        PushConstantMember, ShaderInterface, SpecializationConstant,
+    },
     utils::{debug_utils, BackendError},
     Device,
 };
@@ -19,6 +23,9 @@ pub struct ShaderModule {
     shader_module: vk::ShaderModule,
     descriptor_sets: BTreeMap<u32, BTreeMap<u32, DescriptorInfo>>,
     push_constant_info: Option<PushConstantInfo>,
+    push_constant_members: Vec<PushConstantMember>,
+    specialization_constants: Vec<SpecializationConstant>,
+    interfaces: Vec<ShaderInterface>,
     device: Device,
 }
 
@@ -38,11 +45,19 @@ impl ShaderModule {
         }
 
         let reflection = Reflection::new_from_spirv(tort_utils::slices::bytes_of(desc.code))?;
+        let push_constant_info = reflection.get_push_constant_range()?;
+        let push_constant_members = push_constant_info
+            .as_ref()
+            .map(|info| reflect_push_constant_members(desc.code, info.offset + info.size))
+            .unwrap_or_default();
 
         Ok(Self {
             shader_module,
             descriptor_sets: reflection.get_descriptor_sets()?,
-            push_constant_info: reflection.get_push_constant_range()?,
+            push_constant_info,
+            push_constant_members,
+            specialization_constants: reflect_specialization_constants(desc.code),
+            interfaces: reflect_shader_interfaces(desc.code),
             device,
         })
     }
@@ -56,6 +71,38 @@ impl ShaderModule {
     pub fn push_constant_info(&self) -> &Option<PushConstantInfo> {
         &self.push_constant_info
     }
+
+    #[inline]
+    pub fn push_constant_members(&self) -> &[PushConstantMember] {
+        &self.push_constant_members
+    }
+
+    #[inline]
+    pub fn specialization_constants(&self) -> &[SpecializationConstant] {
+        &self.specialization_constants
+    }
+
+    #[inline]
+    pub fn interfaces(&self) -> &[ShaderInterface] {
+        &self.interfaces
+    }
+
+    /// The interface of the entry point `name` for `stage`, or of the only entry point for
+    /// `stage` if no entry point has that name.
+    pub fn interface(&self, name: &str, stage: vk::ShaderStageFlags) -> Option<&ShaderInterface> {
+        let mut candidates = self
+            .interfaces
+            .iter()
+            .filter(|interface| interface.stage == stage);
+
+        candidates
+            .clone()
+            .find(|interface| interface.entry_point == name)
+            .or_else(|| {
+                let first = candidates.next();
+                candidates.next().is_none().then_some(first).flatten()
+            })
+    }
 }
 
 impl Deref for ShaderModule {
@@ -0,0 +1,492 @@
+use std::borrow::Cow;
+
+use thiserror::Error;
+use tort_utils::OrderedFloat;
+
+use crate::backend::resource::pipeline::{
+    spirv::{
+        self, DECORATION_SPEC_ID, OP_DECORATE, OP_NAME, OP_SPEC_CONSTANT, OP_SPEC_CONSTANT_FALSE,
+        OP_SPEC_CONSTANT_TRUE, OP_TYPE_BOOL, OP_TYPE_FLOAT, OP_TYPE_INT,
+    },
+    SpecializationInfo, SpecializationMapEntry,
+};
+
+#[derive(Debug, Error)]
+pub enum SpecializationError {
+    #[error("The shader has no specialization constant named {name}")]
+    UnknownConstant { name: String },
+    #[error("Specialization constant {name} is a {expected:?}, but a {found:?} was given")]
+    TypeMismatch {
+        name: String,
+        expected: SpecializationConstantType,
+        found: SpecializationConstantType,
+    },
+    #[error("Specialization constant {constant_id} is specialized more than once")]
+    DuplicateConstant { constant_id: u32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SpecializationConstantType {
+    Bool,
+    I32,
+    U32,
+    I64,
+    U64,
+    F32,
+    F64,
+}
+
+impl SpecializationConstantType {
+    /// The size of the value in the specialization data, booleans are `VkBool32`s.
+    #[inline]
+    pub fn size(self) -> usize {
+        match self {
+            Self::Bool | Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::I64 | Self::U64 | Self::F64 => 8,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SpecializationValue {
+    Bool(bool),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    F32(OrderedFloat<f32>),
+    F64(OrderedFloat<f64>),
+}
+
+impl SpecializationValue {
+    #[inline]
+    pub fn ty(&self) -> SpecializationConstantType {
+        match self {
+            Self::Bool(_) => SpecializationConstantType::Bool,
+            Self::I32(_) => SpecializationConstantType::I32,
+            Self::U32(_) => SpecializationConstantType::U32,
+            Self::I64(_) => SpecializationConstantType::I64,
+            Self::U64(_) => SpecializationConstantType::U64,
+            Self::F32(_) => SpecializationConstantType::F32,
+            Self::F64(_) => SpecializationConstantType::F64,
+        }
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        match *self {
+            Self::Bool(value) => data.extend_from_slice(&(value as u32).to_ne_bytes()),
+            Self::I32(value) => data.extend_from_slice(&value.to_ne_bytes()),
+            Self::U32(value) => data.extend_from_slice(&value.to_ne_bytes()),
+            Self::I64(value) => data.extend_from_slice(&value.to_ne_bytes()),
+            Self::U64(value) => data.extend_from_slice(&value.to_ne_bytes()),
+            Self::F32(value) => data.extend_from_slice(&value.0.to_ne_bytes()),
+            Self::F64(value) => data.extend_from_slice(&value.0.to_ne_bytes()),
+        }
+    }
+}
+
+impl From<bool> for SpecializationValue {
+    #[inline]
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i32> for SpecializationValue {
+    #[inline]
+    fn from(value: i32) -> Self {
+        Self::I32(value)
+    }
+}
+
+impl From<u32> for SpecializationValue {
+    #[inline]
+    fn from(value: u32) -> Self {
+        Self::U32(value)
+    }
+}
+
+impl From<i64> for SpecializationValue {
+    #[inline]
+    fn from(value: i64) -> Self {
+        Self::I64(value)
+    }
+}
+
+impl From<u64> for SpecializationValue {
+    #[inline]
+    fn from(value: u64) -> Self {
+        Self::U64(value)
+    }
+}
+
+impl From<f32> for SpecializationValue {
+    #[inline]
+    fn from(value: f32) -> Self {
+        Self::F32(OrderedFloat(value))
+    }
+}
+
+impl From<f64> for SpecializationValue {
+    #[inline]
+    fn from(value: f64) -> Self {
+        Self::F64(OrderedFloat(value))
+    }
+}
+
+/// A specialization constant reflected from a shader module, e.g.
+/// `layout(constant_id = 0) const bool USE_SHADOWS = true;`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SpecializationConstant {
+    pub constant_id: u32,
+    /// Unnamed if the module was stripped of debug names, like the workgroup size.
+    pub name: Option<String>,
+    pub default: SpecializationValue,
+}
+
+impl SpecializationConstant {
+    #[inline]
+    pub fn ty(&self) -> SpecializationConstantType {
+        self.default.ty()
+    }
+}
+
+/// Finds the scalar specialization constants of a SPIR-V module.
+pub(crate) fn reflect_specialization_constants(code: &[u32]) -> Vec<SpecializationConstant> {
+    let mut names = Vec::new();
+    let mut spec_ids = Vec::new();
+    let mut types = Vec::new();
+    let mut constants = Vec::new();
+
+    for (opcode, operands) in spirv::instructions(code) {
+        match (opcode, operands) {
+            (OP_NAME, [target, name @ ..]) => names.push((*target, spirv::decode_string(name))),
+            (OP_DECORATE, [target, DECORATION_SPEC_ID, spec_id]) => {
+                spec_ids.push((*target, *spec_id))
+            }
+            (OP_TYPE_BOOL, [result]) => types.push((*result, SpecializationConstantType::Bool)),
+            (OP_TYPE_INT, [result, 32, 0]) => {
+                types.push((*result, SpecializationConstantType::U32))
+            }
+            (OP_TYPE_INT, [result, 32, 1]) => {
+                types.push((*result, SpecializationConstantType::I32))
+            }
+            (OP_TYPE_INT, [result, 64, 0]) => {
+                types.push((*result, SpecializationConstantType::U64))
+            }
+            (OP_TYPE_INT, [result, 64, 1]) => {
+                types.push((*result, SpecializationConstantType::I64))
+            }
+            (OP_TYPE_FLOAT, [result, 32]) => types.push((*result, SpecializationConstantType::F32)),
+            (OP_TYPE_FLOAT, [result, 64]) => types.push((*result, SpecializationConstantType::F64)),
+            (OP_SPEC_CONSTANT_TRUE, [_, result]) => {
+                constants.push((*result, SpecializationValue::Bool(true)))
+            }
+            (OP_SPEC_CONSTANT_FALSE, [_, result]) => {
+                constants.push((*result, SpecializationValue::Bool(false)))
+            }
+            (OP_SPEC_CONSTANT, [result_type, result, value @ ..]) => {
+                let ty = types
+                    .iter()
+                    .find(|(id, _)| id == result_type)
+                    .map(|(_, ty)| *ty);
+
+                // 64 bit literals are stored low-order word first.
+                let value64 = || u64::from(value[0]) | (u64::from(value[1]) << 32);
+                let default = match (ty, value.len()) {
+                    (Some(SpecializationConstantType::I32), 1) => {
+                        Some(SpecializationValue::I32(value[0] as i32))
+                    }
+                    (Some(SpecializationConstantType::U32), 1) => {
+                        Some(SpecializationValue::U32(value[0]))
+                    }
+                    (Some(SpecializationConstantType::F32), 1) => {
+                        Some(SpecializationValue::F32(OrderedFloat(f32::from_bits(
+                            value[0],
+                        ))))
+                    }
+                    (Some(SpecializationConstantType::I64), 2) => {
+                        Some(SpecializationValue::I64(value64() as i64))
+                    }
+                    (Some(SpecializationConstantType::U64), 2) => {
+                        Some(SpecializationValue::U64(value64()))
+                    }
+                    (Some(SpecializationConstantType::F64), 2) => {
+                        Some(SpecializationValue::F64(OrderedFloat(f64::from_bits(
+                            value64(),
+                        ))))
+                    }
+                    // Other types can't be specialized with a single value.
+                    _ => None,
+                };
+
+                constants.extend(default.map(|default| (*result, default)));
+            }
+            _ => {}
+        }
+    }
+
+    constants
+        .into_iter()
+        .filter_map(|(result, default)| {
+            let constant_id = spec_ids
+                .iter()
+                .find(|(target, _)| *target == result)
+                .map(|(_, spec_id)| *spec_id)?;
+            let name = names
+                .iter()
+                .find(|(target, _)| *target == result)
+                .map(|(_, name)| name.clone());
+
+            Some(SpecializationConstant {
+                constant_id,
+                name,
+                default,
+            })
+        })
+        .collect()
+}
+
+/// Appends the named `values` to `info`, validated against the reflected `constants`.
+pub(crate) fn specialize(
+    constants: &[SpecializationConstant],
+    info: Option<&SpecializationInfo>,
+    values: &[(Cow<'static, str>, SpecializationValue)],
+) -> Result<Option<SpecializationInfo>, SpecializationError> {
+    if values.is_empty() {
+        return Ok(info.cloned())
+    }
+
+    let mut info = info.cloned().unwrap_or(SpecializationInfo {
+        map_entries: Vec::new(),
+        data: Vec::new(),
+    });
+
+    for (name, value) in values {
+        let constant = constants
+            .iter()
+            .find(|constant| constant.name.as_deref() == Some(name.as_ref()))
+            .ok_or_else(|| {
+                SpecializationError::UnknownConstant {
+                    name: name.to_string(),
+                }
+            })?;
+
+        if constant.ty() != value.ty() {
+            return Err(SpecializationError::TypeMismatch {
+                name: name.to_string(),
+                expected: constant.ty(),
+                found: value.ty(),
+            })
+        }
+
+        if info
+            .map_entries
+            .iter()
+            .any(|map_entry| map_entry.constant_id == constant.constant_id)
+        {
+            return Err(SpecializationError::DuplicateConstant {
+                constant_id: constant.constant_id,
+            })
+        }
+
+        info.map_entries.push(SpecializationMapEntry {
+            constant_id: constant.constant_id,
+            offset: info.data.len() as u32,
+            size: value.ty().size(),
+        });
+        value.write(&mut info.data);
+    }
+
+    Ok(Some(info))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use shaderc::{CompileOptions, Compiler, ShaderKind};
+
+    use crate::backend::resource::pipeline::{
+        specialization::{reflect_specialization_constants, specialize},
+        SpecializationConstant, SpecializationConstantType, SpecializationError,
+        SpecializationInfo, SpecializationMapEntry, SpecializationValue,
+    };
+
+    const SHADER: &str = r"
+        #version 450
+        #extension GL_ARB_gpu_shader_int64 : require
+        layout(local_size_x = 1) in;
+        layout(constant_id = 0) const bool USE_SHADOWS = true;
+        layout(constant_id = 1) const int OFFSET = -2;
+        layout(constant_id = 2) const uint COUNT = 3;
+        layout(constant_id = 3) const float SCALE = 0.5;
+        layout(constant_id = 4) const int64_t BIG_OFFSET = -5l;
+        layout(constant_id = 5) const uint64_t BIG_COUNT = 0x100000000ul;
+        layout(constant_id = 6) const double PRECISE_SCALE = 0.25lf;
+        layout(std430, binding = 0) buffer Output { float values[]; };
+        void main() {
+            values[0] = USE_SHADOWS ? float(OFFSET + int(COUNT)) * SCALE : 0.0;
+            values[1] = float(BIG_OFFSET + int64_t(BIG_COUNT)) * float(PRECISE_SCALE);
+        }
+    ";
+
+    fn constants() -> Vec<SpecializationConstant> {
+        let compiler = Compiler::new().unwrap();
+        let mut options = CompileOptions::new().unwrap();
+        options.set_generate_debug_info();
+        let artifact = compiler
+            .compile_into_spirv(
+                SHADER,
+                ShaderKind::Compute,
+                "test.comp",
+                "main",
+                Some(&options),
+            )
+            .unwrap();
+
+        let mut constants = reflect_specialization_constants(artifact.as_binary());
+        constants.sort_by_key(|constant| constant.constant_id);
+        constants
+    }
+
+    fn values(
+        values: &[(&'static str, SpecializationValue)],
+    ) -> Vec<(Cow<'static, str>, SpecializationValue)> {
+        values
+            .iter()
+            .map(|(name, value)| (Cow::Borrowed(*name), *value))
+            .collect()
+    }
+
+    #[test]
+    fn reflected_constants() {
+        let expected = [
+            ("USE_SHADOWS", SpecializationValue::from(true)),
+            ("OFFSET", (-2i32).into()),
+            ("COUNT", 3u32.into()),
+            ("SCALE", 0.5f32.into()),
+            ("BIG_OFFSET", (-5i64).into()),
+            ("BIG_COUNT", 0x1_0000_0000u64.into()),
+            ("PRECISE_SCALE", 0.25f64.into()),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(constant_id, (name, default))| {
+            SpecializationConstant {
+                constant_id: constant_id as u32,
+                name: Some(name.to_owned()),
+                default,
+            }
+        })
+        .collect::<Vec<_>>();
+
+        assert_eq!(constants(), expected);
+    }
+
+    #[test]
+    fn specialized_layout() {
+        let info = specialize(
+            &constants(),
+            None,
+            &values(&[
+                ("SCALE", 2.0f32.into()),
+                ("BIG_COUNT", 7u64.into()),
+                ("USE_SHADOWS", false.into()),
+            ]),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            info.map_entries,
+            [
+                SpecializationMapEntry {
+                    constant_id: 3,
+                    offset: 0,
+                    size: 4,
+                },
+                SpecializationMapEntry {
+                    constant_id: 5,
+                    offset: 4,
+                    size: 8,
+                },
+                SpecializationMapEntry {
+                    constant_id: 0,
+                    offset: 12,
+                    size: 4,
+                },
+            ]
+        );
+        assert_eq!(
+            info.data,
+            [
+                &2.0f32.to_ne_bytes()[..],
+                &7u64.to_ne_bytes(),
+                &0u32.to_ne_bytes(),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn appended_to_existing_info() {
+        let existing = SpecializationInfo {
+            map_entries: vec![SpecializationMapEntry {
+                constant_id: 1,
+                offset: 0,
+                size: 4,
+            }],
+            data: 1i32.to_ne_bytes().to_vec(),
+        };
+
+        let info = specialize(
+            &constants(),
+            Some(&existing),
+            &values(&[("PRECISE_SCALE", 1.5f64.into())]),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            info.map_entries[1],
+            SpecializationMapEntry {
+                constant_id: 6,
+                offset: 4,
+                size: 8,
+            }
+        );
+        assert_eq!(info.data.len(), 12);
+
+        assert_eq!(
+            specialize(&constants(), Some(&existing), &[]).unwrap(),
+            Some(existing)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let constants = constants();
+
+        assert!(matches!(
+            specialize(&constants, None, &values(&[("MISSING", true.into())])),
+            Err(SpecializationError::UnknownConstant { name }) if name == "MISSING"
+        ));
+        assert!(matches!(
+            specialize(&constants, None, &values(&[("SCALE", 1u32.into())])),
+            Err(SpecializationError::TypeMismatch {
+                expected: SpecializationConstantType::F32,
+                found: SpecializationConstantType::U32,
+                ..
+            })
+        ));
+        assert!(matches!(
+            specialize(
+                &constants,
+                None,
+                &values(&[("COUNT", 1u32.into()), ("COUNT", 2u32.into())])
+            ),
+            Err(SpecializationError::DuplicateConstant { constant_id: 2 })
+        ));
+    }
+}
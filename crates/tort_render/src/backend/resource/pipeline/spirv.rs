@@ -0,0 +1,68 @@
+/// The number of words of the module header.
+const HEADER_SIZE: usize = 5;
+
+pub(crate) const OP_NAME: u32 = 5;
+pub(crate) const OP_ENTRY_POINT: u32 = 15;
+pub(crate) const OP_EXECUTION_MODE: u32 = 16;
+pub(crate) const OP_TYPE_BOOL: u32 = 20;
+pub(crate) const OP_TYPE_INT: u32 = 21;
+pub(crate) const OP_TYPE_FLOAT: u32 = 22;
+pub(crate) const OP_TYPE_VECTOR: u32 = 23;
+pub(crate) const OP_TYPE_MATRIX: u32 = 24;
+pub(crate) const OP_TYPE_ARRAY: u32 = 28;
+pub(crate) const OP_TYPE_STRUCT: u32 = 30;
+pub(crate) const OP_TYPE_POINTER: u32 = 32;
+pub(crate) const OP_CONSTANT: u32 = 43;
+pub(crate) const OP_CONSTANT_COMPOSITE: u32 = 44;
+pub(crate) const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+pub(crate) const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+pub(crate) const OP_SPEC_CONSTANT: u32 = 50;
+pub(crate) const OP_SPEC_CONSTANT_COMPOSITE: u32 = 51;
+pub(crate) const OP_VARIABLE: u32 = 59;
+pub(crate) const OP_DECORATE: u32 = 71;
+pub(crate) const OP_MEMBER_DECORATE: u32 = 72;
+
+pub(crate) const STORAGE_CLASS_INPUT: u32 = 1;
+pub(crate) const STORAGE_CLASS_OUTPUT: u32 = 3;
+pub(crate) const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+
+pub(crate) const DECORATION_SPEC_ID: u32 = 1;
+pub(crate) const DECORATION_BUILT_IN: u32 = 11;
+pub(crate) const DECORATION_PATCH: u32 = 15;
+pub(crate) const DECORATION_LOCATION: u32 = 30;
+pub(crate) const DECORATION_COMPONENT: u32 = 31;
+pub(crate) const DECORATION_OFFSET: u32 = 35;
+
+pub(crate) const BUILT_IN_WORKGROUP_SIZE: u32 = 25;
+
+pub(crate) const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+pub(crate) const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+
+/// Iterates the `(opcode, operands)` of the instructions of a SPIR-V module, stopping at the
+/// first malformed instruction.
+pub(crate) fn instructions(code: &[u32]) -> impl Iterator<Item = (u32, &[u32])> {
+    let mut words = code.get(HEADER_SIZE..).unwrap_or_default();
+
+    std::iter::from_fn(move || {
+        let first = *words.first()?;
+        let word_count = (first >> 16) as usize;
+        if word_count == 0 || word_count > words.len() {
+            return None
+        }
+
+        let (instruction, rest) = words.split_at(word_count);
+        words = rest;
+
+        Some((first & 0xffff, &instruction[1..]))
+    })
+}
+
+/// Decodes a nul terminated literal string.
+pub(crate) fn decode_string(words: &[u32]) -> String {
+    let bytes = tort_utils::slices::bytes_of(words);
+    let len = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
@@ -0,0 +1,137 @@
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use ash::vk;
+use log::{debug, warn};
+use shaderc::{CompileOptions, OptimizationLevel, SourceLanguage, SpirvVersion};
+
+/// Bumped when the key or file layout changes or shaderc is updated, which invalidates existing
+/// entries.
+const SPIRV_CACHE_VERSION: u32 = 3;
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+static NEXT_TMP_FILE: AtomicU64 = AtomicU64::new(0);
+
+/// The shaderc options that affect the generated code besides the stage, entry point and
+/// defines. They are part of the cache key.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct ShadercOptions {
+    pub(crate) source_language: SourceLanguage,
+    pub(crate) target_spirv_version: SpirvVersion,
+    pub(crate) optimization_level: OptimizationLevel,
+    pub(crate) generate_debug_info: bool,
+}
+
+impl ShadercOptions {
+    pub(crate) fn apply(&self, compile_options: &mut CompileOptions) {
+        compile_options.set_source_language(self.source_language);
+        compile_options.set_target_spirv(self.target_spirv_version);
+        compile_options.set_optimization_level(self.optimization_level);
+        if self.generate_debug_info {
+            compile_options.set_generate_debug_info();
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct SpirvCacheKey(blake3::Hash);
+
+/// A content-addressed directory of compiled SPIR-V, so GLSL and HLSL shaders aren't
+/// recompiled by shaderc on every launch.
+///
+/// Entries are keyed by the preprocessed source, which contains the resolved includes, so a
+/// changed include results in a different key instead of a stale module.
+pub(crate) struct SpirvCache {
+    path: PathBuf,
+}
+
+impl SpirvCache {
+    #[inline]
+    pub(crate) fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// shaderc doesn't report its own version, the compiler is identified by the SPIR-V version it
+    /// reports and [`SPIRV_CACHE_VERSION`].
+    pub(crate) fn key(
+        options: &ShadercOptions,
+        stage: vk::ShaderStageFlags,
+        entry_point: &str,
+        defines: &[(Cow<'static, str>, Option<Cow<'static, str>>)],
+        preprocessed_source: &str,
+    ) -> SpirvCacheKey {
+        let (spirv_version, spirv_revision) = shaderc::get_spirv_version();
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&SPIRV_CACHE_VERSION.to_le_bytes());
+        hasher.update(&spirv_version.to_le_bytes());
+        hasher.update(&spirv_revision.to_le_bytes());
+        hasher.update(&(options.source_language as u32).to_le_bytes());
+        hasher.update(&(options.target_spirv_version as u32).to_le_bytes());
+        hasher.update(&(options.optimization_level as u32).to_le_bytes());
+        hasher.update(&[options.generate_debug_info as u8]);
+        hasher.update(&stage.as_raw().to_le_bytes());
+
+        // Length prefixes keep the concatenation unambiguous.
+        let mut update_str = |s: &str| {
+            hasher.update(&(s.len() as u64).to_le_bytes());
+            hasher.update(s.as_bytes());
+        };
+
+        update_str(entry_point);
+        for (name, value) in defines {
+            update_str(name);
+            update_str(value.as_deref().unwrap_or_default());
+        }
+        update_str(preprocessed_source);
+
+        SpirvCacheKey(hasher.finalize())
+    }
+
+    #[inline]
+    fn entry_path(&self, key: &SpirvCacheKey) -> PathBuf {
+        self.path.join(format!("{}.spv", key.0.to_hex()))
+    }
+
+    pub(crate) fn get(&self, key: &SpirvCacheKey) -> Option<Vec<u32>> {
+        let path = self.entry_path(key);
+        let bytes = fs::read(&path).ok()?;
+
+        let code = bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_ne_bytes(word.try_into().unwrap()))
+            .collect::<Vec<_>>();
+
+        if bytes.len() % 4 != 0 || code.first() != Some(&SPIRV_MAGIC) {
+            warn!("Ignoring invalid cached SPIR-V {}", path.display());
+            return None
+        }
+
+        debug!("Loaded cached SPIR-V {}", path.display());
+        Some(code)
+    }
+
+    pub(crate) fn insert(&self, key: &SpirvCacheKey, code: &[u32]) {
+        let path = self.entry_path(key);
+        // Write to a temporary file first so concurrent readers never see a partial module.
+        let tmp_path = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            NEXT_TMP_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = fs::create_dir_all(&self.path)
+            .and_then(|()| fs::write(&tmp_path, tort_utils::slices::bytes_of(code)))
+            .and_then(|()| fs::rename(&tmp_path, &path));
+
+        if let Err(e) = result {
+            warn!("Failed to write cached SPIR-V {}: {e}", path.display());
+        }
+    }
+}
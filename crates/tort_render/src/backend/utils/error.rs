@@ -4,6 +4,11 @@ use ash::vk;
 use rspirv_reflect::ReflectError;
 use thiserror::Error;
 
+use crate::backend::resource::{
+    descriptor::DescriptorError,
+    pipeline::{InterfaceError, PushConstantError, SpecializationError},
+};
+
 #[derive(Error, Debug)]
 pub enum BackendError {
     #[error("Null byte missing: {0}")]
@@ -14,4 +19,16 @@ pub enum BackendError {
     Reflection(#[from] ReflectError),
     #[error("Shaderc error: {0}")]
     Shaderc(#[from] shaderc::Error),
+    #[error("WGSL error: {0}")]
+    Wgsl(String),
+    #[error("Cannot infer the stage of {0}, set it in the stage desc or with #pragma stage(...)")]
+    UnknownShaderStage(String),
+    #[error("Descriptor error: {0}")]
+    Descriptor(#[from] DescriptorError),
+    #[error("Specialization error: {0}")]
+    Specialization(#[from] SpecializationError),
+    #[error("Push constant error: {0}")]
+    PushConstant(#[from] PushConstantError),
+    #[error("Shader interface error: {0}")]
+    Interface(#[from] InterfaceError),
 }
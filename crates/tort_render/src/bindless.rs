@@ -0,0 +1,407 @@
+use std::{borrow::Cow, collections::VecDeque, sync::Arc};
+
+use ash::vk;
+use tort_ecs::{
+    system::{Res, ResMut, Resource},
+    {self as bevy_ecs},
+};
+
+use crate::{
+    backend::{
+        resource::{
+            descriptor::{
+                DescriptorError, DescriptorPool, DescriptorPoolDesc, DescriptorSet,
+                DescriptorSetLayout, DescriptorSetLayoutBindingDesc, DescriptorSetLayoutDesc,
+            },
+            pipeline::{PipelineCache, PipelineLayoutModifier},
+        },
+        utils::BackendError,
+        Device,
+    },
+    renderer::FrameCtx,
+};
+
+/// The binding of the sampled images in the bindless set, e.g.
+/// `layout(set = 0, binding = 0) uniform texture2D textures[];`.
+pub const BINDLESS_SAMPLED_IMAGE_BINDING: u32 = 0;
+/// The binding of the storage buffers in the bindless set.
+pub const BINDLESS_STORAGE_BUFFER_BINDING: u32 = 1;
+/// The binding of the samplers in the bindless set.
+pub const BINDLESS_SAMPLER_BINDING: u32 = 2;
+
+/// The index of a sampled image in the bindless set, stable until it is removed.
+///
+/// Not `Clone`, so removing it consumes the only handle to the slot. Dropping it leaks the slot.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BindlessImage(u32);
+
+impl BindlessImage {
+    #[inline]
+    pub fn index(&self) -> u32 {
+        self.0
+    }
+}
+
+/// The index of a storage buffer in the bindless set, stable until it is removed.
+///
+/// Not `Clone`, so removing it consumes the only handle to the slot. Dropping it leaks the slot.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BindlessBuffer(u32);
+
+impl BindlessBuffer {
+    #[inline]
+    pub fn index(&self) -> u32 {
+        self.0
+    }
+}
+
+/// The index of a sampler in the bindless set, stable until it is removed.
+///
+/// Not `Clone`, so removing it consumes the only handle to the slot. Dropping it leaks the slot.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BindlessSampler(u32);
+
+impl BindlessSampler {
+    #[inline]
+    pub fn index(&self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BindlessTablesDesc {
+    pub max_sampled_images: u32,
+    pub max_storage_buffers: u32,
+    pub max_samplers: u32,
+}
+
+impl Default for BindlessTablesDesc {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_sampled_images: 16 * 1024,
+            max_storage_buffers: 16 * 1024,
+            max_samplers: 256,
+        }
+    }
+}
+
+/// Hands out the slots of a table. Freed slots are only reused once the frames that may still
+/// use them have completed.
+struct SlotAllocator {
+    capacity: u32,
+    next: u32,
+    free: Vec<u32>,
+    /// Freed slots and the index of the last frame that may use them.
+    retired: VecDeque<(usize, u32)>,
+}
+
+impl SlotAllocator {
+    fn new(capacity: u32) -> Self {
+        Self {
+            capacity,
+            next: 0,
+            free: Vec::new(),
+            retired: VecDeque::new(),
+        }
+    }
+
+    #[inline]
+    fn allocate(&mut self) -> Option<u32> {
+        self.free.pop().or_else(|| {
+            if self.next == self.capacity {
+                return None
+            }
+
+            self.next += 1;
+            Some(self.next - 1)
+        })
+    }
+
+    #[inline]
+    fn retire(&mut self, slot: u32, frame_index: usize) {
+        self.retired.push_back((frame_index, slot));
+    }
+
+    fn recycle(&mut self, completed_frame_index: usize) {
+        while let Some((frame_index, slot)) = self.retired.front().copied() {
+            if frame_index > completed_frame_index {
+                break
+            }
+
+            self.free.push(slot);
+            self.retired.pop_front();
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> u32 {
+        self.next - self.free.len() as u32 - self.retired.len() as u32
+    }
+}
+
+/// Global tables of sampled images, storage buffers and samplers indexed from shaders, so
+/// materials and meshlet buffers don't have to be bound per draw.
+///
+/// The tables live in a single update-after-bind set with the bindings
+/// [`BINDLESS_SAMPLED_IMAGE_BINDING`], [`BINDLESS_STORAGE_BUFFER_BINDING`] and
+/// [`BINDLESS_SAMPLER_BINDING`]. Pipelines using it replace the reflected layout of the set with
+/// [`BindlessTables::layout_modifier`], which makes their layout compatible with
+/// [`BindlessTables::descriptor_set`].
+///
+/// Descriptors are written when they are added. Removed slots are recycled by
+/// [`BindlessTables::recycle_system`] once the frames that may use them have completed.
+#[derive(Resource)]
+pub struct BindlessTables {
+    descriptor_set: DescriptorSet,
+    _descriptor_pool: DescriptorPool,
+    images: SlotAllocator,
+    buffers: SlotAllocator,
+    samplers: SlotAllocator,
+    frame_index: usize,
+    device: Device,
+}
+
+impl BindlessTables {
+    pub fn new(
+        device: Device,
+        pipeline_cache: &PipelineCache,
+        desc: &BindlessTablesDesc,
+    ) -> Result<Self, BackendError> {
+        let limits = &device.properties().properties_12;
+        let max_sampled_images = desc.max_sampled_images.min(
+            limits
+                .max_descriptor_set_update_after_bind_sampled_images
+                .min(limits.max_per_stage_descriptor_update_after_bind_sampled_images),
+        );
+        let max_storage_buffers = desc.max_storage_buffers.min(
+            limits
+                .max_descriptor_set_update_after_bind_storage_buffers
+                .min(limits.max_per_stage_descriptor_update_after_bind_storage_buffers),
+        );
+        let max_samplers = desc.max_samplers.min(
+            limits
+                .max_descriptor_set_update_after_bind_samplers
+                .min(limits.max_per_stage_descriptor_update_after_bind_samplers),
+        );
+
+        let binding_flags = vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING;
+
+        let layout = pipeline_cache.get_descriptor_set_layout(&DescriptorSetLayoutDesc {
+            label: Some(Cow::Borrowed("bindless_set_layout")),
+            flags: vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+            bindings: [
+                (
+                    BINDLESS_SAMPLED_IMAGE_BINDING,
+                    vk::DescriptorType::SAMPLED_IMAGE,
+                    max_sampled_images,
+                ),
+                (
+                    BINDLESS_STORAGE_BUFFER_BINDING,
+                    vk::DescriptorType::STORAGE_BUFFER,
+                    max_storage_buffers,
+                ),
+                (
+                    BINDLESS_SAMPLER_BINDING,
+                    vk::DescriptorType::SAMPLER,
+                    max_samplers,
+                ),
+            ]
+            .into_iter()
+            .map(|(binding, descriptor_type, descriptor_count)| {
+                DescriptorSetLayoutBindingDesc {
+                    binding,
+                    descriptor_type,
+                    descriptor_count,
+                    stage_flags: vk::ShaderStageFlags::ALL,
+                    immutable_samplers: Vec::new(),
+                }
+            })
+            .collect(),
+            binding_flags: vec![binding_flags; 3],
+        })?;
+
+        let descriptor_pool = DescriptorPool::new(
+            device.clone(),
+            &DescriptorPoolDesc {
+                label: Some(Cow::Borrowed("bindless_descriptor_pool")),
+                flags: vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+                max_sets: 1,
+                pool_sizes: vec![
+                    (vk::DescriptorType::SAMPLED_IMAGE, max_sampled_images),
+                    (vk::DescriptorType::STORAGE_BUFFER, max_storage_buffers),
+                    (vk::DescriptorType::SAMPLER, max_samplers),
+                ],
+            },
+        )?;
+
+        let descriptor_set = DescriptorSet::new(descriptor_pool.allocate(&layout)?, layout);
+
+        Ok(Self {
+            descriptor_set,
+            _descriptor_pool: descriptor_pool,
+            images: SlotAllocator::new(max_sampled_images),
+            buffers: SlotAllocator::new(max_storage_buffers),
+            samplers: SlotAllocator::new(max_samplers),
+            frame_index: 0,
+            device,
+        })
+    }
+
+    #[inline]
+    pub fn descriptor_set(&self) -> &DescriptorSet {
+        &self.descriptor_set
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &Arc<DescriptorSetLayout> {
+        self.descriptor_set.layout()
+    }
+
+    /// Replaces the reflected layout of `set` with the bindless layout.
+    #[inline]
+    pub fn layout_modifier(&self, set: u32) -> PipelineLayoutModifier {
+        PipelineLayoutModifier::SetLayout {
+            set,
+            layout: self.layout().desc().clone(),
+        }
+    }
+
+    #[inline]
+    pub fn num_images(&self) -> u32 {
+        self.images.len()
+    }
+
+    #[inline]
+    pub fn num_buffers(&self) -> u32 {
+        self.buffers.len()
+    }
+
+    #[inline]
+    pub fn num_samplers(&self) -> u32 {
+        self.samplers.len()
+    }
+
+    pub fn add_image(
+        &mut self,
+        image_view: vk::ImageView,
+        image_layout: vk::ImageLayout,
+    ) -> Result<BindlessImage, BackendError> {
+        let slot = self.images.allocate().ok_or(DescriptorError::TableFull {
+            descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+        })?;
+
+        self.descriptor_set
+            .writes()
+            .image(
+                BINDLESS_SAMPLED_IMAGE_BINDING,
+                slot,
+                image_view,
+                image_layout,
+            )
+            .update(&self.device)?;
+
+        Ok(BindlessImage(slot))
+    }
+
+    pub fn add_buffer(
+        &mut self,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> Result<BindlessBuffer, BackendError> {
+        let slot = self.buffers.allocate().ok_or(DescriptorError::TableFull {
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+        })?;
+
+        self.descriptor_set
+            .writes()
+            .buffer(BINDLESS_STORAGE_BUFFER_BINDING, slot, buffer, offset, range)
+            .update(&self.device)?;
+
+        Ok(BindlessBuffer(slot))
+    }
+
+    pub fn add_sampler(&mut self, sampler: vk::Sampler) -> Result<BindlessSampler, BackendError> {
+        let slot = self.samplers.allocate().ok_or(DescriptorError::TableFull {
+            descriptor_type: vk::DescriptorType::SAMPLER,
+        })?;
+
+        self.descriptor_set
+            .writes()
+            .sampler(BINDLESS_SAMPLER_BINDING, slot, sampler)
+            .update(&self.device)?;
+
+        Ok(BindlessSampler(slot))
+    }
+
+    /// The slot is reused once the current frame has completed, the image view has to stay
+    /// alive until then.
+    #[inline]
+    pub fn remove_image(&mut self, image: BindlessImage) {
+        self.images.retire(image.0, self.frame_index);
+    }
+
+    /// The slot is reused once the current frame has completed, the buffer has to stay alive
+    /// until then.
+    #[inline]
+    pub fn remove_buffer(&mut self, buffer: BindlessBuffer) {
+        self.buffers.retire(buffer.0, self.frame_index);
+    }
+
+    /// The slot is reused once the current frame has completed, the sampler has to stay alive
+    /// until then.
+    #[inline]
+    pub fn remove_sampler(&mut self, sampler: BindlessSampler) {
+        self.samplers.retire(sampler.0, self.frame_index);
+    }
+
+    /// Must run after the fence of the current frame was waited for, which
+    /// [`DescriptorAllocator::begin_frame_system`](crate::backend::resource::descriptor::DescriptorAllocator::begin_frame_system)
+    /// does.
+    pub fn recycle_system(frame_ctx: Res<FrameCtx>, mut tables: ResMut<BindlessTables>) {
+        tables.frame_index = frame_ctx.frame_index();
+
+        if let Some(completed_frame_index) = frame_ctx.device_completed_frame_index() {
+            tables.images.recycle(completed_frame_index);
+            tables.buffers.recycle(completed_frame_index);
+            tables.samplers.recycle(completed_frame_index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bindless::SlotAllocator;
+
+    #[test]
+    fn slot_allocator() {
+        let mut slots = SlotAllocator::new(2);
+
+        assert_eq!(slots.allocate(), Some(0));
+        assert_eq!(slots.allocate(), Some(1));
+        assert_eq!(slots.allocate(), None);
+        assert_eq!(slots.len(), 2);
+
+        // Frame 5 may still use the slot, so it isn't reused before it has completed.
+        slots.retire(0, 5);
+        assert_eq!(slots.len(), 1);
+        slots.recycle(4);
+        assert_eq!(slots.allocate(), None);
+
+        slots.recycle(5);
+        assert_eq!(slots.allocate(), Some(0));
+        assert_eq!(slots.allocate(), None);
+        assert_eq!(slots.len(), 2);
+
+        slots.retire(1, 6);
+        slots.retire(0, 7);
+        slots.recycle(7);
+        assert_eq!(slots.len(), 0);
+        assert_eq!(slots.allocate(), Some(0));
+        assert_eq!(slots.allocate(), Some(1));
+        assert_eq!(slots.allocate(), None);
+    }
+}
@@ -0,0 +1,92 @@
+use async_channel::{Receiver, Sender};
+use tort_app::{App, Plugin};
+use tort_diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
+use tort_ecs::{
+    schedule::IntoSystemConfig,
+    system::{Res, ResMut, Resource},
+    {self as bevy_ecs},
+};
+
+use crate::{
+    graph::{TransientMemoryStats, TransientResourcePool},
+    RenderApp, RenderSet,
+};
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+#[derive(Resource)]
+struct TransientMemoryStatsSender(Sender<TransientMemoryStats>);
+
+#[derive(Resource)]
+struct TransientMemoryStatsReceiver(Receiver<TransientMemoryStats>);
+
+/// Adds diagnostics for the memory used by the transient resources of the
+/// [`RenderGraph`](super::RenderGraph), including the memory saved by aliasing.
+///
+/// Must be added after the [`RenderPlugin`](crate::RenderPlugin).
+#[derive(Default)]
+pub struct TransientMemoryDiagnosticsPlugin;
+
+impl Plugin for TransientMemoryDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        // Only the latest stats are of interest, older ones are dropped if nobody receives them.
+        let (sender, receiver) = async_channel::bounded(1);
+
+        app.insert_resource(TransientMemoryStatsReceiver(receiver))
+            .add_startup_system(Self::setup_system)
+            .add_system(Self::diagnostic_system);
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .insert_resource(TransientMemoryStatsSender(sender))
+                .add_system(Self::send_stats_system.in_set(RenderSet::Cleanup));
+        }
+    }
+}
+
+impl TransientMemoryDiagnosticsPlugin {
+    pub const REQUESTED_MEMORY: DiagnosticId =
+        DiagnosticId::from_u128(144_718_356_274_811_362_049_274_306_112_553_012_733);
+    pub const ALLOCATED_MEMORY: DiagnosticId =
+        DiagnosticId::from_u128(302_916_448_021_573_905_118_772_430_657_104_288_391);
+    pub const SAVED_MEMORY: DiagnosticId =
+        DiagnosticId::from_u128(61_035_772_809_165_244_386_512_107_894_331_902_114);
+
+    fn setup_system(mut diagnostics: ResMut<Diagnostics>) {
+        diagnostics.add(
+            Diagnostic::new(Self::REQUESTED_MEMORY, "transient_memory_requested", 20)
+                .with_suffix(" MiB"),
+        );
+        diagnostics.add(
+            Diagnostic::new(Self::ALLOCATED_MEMORY, "transient_memory_allocated", 20)
+                .with_suffix(" MiB"),
+        );
+        diagnostics.add(
+            Diagnostic::new(Self::SAVED_MEMORY, "transient_memory_saved", 20).with_suffix(" MiB"),
+        );
+    }
+
+    fn send_stats_system(
+        transient_pool: Res<TransientResourcePool>,
+        sender: Res<TransientMemoryStatsSender>,
+    ) {
+        let _ = sender.0.try_send(transient_pool.stats());
+    }
+
+    fn diagnostic_system(
+        mut diagnostics: ResMut<Diagnostics>,
+        receiver: Res<TransientMemoryStatsReceiver>,
+    ) {
+        let Ok(stats) = receiver.0.try_recv() else {
+            return
+        };
+
+        diagnostics.add_measurement(Self::REQUESTED_MEMORY, || {
+            stats.requested_bytes as f64 / MIB
+        });
+        diagnostics.add_measurement(Self::ALLOCATED_MEMORY, || {
+            stats.allocated_bytes as f64 / MIB
+        });
+        diagnostics.add_measurement(Self::SAVED_MEMORY, || stats.saved_bytes() as f64 / MIB);
+    }
+}
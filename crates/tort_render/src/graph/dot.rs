@@ -0,0 +1,266 @@
+use std::fmt::{self, Write};
+
+use crate::graph::{Barrier, CompiledGraph, RenderGraph, ResourceHandle, ResourceState};
+
+#[inline]
+fn resource_node(resource: ResourceHandle) -> String {
+    match resource {
+        ResourceHandle::Image(image) => format!("image_{}", image.0),
+        ResourceHandle::Buffer(buffer) => format!("buffer_{}", buffer.0),
+    }
+}
+
+#[inline]
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Empty flags are formatted as an empty string by ash.
+#[inline]
+fn flags_label(flags: impl fmt::Debug, is_empty: bool) -> String {
+    if is_empty {
+        "NONE".to_owned()
+    } else {
+        format!("{:?}", flags)
+    }
+}
+
+fn state_label(state: &ResourceState, is_image: bool) -> String {
+    let stage = flags_label(state.stage, state.stage.is_empty());
+    let access = flags_label(state.access, state.access.is_empty());
+
+    if is_image {
+        format!("{}\\n{}\\n{:?}", stage, access, state.layout)
+    } else {
+        format!("{}\\n{}", stage, access)
+    }
+}
+
+fn barrier_label(barrier: &Barrier) -> String {
+    let is_image = matches!(barrier.resource, ResourceHandle::Image(_));
+
+    format!(
+        "barrier\\n{}\\n->\\n{}",
+        state_label(&barrier.src, is_image),
+        state_label(&barrier.dst, is_image)
+    )
+}
+
+impl RenderGraph {
+    /// Returns the graph in the Graphviz DOT format.
+    ///
+    /// Passes are boxes connected in execution order, culled passes are dashed. Resources are
+    /// ellipses, transient ones dashed, with edges from the passes writing them to the passes
+    /// reading them. Edges that need a barrier are red and labeled with it.
+    pub fn to_dot(&self, compiled: &CompiledGraph) -> String {
+        let mut dot = String::new();
+
+        writeln!(dot, "digraph render_graph {{").unwrap();
+        writeln!(dot, "    rankdir=LR;").unwrap();
+        writeln!(dot, "    node [fontname=\"monospace\"];").unwrap();
+        writeln!(dot, "    edge [fontname=\"monospace\", fontsize=10];").unwrap();
+
+        for (i, desc) in self.images.iter().enumerate() {
+            let transient = self.transient_images[i].is_some();
+            writeln!(
+                dot,
+                "    image_{} [label=\"{}\\n{}\", shape=ellipse{}];",
+                i,
+                escape(&desc.label),
+                if transient {
+                    "transient image"
+                } else {
+                    "imported image"
+                },
+                if transient { ", style=dashed" } else { "" }
+            )
+            .unwrap();
+        }
+
+        for (i, desc) in self.buffers.iter().enumerate() {
+            let transient = self.transient_buffers[i].is_some();
+            writeln!(
+                dot,
+                "    buffer_{} [label=\"{}\\n{}\", shape=ellipse{}];",
+                i,
+                escape(&desc.label),
+                if transient {
+                    "transient buffer"
+                } else {
+                    "imported buffer"
+                },
+                if transient { ", style=dashed" } else { "" }
+            )
+            .unwrap();
+        }
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            if compiled.culled_passes.contains(&i) {
+                writeln!(
+                    dot,
+                    "    pass_{} [label=\"{}\\nculled\", shape=box, style=dashed, color=gray];",
+                    i,
+                    escape(&pass.name)
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    dot,
+                    "    pass_{} [label=\"{}\", shape=box];",
+                    i,
+                    escape(&pass.name)
+                )
+                .unwrap();
+            }
+        }
+
+        // The execution order of the passes that weren't culled.
+        for order in compiled.passes.windows(2) {
+            writeln!(
+                dot,
+                "    pass_{} -> pass_{} [style=bold, color=blue];",
+                order[0].pass, order[1].pass
+            )
+            .unwrap();
+        }
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            let barriers = compiled
+                .passes
+                .iter()
+                .find(|compiled_pass| compiled_pass.pass == i)
+                .map(|compiled_pass| compiled_pass.barriers.as_slice())
+                .unwrap_or_default();
+
+            for access in &pass.accesses {
+                let resource = resource_node(access.resource);
+                let (from, to) = if access.write {
+                    (format!("pass_{}", i), resource)
+                } else {
+                    (resource, format!("pass_{}", i))
+                };
+                let kind = if access.write { "write" } else { "read" };
+
+                match barriers
+                    .iter()
+                    .find(|barrier| barrier.resource == access.resource)
+                {
+                    Some(barrier) => {
+                        writeln!(
+                            dot,
+                            "    {} -> {} [label=\"{}\\n{}\", color=red];",
+                            from,
+                            to,
+                            kind,
+                            barrier_label(barrier)
+                        )
+                        .unwrap()
+                    }
+                    None => writeln!(dot, "    {} -> {} [label=\"{}\"];", from, to, kind).unwrap(),
+                }
+            }
+        }
+
+        if !compiled.final_barriers.is_empty() {
+            writeln!(dot, "    end [label=\"end of graph\", shape=doublecircle];").unwrap();
+
+            for barrier in &compiled.final_barriers {
+                writeln!(
+                    dot,
+                    "    {} -> end [label=\"{}\", color=red];",
+                    resource_node(barrier.resource),
+                    barrier_label(barrier)
+                )
+                .unwrap();
+            }
+        }
+
+        writeln!(dot, "}}").unwrap();
+
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use ash::vk;
+
+    use crate::graph::{
+        BufferUsage, ImageUsage, ImportedBufferDesc, ImportedImageDesc, RenderGraph, ResourceState,
+        TransientImageDesc,
+    };
+
+    #[test]
+    fn to_dot() {
+        let mut graph = RenderGraph::default();
+
+        let color = graph.import_image(ImportedImageDesc {
+            label: Cow::Borrowed("color"),
+            image: vk::Image::null(),
+            image_view: vk::ImageView::null(),
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            initial_state: ResourceState::default(),
+            final_state: Some(ResourceState::new(
+                vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+                vk::AccessFlags2::NONE,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            )),
+        });
+        let depth = graph.create_image(TransientImageDesc {
+            label: Cow::Borrowed("depth"),
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            ..Default::default()
+        });
+        let unused = graph.import_buffer(ImportedBufferDesc {
+            label: Cow::Borrowed("unused"),
+            buffer: vk::Buffer::null(),
+            initial_state: ResourceState::default(),
+            final_state: None,
+        });
+        let scratch = graph.create_image(TransientImageDesc {
+            label: Cow::Borrowed("scratch"),
+            ..Default::default()
+        });
+
+        graph
+            .add_pass("geometry")
+            .write_image(color, ImageUsage::ColorAttachment)
+            .write_image(depth, ImageUsage::DepthAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("never_read")
+            .read_buffer(
+                unused,
+                BufferUsage::Uniform(vk::PipelineStageFlags2::FRAGMENT_SHADER),
+            )
+            .write_image(scratch, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+
+        let compiled = graph.compile();
+
+        assert_eq!(compiled.culled_passes, [1]);
+        assert_eq!(
+            graph.to_dot(&compiled),
+            r#"digraph render_graph {
+    rankdir=LR;
+    node [fontname="monospace"];
+    edge [fontname="monospace", fontsize=10];
+    image_0 [label="color\nimported image", shape=ellipse];
+    image_1 [label="depth\ntransient image", shape=ellipse, style=dashed];
+    image_2 [label="scratch\ntransient image", shape=ellipse, style=dashed];
+    buffer_0 [label="unused\nimported buffer", shape=ellipse];
+    pass_0 [label="geometry", shape=box];
+    pass_1 [label="never_read\nculled", shape=box, style=dashed, color=gray];
+    pass_0 -> image_0 [label="write\nbarrier\nNONE\nNONE\nUNDEFINED\n->\nCOLOR_ATTACHMENT_OUTPUT\nCOLOR_ATTACHMENT_READ | COLOR_ATTACHMENT_WRITE\nCOLOR_ATTACHMENT_OPTIMAL", color=red];
+    pass_0 -> image_1 [label="write\nbarrier\nALL_COMMANDS\nMEMORY_WRITE\nUNDEFINED\n->\nEARLY_FRAGMENT_TESTS | LATE_FRAGMENT_TESTS\nDEPTH_STENCIL_ATTACHMENT_READ | DEPTH_STENCIL_ATTACHMENT_WRITE\nDEPTH_ATTACHMENT_OPTIMAL", color=red];
+    buffer_0 -> pass_1 [label="read"];
+    pass_1 -> image_2 [label="write"];
+    end [label="end of graph", shape=doublecircle];
+    image_0 -> end [label="barrier\nCOLOR_ATTACHMENT_OUTPUT\nCOLOR_ATTACHMENT_WRITE\nCOLOR_ATTACHMENT_OPTIMAL\n->\nBOTTOM_OF_PIPE\nNONE\nPRESENT_SRC_KHR", color=red];
+}
+"#
+        );
+    }
+}
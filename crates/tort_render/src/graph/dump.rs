@@ -0,0 +1,103 @@
+use std::{fs, path::PathBuf};
+
+use log::{debug, info, warn};
+use tort_ecs::{
+    system::{Res, ResMut, Resource},
+    {self as bevy_ecs},
+};
+use tort_input::{keyboard::KeyCode, Input};
+
+use crate::{graph::RenderGraph, Extract};
+
+/// Dumps the [`RenderGraph`] as a Graphviz DOT file, see [`RenderGraph::to_dot`].
+///
+/// A single dump is written when [`RenderGraphDump::key`] is pressed or
+/// [`RenderGraphDump::request`] is called.
+#[derive(Clone, Debug, Resource)]
+pub struct RenderGraphDump {
+    pub path: PathBuf,
+    /// Dumps the graph of every frame, overwriting the file each time.
+    pub every_frame: bool,
+    /// Ignored if there is no `Input<KeyCode>` resource.
+    pub key: Option<KeyCode>,
+    requests: u64,
+}
+
+impl Default for RenderGraphDump {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("render_graph.dot"),
+            every_frame: false,
+            key: Some(KeyCode::F9),
+            requests: 0,
+        }
+    }
+}
+
+impl RenderGraphDump {
+    /// Dumps the graph of the next rendered frame.
+    #[inline]
+    pub fn request(&mut self) {
+        self.requests += 1;
+    }
+}
+
+#[derive(Default, Resource)]
+pub(crate) struct ExtractedRenderGraphDump {
+    path: PathBuf,
+    every_frame: bool,
+    requests: u64,
+    handled_requests: u64,
+}
+
+/// Apps without the `InputPlugin` can only request dumps with [`RenderGraphDump::request`].
+pub(crate) fn request_render_graph_dump_system(
+    keys: Option<Res<Input<KeyCode>>>,
+    mut dump: ResMut<RenderGraphDump>,
+) {
+    let (Some(keys), Some(key)) = (keys, dump.key) else {
+        return
+    };
+
+    if keys.just_pressed(key) {
+        dump.request();
+    }
+}
+
+pub(crate) fn extract_render_graph_dump_system(
+    mut extracted: ResMut<ExtractedRenderGraphDump>,
+    dump: Extract<Res<RenderGraphDump>>,
+) {
+    extracted.path.clone_from(&dump.path);
+    extracted.every_frame = dump.every_frame;
+    extracted.requests = dump.requests;
+}
+
+/// Writes the graph before it is executed, which clears it.
+pub(crate) fn dump_render_graph_system(
+    graph: Res<RenderGraph>,
+    mut dump: ResMut<ExtractedRenderGraphDump>,
+) {
+    let requested = dump.requests != dump.handled_requests;
+
+    if !requested && !dump.every_frame {
+        return
+    }
+
+    dump.handled_requests = dump.requests;
+
+    let dot = graph.to_dot(&graph.compile());
+
+    match fs::write(&dump.path, dot) {
+        Ok(()) if requested => info!("Render graph written to {}", dump.path.display()),
+        Ok(()) => debug!("Render graph written to {}", dump.path.display()),
+        Err(e) => {
+            warn!(
+                "Failed to write the render graph to {}: {}",
+                dump.path.display(),
+                e
+            )
+        }
+    }
+}
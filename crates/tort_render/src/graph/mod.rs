@@ -0,0 +1,1052 @@
+mod diagnostics;
+mod dot;
+mod dump;
+mod pass;
+mod transient;
+mod usage;
+
+use std::{borrow::Cow, mem};
+
+use ash::vk;
+pub use diagnostics::*;
+pub use dump::*;
+pub use pass::*;
+use tort_ecs::{self as bevy_ecs, system::Resource};
+pub use transient::*;
+pub use usage::*;
+
+use crate::backend::{
+    resource::{BufferDesc, ImageDesc},
+    utils::BackendError,
+    Device,
+};
+
+/// The state transient resources start in each frame. As they may alias the memory of resources
+/// used earlier, their first use waits for all previous work. The writes to the aliased memory
+/// are made available by a [`MemoryBarrier`] before the first use.
+const TRANSIENT_INITIAL_STATE: ResourceState = ResourceState::new(
+    vk::PipelineStageFlags2::ALL_COMMANDS,
+    vk::AccessFlags2::MEMORY_WRITE,
+    vk::ImageLayout::UNDEFINED,
+);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ImageHandle(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BufferHandle(usize);
+
+/// An image owned outside of the graph, like a swapchain image.
+#[derive(Clone, Debug)]
+pub struct ImportedImageDesc {
+    pub label: Cow<'static, str>,
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    pub aspect_mask: vk::ImageAspectFlags,
+    /// The state of the image before the graph executes. An `UNDEFINED` layout discards its
+    /// contents, the stage still orders the first use after previous work on the image.
+    pub initial_state: ResourceState,
+    /// The state the image is transitioned to after the graph executed.
+    /// `None` leaves it in the state of its last use.
+    pub final_state: Option<ResourceState>,
+}
+
+/// A buffer owned outside of the graph.
+#[derive(Clone, Debug)]
+pub struct ImportedBufferDesc {
+    pub label: Cow<'static, str>,
+    pub buffer: vk::Buffer,
+    pub initial_state: ResourceState,
+    pub final_state: Option<ResourceState>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Barrier {
+    pub resource: ResourceHandle,
+    pub src: ResourceState,
+    pub dst: ResourceState,
+}
+
+/// A global barrier, the barriers of a resource only cover the writes to the resource itself and
+/// not those to resources that used its memory before.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryBarrier {
+    pub src_stage: vk::PipelineStageFlags2,
+    pub src_access: vk::AccessFlags2,
+    pub dst_stage: vk::PipelineStageFlags2,
+    pub dst_access: vk::AccessFlags2,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CompiledPass {
+    pub pass: usize,
+    /// Recorded before the pass if it is the first use of transient resources that share memory
+    /// with other ones.
+    pub memory_barrier: Option<MemoryBarrier>,
+    /// The barriers recorded before the pass.
+    pub barriers: Vec<Barrier>,
+}
+
+/// The passes that will execute in order and the synchronization between them.
+#[derive(Clone, Debug, Default)]
+pub struct CompiledGraph {
+    pub passes: Vec<CompiledPass>,
+    pub culled_passes: Vec<usize>,
+    /// The barriers into the final states of the imported resources.
+    pub final_barriers: Vec<Barrier>,
+}
+
+/// The state of a resource while compiling the graph.
+#[derive(Copy, Clone, Debug)]
+struct TrackedState {
+    layout: vk::ImageLayout,
+    write_stage: vk::PipelineStageFlags2,
+    write_access: vk::AccessFlags2,
+    read_stage: vk::PipelineStageFlags2,
+    /// The stages the last write is already visible to.
+    visible_stage: vk::PipelineStageFlags2,
+}
+
+impl TrackedState {
+    fn new(initial_state: ResourceState) -> Self {
+        let write_access = initial_state.write_access();
+
+        Self {
+            layout: initial_state.layout,
+            write_stage: if write_access.is_empty() {
+                vk::PipelineStageFlags2::NONE
+            } else {
+                initial_state.stage
+            },
+            write_access,
+            read_stage: if write_access.is_empty() {
+                initial_state.stage
+            } else {
+                vk::PipelineStageFlags2::NONE
+            },
+            visible_stage: vk::PipelineStageFlags2::NONE,
+        }
+    }
+
+    /// Transitions into `dst`, returning the barrier if one is needed.
+    fn transition(
+        &mut self,
+        dst: ResourceState,
+        write: bool,
+        is_image: bool,
+    ) -> Option<ResourceState> {
+        let layout_change = is_image && self.layout != dst.layout;
+        let unsynchronized_read =
+            !self.write_access.is_empty() && !self.visible_stage.contains(dst.stage);
+
+        if !layout_change && !write && !unsynchronized_read {
+            self.read_stage |= dst.stage;
+            return None
+        }
+
+        let src = ResourceState::new(
+            self.write_stage | self.read_stage,
+            self.write_access,
+            self.layout,
+        );
+
+        if write || layout_change {
+            *self = Self {
+                layout: dst.layout,
+                write_stage: if write {
+                    dst.stage
+                } else {
+                    vk::PipelineStageFlags2::NONE
+                },
+                write_access: if write {
+                    dst.write_access()
+                } else {
+                    vk::AccessFlags2::NONE
+                },
+                read_stage: if write {
+                    vk::PipelineStageFlags2::NONE
+                } else {
+                    dst.stage
+                },
+                visible_stage: vk::PipelineStageFlags2::NONE,
+            };
+        } else {
+            self.read_stage |= dst.stage;
+            self.visible_stage |= dst.stage;
+        }
+
+        // Nothing happened to the resource yet that has to be waited for.
+        if src.stage.is_empty() && !layout_change {
+            return None
+        }
+
+        Some(src)
+    }
+}
+
+/// Combines the accesses of a pass to the same resource, so each resource gets a single barrier
+/// before the pass. An image used with different layouts in one pass is used in the `GENERAL`
+/// layout.
+fn merge_accesses(accesses: &[ResourceAccess]) -> Vec<ResourceAccess> {
+    let mut merged = Vec::<ResourceAccess>::with_capacity(accesses.len());
+
+    for access in accesses {
+        match merged
+            .iter_mut()
+            .find(|merged| merged.resource == access.resource)
+        {
+            Some(merged) => {
+                merged.state.stage |= access.state.stage;
+                merged.state.access |= access.state.access;
+                if merged.state.layout != access.state.layout {
+                    merged.state.layout = vk::ImageLayout::GENERAL;
+                }
+                merged.write |= access.write;
+            }
+            None => merged.push(*access),
+        }
+    }
+
+    merged
+}
+
+/// The passes of the current frame.
+///
+/// Systems in [`RenderSet::Render`](crate::RenderSet::Render) add passes, which are executed in
+/// the order they were added. Passes whose results are never used are culled, and the barriers
+/// and layout transitions between the passes are inserted automatically.
+///
+/// Transient resources created with [`RenderGraph::create_image`] and
+/// [`RenderGraph::create_buffer`] are allocated from the [`TransientResourcePool`] when the graph
+/// executes. Until then their handles are null.
+#[derive(Default, Resource)]
+pub struct RenderGraph {
+    pub(crate) images: Vec<ImportedImageDesc>,
+    pub(crate) buffers: Vec<ImportedBufferDesc>,
+    /// The descs of the transient resources, indexed like `images` and `buffers`.
+    transient_images: Vec<Option<TransientImageDesc>>,
+    transient_buffers: Vec<Option<TransientBufferDesc>>,
+    pub(crate) passes: Vec<Pass>,
+}
+
+impl RenderGraph {
+    #[inline]
+    pub fn import_image(&mut self, desc: ImportedImageDesc) -> ImageHandle {
+        self.images.push(desc);
+        self.transient_images.push(None);
+        ImageHandle(self.images.len() - 1)
+    }
+
+    #[inline]
+    pub fn import_buffer(&mut self, desc: ImportedBufferDesc) -> BufferHandle {
+        self.buffers.push(desc);
+        self.transient_buffers.push(None);
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    /// Creates an image that only lives while the graph executes. Its contents are undefined
+    /// before the first pass writes it.
+    pub fn create_image(&mut self, desc: TransientImageDesc) -> ImageHandle {
+        self.images.push(ImportedImageDesc {
+            label: desc.label.clone(),
+            image: vk::Image::null(),
+            image_view: vk::ImageView::null(),
+            aspect_mask: desc.aspect_mask,
+            initial_state: TRANSIENT_INITIAL_STATE,
+            final_state: None,
+        });
+        self.transient_images.push(Some(desc));
+        ImageHandle(self.images.len() - 1)
+    }
+
+    /// Creates a buffer that only lives while the graph executes. Its contents are undefined
+    /// before the first pass writes it.
+    pub fn create_buffer(&mut self, desc: TransientBufferDesc) -> BufferHandle {
+        self.buffers.push(ImportedBufferDesc {
+            label: desc.label.clone(),
+            buffer: vk::Buffer::null(),
+            initial_state: TRANSIENT_INITIAL_STATE,
+            final_state: None,
+        });
+        self.transient_buffers.push(Some(desc));
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    /// Adds a pass that executes after all passes added before it. The graph doesn't reorder
+    /// passes, a pass reading a resource depends on the last pass added before it that writes the
+    /// resource.
+    #[inline]
+    pub fn add_pass(&mut self, name: impl Into<Cow<'static, str>>) -> PassBuilder<'_> {
+        PassBuilder::new(self, name.into())
+    }
+
+    #[inline]
+    pub fn image_desc(&self, image: ImageHandle) -> &ImportedImageDesc {
+        &self.images[image.0]
+    }
+
+    #[inline]
+    pub fn buffer_desc(&self, buffer: BufferHandle) -> &ImportedBufferDesc {
+        &self.buffers[buffer.0]
+    }
+
+    #[inline]
+    pub fn transient_image_desc(&self, image: ImageHandle) -> Option<&TransientImageDesc> {
+        self.transient_images[image.0].as_ref()
+    }
+
+    #[inline]
+    pub fn transient_buffer_desc(&self, buffer: BufferHandle) -> Option<&TransientBufferDesc> {
+        self.transient_buffers[buffer.0].as_ref()
+    }
+
+    #[inline]
+    pub fn pass_name(&self, pass: usize) -> &str {
+        &self.passes[pass].name
+    }
+
+    #[inline]
+    pub fn pass_accesses(&self, pass: usize) -> &[ResourceAccess] {
+        &self.passes[pass].accesses
+    }
+
+    #[inline]
+    pub fn num_passes(&self) -> usize {
+        self.passes.len()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.buffers.clear();
+        self.transient_images.clear();
+        self.transient_buffers.clear();
+        self.passes.clear();
+    }
+
+    /// Imported resources outlive the graph and are therefore always considered read after it
+    /// executed, transient ones are not.
+    #[inline]
+    fn is_external(&self, resource: ResourceHandle) -> bool {
+        match resource {
+            ResourceHandle::Image(image) => self.transient_images[image.0].is_none(),
+            ResourceHandle::Buffer(buffer) => self.transient_buffers[buffer.0].is_none(),
+        }
+    }
+
+    fn initial_state(&self, resource: ResourceHandle) -> ResourceState {
+        match resource {
+            ResourceHandle::Image(image) => self.images[image.0].initial_state,
+            ResourceHandle::Buffer(buffer) => self.buffers[buffer.0].initial_state,
+        }
+    }
+
+    fn final_state(&self, resource: ResourceHandle) -> Option<ResourceState> {
+        match resource {
+            ResourceHandle::Image(image) => self.images[image.0].final_state,
+            ResourceHandle::Buffer(buffer) => self.buffers[buffer.0].final_state,
+        }
+    }
+
+    fn resources(&self) -> impl Iterator<Item = ResourceHandle> {
+        (0..self.images.len())
+            .map(|i| ResourceHandle::Image(ImageHandle(i)))
+            .chain((0..self.buffers.len()).map(|i| ResourceHandle::Buffer(BufferHandle(i))))
+    }
+
+    pub fn compile(&self) -> CompiledGraph {
+        // Walk backwards and keep the passes that write something that is read later on.
+        let mut live = vec![false; self.passes.len()];
+        let mut needed = Vec::new();
+
+        for (i, pass) in self.passes.iter().enumerate().rev() {
+            live[i] = pass.side_effects
+                || pass.accesses.iter().any(|access| {
+                    access.write
+                        && (self.is_external(access.resource) || needed.contains(&access.resource))
+                });
+
+            if !live[i] {
+                continue
+            }
+
+            for access in &pass.accesses {
+                if access.write {
+                    needed.retain(|resource| *resource != access.resource);
+                }
+            }
+
+            for access in pass.accesses.iter().filter(|access| !access.write) {
+                if !needed.contains(&access.resource) {
+                    needed.push(access.resource);
+                }
+            }
+        }
+
+        let mut states = self
+            .resources()
+            .map(|resource| (resource, TrackedState::new(self.initial_state(resource))))
+            .collect::<Vec<_>>();
+        let num_images = self.images.len();
+        let state_index = |resource: ResourceHandle| {
+            match resource {
+                ResourceHandle::Image(image) => image.0,
+                ResourceHandle::Buffer(buffer) => num_images + buffer.0,
+            }
+        };
+
+        let mut compiled = CompiledGraph::default();
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            if !live[i] {
+                compiled.culled_passes.push(i);
+                continue
+            }
+
+            let mut compiled_pass = CompiledPass {
+                pass: i,
+                memory_barrier: None,
+                barriers: Vec::new(),
+            };
+
+            for access in merge_accesses(&pass.accesses) {
+                let is_image = matches!(access.resource, ResourceHandle::Image(_));
+                let state = &mut states[state_index(access.resource)].1;
+
+                if let Some(src) = state.transition(access.state, access.write, is_image) {
+                    compiled_pass.barriers.push(Barrier {
+                        resource: access.resource,
+                        src,
+                        dst: access.state,
+                    });
+                }
+            }
+
+            compiled.passes.push(compiled_pass);
+        }
+
+        for (resource, state) in &mut states {
+            let Some(final_state) = self.final_state(*resource) else {
+                continue
+            };
+
+            let is_image = matches!(resource, ResourceHandle::Image(_));
+            let write = !final_state.write_access().is_empty();
+
+            if let Some(src) = state.transition(final_state, write, is_image) {
+                compiled.final_barriers.push(Barrier {
+                    resource: *resource,
+                    src,
+                    dst: final_state,
+                });
+            }
+        }
+
+        compiled
+    }
+
+    /// Adds a [`MemoryBarrier`] before the first use of each of the `aliased` resources.
+    fn insert_alias_barriers(&self, compiled: &mut CompiledGraph, aliased: &[ResourceHandle]) {
+        let mut pending = aliased.to_vec();
+
+        for compiled_pass in &mut compiled.passes {
+            if pending.is_empty() {
+                break
+            }
+
+            for access in merge_accesses(&self.passes[compiled_pass.pass].accesses) {
+                let Some(i) = pending
+                    .iter()
+                    .position(|resource| *resource == access.resource)
+                else {
+                    continue
+                };
+                pending.swap_remove(i);
+
+                let memory_barrier = compiled_pass.memory_barrier.get_or_insert(MemoryBarrier {
+                    src_stage: TRANSIENT_INITIAL_STATE.stage,
+                    src_access: TRANSIENT_INITIAL_STATE.access,
+                    dst_stage: vk::PipelineStageFlags2::NONE,
+                    dst_access: vk::AccessFlags2::NONE,
+                });
+                memory_barrier.dst_stage |= access.state.stage;
+                memory_barrier.dst_access |= access.state.access;
+            }
+        }
+    }
+
+    unsafe fn record_barriers(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        memory_barrier: Option<&MemoryBarrier>,
+        barriers: &[Barrier],
+    ) {
+        if memory_barrier.is_none() && barriers.is_empty() {
+            return
+        }
+
+        let memory_barriers = memory_barrier
+            .iter()
+            .map(|barrier| {
+                vk::MemoryBarrier2::default()
+                    .src_stage_mask(barrier.src_stage)
+                    .src_access_mask(barrier.src_access)
+                    .dst_stage_mask(barrier.dst_stage)
+                    .dst_access_mask(barrier.dst_access)
+            })
+            .collect::<Vec<_>>();
+
+        let mut image_barriers = Vec::new();
+        let mut buffer_barriers = Vec::new();
+
+        for barrier in barriers {
+            match barrier.resource {
+                ResourceHandle::Image(image) => {
+                    let desc = &self.images[image.0];
+
+                    image_barriers.push(
+                        vk::ImageMemoryBarrier2::default()
+                            .src_stage_mask(barrier.src.stage)
+                            .src_access_mask(barrier.src.access)
+                            .dst_stage_mask(barrier.dst.stage)
+                            .dst_access_mask(barrier.dst.access)
+                            .old_layout(barrier.src.layout)
+                            .new_layout(barrier.dst.layout)
+                            .image(desc.image)
+                            .subresource_range(
+                                vk::ImageSubresourceRange::default()
+                                    .aspect_mask(desc.aspect_mask)
+                                    .level_count(vk::REMAINING_MIP_LEVELS)
+                                    .layer_count(vk::REMAINING_ARRAY_LAYERS),
+                            ),
+                    );
+                }
+                ResourceHandle::Buffer(buffer) => {
+                    buffer_barriers.push(
+                        vk::BufferMemoryBarrier2::default()
+                            .src_stage_mask(barrier.src.stage)
+                            .src_access_mask(barrier.src.access)
+                            .dst_stage_mask(barrier.dst.stage)
+                            .dst_access_mask(barrier.dst.access)
+                            .buffer(self.buffers[buffer.0].buffer)
+                            .size(vk::WHOLE_SIZE),
+                    );
+                }
+            }
+        }
+
+        device.synchronization2_loader().cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo::default()
+                .memory_barriers(&memory_barriers)
+                .image_memory_barriers(&image_barriers)
+                .buffer_memory_barriers(&buffer_barriers),
+        );
+    }
+
+    /// Acquires the transient resources used by the passes in `compiled` from `transient_pool`.
+    /// Returns the resources that share memory with other ones.
+    fn acquire_transient_resources(
+        &mut self,
+        compiled: &CompiledGraph,
+        transient_pool: &mut TransientResourcePool,
+        frame_index: usize,
+    ) -> Result<Vec<ResourceHandle>, BackendError> {
+        // The executed passes using each resource and the usage flags they need.
+        let mut image_uses = vec![None; self.images.len()];
+        let mut buffer_uses = vec![None; self.buffers.len()];
+
+        for (position, compiled_pass) in compiled.passes.iter().enumerate() {
+            for access in &self.passes[compiled_pass.pass].accesses {
+                match access.resource {
+                    ResourceHandle::Image(image) => {
+                        let (_, last_pass, usage) = image_uses[image.0].get_or_insert((
+                            position,
+                            position,
+                            vk::ImageUsageFlags::empty(),
+                        ));
+                        *last_pass = position;
+                        *usage |= access.state.image_usage_flags();
+                    }
+                    ResourceHandle::Buffer(buffer) => {
+                        let (_, last_pass, usage) = buffer_uses[buffer.0].get_or_insert((
+                            position,
+                            position,
+                            vk::BufferUsageFlags::empty(),
+                        ));
+                        *last_pass = position;
+                        *usage |= access.state.buffer_usage_flags();
+                    }
+                }
+            }
+        }
+
+        let mut images = Vec::new();
+        let mut image_requests = Vec::new();
+
+        for (i, desc) in self.transient_images.iter().enumerate() {
+            let (Some(desc), Some((first_pass, last_pass, usage))) = (desc, image_uses[i]) else {
+                continue
+            };
+
+            images.push(i);
+            image_requests.push(TransientImageRequest {
+                desc: ImageDesc {
+                    label: Some(desc.label.clone()),
+                    image_type: if desc.extent.depth > 1 {
+                        vk::ImageType::TYPE_3D
+                    } else {
+                        vk::ImageType::TYPE_2D
+                    },
+                    format: desc.format,
+                    extent: desc.extent,
+                    mip_levels: desc.mip_levels,
+                    array_layers: desc.array_layers,
+                    samples: desc.samples,
+                    tiling: vk::ImageTiling::OPTIMAL,
+                    usage: desc.usage | usage,
+                    initial_layout: vk::ImageLayout::UNDEFINED,
+                    ..Default::default()
+                },
+                aspect_mask: desc.aspect_mask,
+                first_pass,
+                last_pass,
+            });
+        }
+
+        let mut buffers = Vec::new();
+        let mut buffer_requests = Vec::new();
+
+        for (i, desc) in self.transient_buffers.iter().enumerate() {
+            let (Some(desc), Some((first_pass, last_pass, usage))) = (desc, buffer_uses[i]) else {
+                continue
+            };
+
+            buffers.push(i);
+            buffer_requests.push(TransientBufferRequest {
+                desc: BufferDesc {
+                    label: Some(desc.label.clone()),
+                    size: desc.size,
+                    usage: desc.usage | usage,
+                    ..Default::default()
+                },
+                first_pass,
+                last_pass,
+            });
+        }
+
+        transient_pool.acquire(frame_index, image_requests, buffer_requests)?;
+
+        let mut aliased = Vec::new();
+
+        for (index, i) in images.into_iter().enumerate() {
+            let (image, image_view) = transient_pool.image(index);
+            self.images[i].image = **image;
+            self.images[i].image_view = **image_view;
+
+            if transient_pool.is_image_aliased(index) {
+                aliased.push(ResourceHandle::Image(ImageHandle(i)));
+            }
+        }
+
+        for (index, i) in buffers.into_iter().enumerate() {
+            self.buffers[i].buffer = **transient_pool.buffer(index);
+
+            if transient_pool.is_buffer_aliased(index) {
+                aliased.push(ResourceHandle::Buffer(BufferHandle(i)));
+            }
+        }
+
+        Ok(aliased)
+    }
+
+    /// Compiles and records the graph into `command_buffer`, then clears it for the next frame.
+    ///
+    /// The transient resources are acquired from `transient_pool` and must not be used by frames
+    /// in flight anymore once `frame_index` is `num_frames` ahead.
+    pub unsafe fn execute(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        transient_pool: &mut TransientResourcePool,
+        frame_index: usize,
+    ) -> Result<(), BackendError> {
+        let mut compiled = self.compile();
+        let aliased = self.acquire_transient_resources(&compiled, transient_pool, frame_index)?;
+        self.insert_alias_barriers(&mut compiled, &aliased);
+
+        let mut passes = mem::take(&mut self.passes);
+
+        {
+            let context = PassContext {
+                device,
+                command_buffer,
+                graph: self,
+            };
+
+            for compiled_pass in &compiled.passes {
+                self.record_barriers(
+                    device,
+                    command_buffer,
+                    compiled_pass.memory_barrier.as_ref(),
+                    &compiled_pass.barriers,
+                );
+
+                if let Some(execute) = passes[compiled_pass.pass].execute.take() {
+                    execute(&context);
+                }
+            }
+
+            self.record_barriers(device, command_buffer, None, &compiled.final_barriers);
+        }
+
+        // Keep the allocation of the passes for the next frame.
+        passes.clear();
+        self.passes = passes;
+        self.clear();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use ash::vk;
+
+    use crate::graph::{
+        Barrier, BufferHandle, BufferUsage, ImageHandle, ImageUsage, ImportedBufferDesc,
+        ImportedImageDesc, MemoryBarrier, RenderGraph, ResourceHandle, ResourceState,
+        TransientImageDesc,
+    };
+
+    fn import_image(graph: &mut RenderGraph, label: &'static str) -> ImageHandle {
+        graph.import_image(ImportedImageDesc {
+            label: Cow::Borrowed(label),
+            image: vk::Image::null(),
+            image_view: vk::ImageView::null(),
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            initial_state: ResourceState::default(),
+            final_state: None,
+        })
+    }
+
+    fn import_buffer(graph: &mut RenderGraph, label: &'static str) -> BufferHandle {
+        graph.import_buffer(ImportedBufferDesc {
+            label: Cow::Borrowed(label),
+            buffer: vk::Buffer::null(),
+            initial_state: ResourceState::default(),
+            final_state: None,
+        })
+    }
+
+    fn create_image(graph: &mut RenderGraph, label: &'static str) -> ImageHandle {
+        graph.create_image(TransientImageDesc {
+            label: Cow::Borrowed(label),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn read_after_write() {
+        let mut graph = RenderGraph::default();
+        let image = import_image(&mut graph, "image");
+
+        graph
+            .add_pass("write")
+            .write_image(
+                image,
+                ImageUsage::StorageWrite(vk::PipelineStageFlags2::COMPUTE_SHADER),
+            )
+            .execute(|_| {});
+        graph
+            .add_pass("read")
+            .read_image(
+                image,
+                ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER),
+            )
+            .side_effects()
+            .execute(|_| {});
+        graph
+            .add_pass("read_again")
+            .read_image(
+                image,
+                ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER),
+            )
+            .side_effects()
+            .execute(|_| {});
+
+        let compiled = graph.compile();
+        let resource = ResourceHandle::Image(image);
+
+        assert_eq!(
+            compiled.passes[0].barriers,
+            [Barrier {
+                resource,
+                src: ResourceState::default(),
+                dst: ImageUsage::StorageWrite(vk::PipelineStageFlags2::COMPUTE_SHADER).state(),
+            }]
+        );
+        assert_eq!(
+            compiled.passes[1].barriers,
+            [Barrier {
+                resource,
+                src: ResourceState::new(
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                    vk::ImageLayout::GENERAL,
+                ),
+                dst: ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER).state(),
+            }]
+        );
+        assert!(compiled.passes[2].barriers.is_empty());
+    }
+
+    #[test]
+    fn write_after_read() {
+        let mut graph = RenderGraph::default();
+        let buffer = import_buffer(&mut graph, "buffer");
+
+        graph
+            .add_pass("read")
+            .read_buffer(
+                buffer,
+                BufferUsage::StorageRead(vk::PipelineStageFlags2::COMPUTE_SHADER),
+            )
+            .side_effects()
+            .execute(|_| {});
+        graph
+            .add_pass("write")
+            .write_buffer(buffer, BufferUsage::TransferDst)
+            .execute(|_| {});
+
+        let compiled = graph.compile();
+
+        assert!(compiled.passes[0].barriers.is_empty());
+        // Only an execution dependency, there is nothing to make visible.
+        assert_eq!(
+            compiled.passes[1].barriers,
+            [Barrier {
+                resource: ResourceHandle::Buffer(buffer),
+                src: ResourceState::new(
+                    vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    vk::AccessFlags2::NONE,
+                    vk::ImageLayout::UNDEFINED,
+                ),
+                dst: BufferUsage::TransferDst.state(),
+            }]
+        );
+    }
+
+    #[test]
+    fn write_after_write() {
+        let mut graph = RenderGraph::default();
+        let buffer = import_buffer(&mut graph, "buffer");
+
+        graph
+            .add_pass("first")
+            .write_buffer(buffer, BufferUsage::TransferDst)
+            .execute(|_| {});
+        graph
+            .add_pass("second")
+            .write_buffer(
+                buffer,
+                BufferUsage::StorageWrite(vk::PipelineStageFlags2::COMPUTE_SHADER),
+            )
+            .execute(|_| {});
+
+        let compiled = graph.compile();
+
+        assert!(compiled.culled_passes.is_empty());
+        assert!(compiled.passes[0].barriers.is_empty());
+        assert_eq!(
+            compiled.passes[1].barriers,
+            [Barrier {
+                resource: ResourceHandle::Buffer(buffer),
+                src: ResourceState::new(
+                    vk::PipelineStageFlags2::COPY,
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                    vk::ImageLayout::UNDEFINED,
+                ),
+                dst: BufferUsage::StorageWrite(vk::PipelineStageFlags2::COMPUTE_SHADER).state(),
+            }]
+        );
+    }
+
+    #[test]
+    fn layout_change_between_reads() {
+        let mut graph = RenderGraph::default();
+        let image = import_image(&mut graph, "image");
+
+        graph
+            .add_pass("upload")
+            .write_image(image, ImageUsage::TransferDst)
+            .execute(|_| {});
+        graph
+            .add_pass("sample")
+            .read_image(
+                image,
+                ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER),
+            )
+            .side_effects()
+            .execute(|_| {});
+        graph
+            .add_pass("copy")
+            .read_image(image, ImageUsage::TransferSrc)
+            .side_effects()
+            .execute(|_| {});
+
+        let compiled = graph.compile();
+
+        // Reads don't need to be ordered against each other, the layout transition does.
+        assert_eq!(
+            compiled.passes[2].barriers,
+            [Barrier {
+                resource: ResourceHandle::Image(image),
+                src: ResourceState::new(
+                    vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                    vk::AccessFlags2::NONE,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ),
+                dst: ImageUsage::TransferSrc.state(),
+            }]
+        );
+    }
+
+    #[test]
+    fn merged_accesses() {
+        let mut graph = RenderGraph::default();
+        let image = import_image(&mut graph, "image");
+
+        graph
+            .add_pass("blit_mips")
+            .read_image(image, ImageUsage::TransferSrc)
+            .write_image(image, ImageUsage::TransferDst)
+            .execute(|_| {});
+
+        let compiled = graph.compile();
+
+        assert_eq!(
+            compiled.passes[0].barriers,
+            [Barrier {
+                resource: ResourceHandle::Image(image),
+                src: ResourceState::default(),
+                dst: ResourceState::new(
+                    vk::PipelineStageFlags2::COPY,
+                    vk::AccessFlags2::TRANSFER_READ | vk::AccessFlags2::TRANSFER_WRITE,
+                    vk::ImageLayout::GENERAL,
+                ),
+            }]
+        );
+    }
+
+    #[test]
+    fn culling() {
+        let mut graph = RenderGraph::default();
+        let output = import_image(&mut graph, "output");
+        let unused = create_image(&mut graph, "unused");
+        let used = create_image(&mut graph, "used");
+        let dead = create_image(&mut graph, "dead");
+        let dead_output = create_image(&mut graph, "dead_output");
+
+        graph
+            .add_pass("unused")
+            .write_image(unused, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("producer")
+            .write_image(used, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("consumer")
+            .read_image(
+                used,
+                ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER),
+            )
+            .write_image(output, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("dead_producer")
+            .write_image(dead, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("dead_consumer")
+            .read_image(
+                dead,
+                ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER),
+            )
+            .write_image(dead_output, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+
+        let compiled = graph.compile();
+
+        assert_eq!(compiled.culled_passes, [0, 3, 4]);
+        assert_eq!(
+            compiled
+                .passes
+                .iter()
+                .map(|compiled_pass| compiled_pass.pass)
+                .collect::<Vec<_>>(),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    fn alias_barriers() {
+        let mut graph = RenderGraph::default();
+        let output = import_image(&mut graph, "output");
+        let first = create_image(&mut graph, "first");
+        let second = create_image(&mut graph, "second");
+
+        graph
+            .add_pass("write_first")
+            .write_image(first, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("read_first")
+            .read_image(
+                first,
+                ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER),
+            )
+            .write_image(output, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("write_second")
+            .write_image(second, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("read_second")
+            .read_image(
+                second,
+                ImageUsage::Sampled(vk::PipelineStageFlags2::FRAGMENT_SHADER),
+            )
+            .write_image(output, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+
+        // `second` reuses the memory of `first`.
+        let mut compiled = graph.compile();
+        graph.insert_alias_barriers(&mut compiled, &[ResourceHandle::Image(second)]);
+
+        let color_attachment = ImageUsage::ColorAttachment.state();
+        assert_eq!(
+            compiled
+                .passes
+                .iter()
+                .map(|compiled_pass| compiled_pass.memory_barrier)
+                .collect::<Vec<_>>(),
+            [
+                None,
+                None,
+                Some(MemoryBarrier {
+                    src_stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+                    src_access: vk::AccessFlags2::MEMORY_WRITE,
+                    dst_stage: color_attachment.stage,
+                    dst_access: color_attachment.access,
+                }),
+                None,
+            ]
+        );
+    }
+}
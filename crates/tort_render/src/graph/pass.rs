@@ -0,0 +1,157 @@
+use std::{borrow::Cow, mem};
+
+use ash::vk;
+use tort_utils::{bytemuck::Pod, slices};
+
+use crate::{
+    backend::{
+        resource::pipeline::{PipelineLayout, PushConstantError},
+        Device,
+    },
+    graph::{BufferHandle, BufferUsage, ImageHandle, ImageUsage, RenderGraph, ResourceState},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceHandle {
+    Image(ImageHandle),
+    Buffer(BufferHandle),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceAccess {
+    pub resource: ResourceHandle,
+    pub state: ResourceState,
+    pub write: bool,
+}
+
+pub(crate) type ExecuteFn = Box<dyn FnOnce(&PassContext) + Send + Sync>;
+
+pub(crate) struct Pass {
+    pub(crate) name: Cow<'static, str>,
+    pub(crate) accesses: Vec<ResourceAccess>,
+    pub(crate) side_effects: bool,
+    pub(crate) execute: Option<ExecuteFn>,
+}
+
+/// Declares the resources a pass uses. The pass is added to the graph by [`PassBuilder::execute`].
+///
+/// Several uses of the same resource are combined, an image used with different layouts is in
+/// the `GENERAL` layout during the pass.
+pub struct PassBuilder<'a> {
+    graph: &'a mut RenderGraph,
+    pass: Pass,
+}
+
+impl<'a> PassBuilder<'a> {
+    #[inline]
+    pub(crate) fn new(graph: &'a mut RenderGraph, name: Cow<'static, str>) -> Self {
+        Self {
+            graph,
+            pass: Pass {
+                name,
+                accesses: Vec::new(),
+                side_effects: false,
+                execute: None,
+            },
+        }
+    }
+
+    #[inline]
+    fn access(mut self, resource: ResourceHandle, state: ResourceState, write: bool) -> Self {
+        self.pass.accesses.push(ResourceAccess {
+            resource,
+            state,
+            write,
+        });
+        self
+    }
+
+    #[inline]
+    pub fn read_image(self, image: ImageHandle, usage: ImageUsage) -> Self {
+        self.access(ResourceHandle::Image(image), usage.state(), false)
+    }
+
+    #[inline]
+    pub fn write_image(self, image: ImageHandle, usage: ImageUsage) -> Self {
+        self.access(ResourceHandle::Image(image), usage.state(), true)
+    }
+
+    #[inline]
+    pub fn read_buffer(self, buffer: BufferHandle, usage: BufferUsage) -> Self {
+        self.access(ResourceHandle::Buffer(buffer), usage.state(), false)
+    }
+
+    #[inline]
+    pub fn write_buffer(self, buffer: BufferHandle, usage: BufferUsage) -> Self {
+        self.access(ResourceHandle::Buffer(buffer), usage.state(), true)
+    }
+
+    /// Prevents the pass from being culled even if nothing reads what it writes.
+    #[inline]
+    pub fn side_effects(mut self) -> Self {
+        self.pass.side_effects = true;
+        self
+    }
+
+    #[inline]
+    pub fn execute(mut self, execute: impl FnOnce(&PassContext) + Send + Sync + 'static) {
+        self.pass.execute = Some(Box::new(execute));
+        self.graph.passes.push(self.pass);
+    }
+}
+
+/// What a pass records its commands with.
+pub struct PassContext<'a> {
+    pub device: &'a Device,
+    pub command_buffer: vk::CommandBuffer,
+    pub(crate) graph: &'a RenderGraph,
+}
+
+impl<'a> PassContext<'a> {
+    #[inline]
+    pub fn image(&self, image: ImageHandle) -> vk::Image {
+        self.graph.images[image.0].image
+    }
+
+    #[inline]
+    pub fn image_view(&self, image: ImageHandle) -> vk::ImageView {
+        self.graph.images[image.0].image_view
+    }
+
+    #[inline]
+    pub fn buffer(&self, buffer: BufferHandle) -> vk::Buffer {
+        self.graph.buffers[buffer.0].buffer
+    }
+
+    /// Pushes `value` as the push constants of `pipeline_layout`, with the stages of its merged
+    /// ranges. `T` must have the size of the reflected push constants.
+    pub fn push_constants<T: Pod>(
+        &self,
+        pipeline_layout: &PipelineLayout,
+        value: &T,
+    ) -> Result<(), PushConstantError> {
+        let size = pipeline_layout.push_constant_size();
+        if mem::size_of::<T>() != size as usize {
+            return Err(PushConstantError::SizeMismatch {
+                expected: size,
+                found: mem::size_of::<T>(),
+            })
+        }
+
+        let bytes = slices::bytes_of(std::slice::from_ref(value));
+        // The stages of different ranges may not overlap, so each range is pushed separately.
+        for range in pipeline_layout.push_constant_ranges() {
+            unsafe {
+                self.device.loader().cmd_push_constants(
+                    self.command_buffer,
+                    **pipeline_layout,
+                    range.stage_flags,
+                    range.offset,
+                    &bytes[range.offset as usize..(range.offset + range.size) as usize],
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,567 @@
+use std::{borrow::Cow, cmp::Reverse, sync::Arc};
+
+use ash::vk;
+use log::debug;
+use tort_ecs::{self as bevy_ecs, system::Resource};
+
+use crate::backend::{
+    resource::{
+        Buffer, BufferDesc, Image, ImageDesc, ImageView, ImageViewDesc, MemoryBlock,
+        MemoryBlockDesc,
+    },
+    utils::{BackendError, Extent3D},
+    Device,
+};
+
+/// An image created by the [`RenderGraph`](super::RenderGraph) that only lives while it executes.
+///
+/// The usage flags are inferred from the passes using the image.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TransientImageDesc {
+    pub label: Cow<'static, str>,
+    pub format: vk::Format,
+    pub extent: Extent3D,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub samples: vk::SampleCountFlags,
+    /// Added to the inferred usage flags.
+    pub usage: vk::ImageUsageFlags,
+    pub aspect_mask: vk::ImageAspectFlags,
+}
+
+impl Default for TransientImageDesc {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            label: Cow::Borrowed("transient_image"),
+            format: vk::Format::UNDEFINED,
+            extent: Extent3D::default(),
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            usage: vk::ImageUsageFlags::empty(),
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+        }
+    }
+}
+
+/// A buffer created by the [`RenderGraph`](super::RenderGraph) that only lives while it executes.
+///
+/// The usage flags are inferred from the passes using the buffer.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TransientBufferDesc {
+    pub label: Cow<'static, str>,
+    pub size: vk::DeviceSize,
+    /// Added to the inferred usage flags.
+    pub usage: vk::BufferUsageFlags,
+}
+
+impl Default for TransientBufferDesc {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            label: Cow::Borrowed("transient_buffer"),
+            size: 0,
+            usage: vk::BufferUsageFlags::empty(),
+        }
+    }
+}
+
+/// A transient image used from the `first_pass` to the `last_pass` executed pass.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct TransientImageRequest {
+    pub(crate) desc: ImageDesc,
+    pub(crate) aspect_mask: vk::ImageAspectFlags,
+    pub(crate) first_pass: usize,
+    pub(crate) last_pass: usize,
+}
+
+/// A transient buffer used from the `first_pass` to the `last_pass` executed pass.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct TransientBufferRequest {
+    pub(crate) desc: BufferDesc,
+    pub(crate) first_pass: usize,
+    pub(crate) last_pass: usize,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TransientMemoryStats {
+    pub num_images: usize,
+    pub num_buffers: usize,
+    pub num_blocks: usize,
+    /// The memory the resources would need if each had its own allocation.
+    pub requested_bytes: vk::DeviceSize,
+    pub allocated_bytes: vk::DeviceSize,
+}
+
+impl TransientMemoryStats {
+    #[inline]
+    pub fn saved_bytes(&self) -> vk::DeviceSize {
+        self.requested_bytes.saturating_sub(self.allocated_bytes)
+    }
+}
+
+/// The resources of one graph layout, placed into shared memory blocks.
+struct TransientResources {
+    image_requests: Vec<TransientImageRequest>,
+    buffer_requests: Vec<TransientBufferRequest>,
+    images: Vec<(Image, ImageView)>,
+    buffers: Vec<Buffer>,
+    /// Whether each image and buffer shares memory with another one.
+    image_aliased: Vec<bool>,
+    buffer_aliased: Vec<bool>,
+    stats: TransientMemoryStats,
+}
+
+/// A resource to be placed into a memory block.
+struct Placement {
+    requirements: vk::MemoryRequirements,
+    first_pass: usize,
+    last_pass: usize,
+}
+
+struct PlacedRange {
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    first_pass: usize,
+    last_pass: usize,
+}
+
+#[derive(Default)]
+struct PlannedBlock {
+    requirements: vk::MemoryRequirements,
+    ranges: Vec<PlacedRange>,
+}
+
+#[inline]
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+/// Places the resources into as few blocks as possible, letting resources whose lifetimes don't
+/// overlap share memory. Returns the block and offset of every resource and the blocks.
+fn plan_blocks(placements: &[Placement]) -> (Vec<(usize, vk::DeviceSize)>, Vec<PlannedBlock>) {
+    // Placing the largest resources first leaves the gaps between them to the smaller ones.
+    let mut order = (0..placements.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| Reverse(placements[*i].requirements.size));
+
+    let mut locations = vec![(0, 0); placements.len()];
+    let mut blocks: Vec<PlannedBlock> = Vec::new();
+
+    for i in order {
+        let placement = &placements[i];
+        let requirements = placement.requirements;
+
+        let overlaps = |range: &PlacedRange, offset: vk::DeviceSize| {
+            range.first_pass <= placement.last_pass
+                && placement.first_pass <= range.last_pass
+                && range.offset < offset + requirements.size
+                && offset < range.offset + range.size
+        };
+
+        let location = blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| {
+                block.requirements.memory_type_bits & requirements.memory_type_bits != 0
+            })
+            .find_map(|(block_index, block)| {
+                // The lowest offset that doesn't overlap a resource alive at the same time.
+                let mut candidates = block
+                    .ranges
+                    .iter()
+                    .map(|range| align_up(range.offset + range.size, requirements.alignment))
+                    .chain(Some(0))
+                    .collect::<Vec<_>>();
+                candidates.sort_unstable();
+
+                candidates
+                    .into_iter()
+                    .find(|offset| !block.ranges.iter().any(|range| overlaps(range, *offset)))
+                    .map(|offset| (block_index, offset))
+            });
+
+        let (block_index, offset) = location.unwrap_or_else(|| {
+            blocks.push(PlannedBlock {
+                requirements: vk::MemoryRequirements {
+                    size: 0,
+                    alignment: 1,
+                    memory_type_bits: requirements.memory_type_bits,
+                },
+                ranges: Vec::new(),
+            });
+            (blocks.len() - 1, 0)
+        });
+
+        let block = &mut blocks[block_index];
+        block.requirements.size = block.requirements.size.max(offset + requirements.size);
+        block.requirements.alignment = block.requirements.alignment.max(requirements.alignment);
+        block.requirements.memory_type_bits &= requirements.memory_type_bits;
+        block.ranges.push(PlacedRange {
+            offset,
+            size: requirements.size,
+            first_pass: placement.first_pass,
+            last_pass: placement.last_pass,
+        });
+
+        locations[i] = (block_index, offset);
+    }
+
+    (locations, blocks)
+}
+
+/// Returns whether each resource shares memory with another one, used before it in this frame or
+/// after it in the last one.
+fn aliased_placements(
+    placements: &[Placement],
+    locations: &[(usize, vk::DeviceSize)],
+) -> Vec<bool> {
+    let ranges = placements
+        .iter()
+        .zip(locations)
+        .map(|(placement, (block_index, offset))| {
+            (*block_index, *offset..offset + placement.requirements.size)
+        })
+        .collect::<Vec<_>>();
+
+    ranges
+        .iter()
+        .enumerate()
+        .map(|(i, (block_index, range))| {
+            ranges
+                .iter()
+                .enumerate()
+                .any(|(j, (other_block_index, other_range))| {
+                    i != j
+                        && block_index == other_block_index
+                        && range.start < other_range.end
+                        && other_range.start < range.end
+                })
+        })
+        .collect()
+}
+
+fn allocate_blocks(
+    device: &Device,
+    label: &'static str,
+    blocks: &[PlannedBlock],
+) -> Result<Vec<Arc<MemoryBlock>>, BackendError> {
+    blocks
+        .iter()
+        .map(|block| {
+            MemoryBlock::new(
+                device.clone(),
+                &MemoryBlockDesc {
+                    label: Some(Cow::Borrowed(label)),
+                    requirements: block.requirements,
+                    required_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    ..Default::default()
+                },
+            )
+            .map(Arc::new)
+        })
+        .collect()
+}
+
+impl TransientResources {
+    fn new(
+        device: &Device,
+        image_requests: Vec<TransientImageRequest>,
+        buffer_requests: Vec<TransientBufferRequest>,
+    ) -> Result<Self, BackendError> {
+        let image_placements = image_requests
+            .iter()
+            .map(|request| {
+                Ok(Placement {
+                    requirements: Image::memory_requirements(device, &request.desc)?,
+                    first_pass: request.first_pass,
+                    last_pass: request.last_pass,
+                })
+            })
+            .collect::<Result<Vec<_>, BackendError>>()?;
+        let buffer_placements = buffer_requests
+            .iter()
+            .map(|request| {
+                Ok(Placement {
+                    requirements: Buffer::memory_requirements(device, &request.desc)?,
+                    first_pass: request.first_pass,
+                    last_pass: request.last_pass,
+                })
+            })
+            .collect::<Result<Vec<_>, BackendError>>()?;
+
+        // Images and buffers are placed into separate blocks, so `bufferImageGranularity`
+        // never has to be considered.
+        let (image_locations, image_blocks) = plan_blocks(&image_placements);
+        let (buffer_locations, buffer_blocks) = plan_blocks(&buffer_placements);
+        let image_aliased = aliased_placements(&image_placements, &image_locations);
+        let buffer_aliased = aliased_placements(&buffer_placements, &buffer_locations);
+
+        let stats = TransientMemoryStats {
+            num_images: image_requests.len(),
+            num_buffers: buffer_requests.len(),
+            num_blocks: image_blocks.len() + buffer_blocks.len(),
+            requested_bytes: image_placements
+                .iter()
+                .chain(&buffer_placements)
+                .map(|placement| placement.requirements.size)
+                .sum(),
+            allocated_bytes: image_blocks
+                .iter()
+                .chain(&buffer_blocks)
+                .map(|block| block.requirements.size)
+                .sum(),
+        };
+
+        let image_blocks = allocate_blocks(device, "transient_image_memory", &image_blocks)?;
+        let buffer_blocks = allocate_blocks(device, "transient_buffer_memory", &buffer_blocks)?;
+
+        let images = image_requests
+            .iter()
+            .zip(image_locations)
+            .map(|(request, (block_index, offset))| {
+                let image = Image::new_aliased(
+                    device.clone(),
+                    &request.desc,
+                    image_blocks[block_index].clone(),
+                    offset,
+                )?;
+
+                let view_type = if request.desc.image_type == vk::ImageType::TYPE_3D {
+                    vk::ImageViewType::TYPE_3D
+                } else if request.desc.array_layers > 1 {
+                    vk::ImageViewType::TYPE_2D_ARRAY
+                } else {
+                    vk::ImageViewType::TYPE_2D
+                };
+
+                let image_view = ImageView::new(
+                    device.clone(),
+                    *image,
+                    &ImageViewDesc {
+                        label: request.desc.label.clone(),
+                        view_type,
+                        format: request.desc.format,
+                        aspect_mask: request.aspect_mask,
+                        level_count: request.desc.mip_levels,
+                        layer_count: request.desc.array_layers,
+                        ..Default::default()
+                    },
+                )?;
+
+                Ok((image, image_view))
+            })
+            .collect::<Result<Vec<_>, BackendError>>()?;
+
+        let buffers = buffer_requests
+            .iter()
+            .zip(buffer_locations)
+            .map(|(request, (block_index, offset))| {
+                Buffer::new_aliased(
+                    device.clone(),
+                    &request.desc,
+                    buffer_blocks[block_index].clone(),
+                    offset,
+                )
+            })
+            .collect::<Result<Vec<_>, BackendError>>()?;
+
+        Ok(Self {
+            image_requests,
+            buffer_requests,
+            images,
+            buffers,
+            image_aliased,
+            buffer_aliased,
+            stats,
+        })
+    }
+}
+
+/// Creates the transient resources of the [`RenderGraph`](super::RenderGraph).
+///
+/// Resources whose lifetimes within a frame don't overlap share memory. They are kept alive
+/// across frames as long as the graph requests the same resources with the same lifetimes, and
+/// only recreated when that changes.
+#[derive(Resource)]
+pub struct TransientResourcePool {
+    current: Option<TransientResources>,
+    /// Resources that may still be in use by frames in flight, with the frame they were last used.
+    retired: Vec<(usize, TransientResources)>,
+    num_frames: usize,
+    device: Device,
+}
+
+impl TransientResourcePool {
+    pub fn new(device: Device, num_frames: usize) -> Self {
+        Self {
+            current: None,
+            retired: Vec::new(),
+            num_frames,
+            device,
+        }
+    }
+
+    /// Returns the memory used by the resources of the last frame.
+    #[inline]
+    pub fn stats(&self) -> TransientMemoryStats {
+        self.current
+            .as_ref()
+            .map(|resources| resources.stats)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn acquire(
+        &mut self,
+        frame_index: usize,
+        image_requests: Vec<TransientImageRequest>,
+        buffer_requests: Vec<TransientBufferRequest>,
+    ) -> Result<(), BackendError> {
+        let num_frames = self.num_frames;
+        self.retired
+            .retain(|(last_used, _)| frame_index < last_used + num_frames);
+
+        if let Some(current) = &self.current {
+            if current.image_requests == image_requests
+                && current.buffer_requests == buffer_requests
+            {
+                return Ok(())
+            }
+        }
+
+        if let Some(current) = self.current.take() {
+            self.retired.push((frame_index.saturating_sub(1), current));
+        }
+
+        if image_requests.is_empty() && buffer_requests.is_empty() {
+            return Ok(())
+        }
+
+        let resources = TransientResources::new(&self.device, image_requests, buffer_requests)?;
+
+        let stats = resources.stats;
+        debug!(
+            "Allocated {} transient images and {} buffers in {} blocks, {} of {} bytes saved by aliasing",
+            stats.num_images,
+            stats.num_buffers,
+            stats.num_blocks,
+            stats.saved_bytes(),
+            stats.requested_bytes
+        );
+
+        self.current = Some(resources);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub(crate) fn image(&self, index: usize) -> &(Image, ImageView) {
+        &self.current.as_ref().unwrap().images[index]
+    }
+
+    #[inline]
+    pub(crate) fn buffer(&self, index: usize) -> &Buffer {
+        &self.current.as_ref().unwrap().buffers[index]
+    }
+
+    #[inline]
+    pub(crate) fn is_image_aliased(&self, index: usize) -> bool {
+        self.current.as_ref().unwrap().image_aliased[index]
+    }
+
+    #[inline]
+    pub(crate) fn is_buffer_aliased(&self, index: usize) -> bool {
+        self.current.as_ref().unwrap().buffer_aliased[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use crate::graph::transient::{aliased_placements, plan_blocks, Placement};
+
+    fn placement(
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+        first_pass: usize,
+        last_pass: usize,
+    ) -> Placement {
+        Placement {
+            requirements: vk::MemoryRequirements {
+                size,
+                alignment,
+                memory_type_bits: 0b11,
+            },
+            first_pass,
+            last_pass,
+        }
+    }
+
+    #[test]
+    fn disjoint_lifetimes_alias() {
+        let (locations, blocks) =
+            plan_blocks(&[placement(256, 16, 0, 1), placement(128, 16, 2, 3)]);
+
+        assert_eq!(locations, [(0, 0), (0, 0)]);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].requirements.size, 256);
+    }
+
+    #[test]
+    fn overlapping_lifetimes_dont_alias() {
+        let (locations, blocks) = plan_blocks(&[
+            placement(100, 16, 0, 1),
+            placement(256, 16, 1, 2),
+            placement(64, 64, 1, 1),
+        ]);
+
+        // The largest resource is placed first, the others behind it at their alignment.
+        assert_eq!(locations, [(0, 256), (0, 0), (0, 384)]);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].requirements.size, 448);
+        assert_eq!(blocks[0].requirements.alignment, 64);
+    }
+
+    #[test]
+    fn gaps_are_reused() {
+        // `b` and `c` only overlap `a`, so `c` is placed where `b` is once `b` is dead.
+        let (locations, blocks) = plan_blocks(&[
+            placement(256, 16, 0, 3),
+            placement(128, 16, 0, 1),
+            placement(128, 16, 2, 3),
+        ]);
+
+        assert_eq!(locations, [(0, 0), (0, 256), (0, 256)]);
+        assert_eq!(blocks[0].requirements.size, 384);
+    }
+
+    #[test]
+    fn incompatible_memory_types() {
+        let mut host_only = placement(128, 16, 0, 0);
+        host_only.requirements.memory_type_bits = 0b100;
+
+        let (locations, blocks) = plan_blocks(&[placement(256, 16, 1, 1), host_only]);
+
+        assert_eq!(locations, [(0, 0), (1, 0)]);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1].requirements.memory_type_bits, 0b100);
+    }
+
+    #[test]
+    fn aliased_memory() {
+        let placements = [
+            placement(256, 16, 0, 1),
+            placement(128, 16, 2, 3),
+            placement(64, 16, 0, 3),
+        ];
+        let (locations, _) = plan_blocks(&placements);
+
+        // The first two share memory, the last one is placed behind them.
+        assert_eq!(locations, [(0, 0), (0, 0), (0, 256)]);
+        assert_eq!(
+            aliased_placements(&placements, &locations),
+            [true, true, false]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use ash::vk;
+
+/// The pipeline stages, accesses and image layout a resource is used with.
+/// The layout is ignored for buffers.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ResourceState {
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+    pub layout: vk::ImageLayout,
+}
+
+impl ResourceState {
+    #[inline]
+    pub const fn new(
+        stage: vk::PipelineStageFlags2,
+        access: vk::AccessFlags2,
+        layout: vk::ImageLayout,
+    ) -> Self {
+        Self {
+            stage,
+            access,
+            layout,
+        }
+    }
+
+    #[inline]
+    pub fn write_access(&self) -> vk::AccessFlags2 {
+        self.access
+            & (vk::AccessFlags2::SHADER_WRITE
+                | vk::AccessFlags2::SHADER_STORAGE_WRITE
+                | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+                | vk::AccessFlags2::TRANSFER_WRITE
+                | vk::AccessFlags2::HOST_WRITE
+                | vk::AccessFlags2::MEMORY_WRITE)
+    }
+
+    /// The image usage flags an image needs to be used in this state.
+    pub fn image_usage_flags(&self) -> vk::ImageUsageFlags {
+        let mut usage = vk::ImageUsageFlags::empty();
+
+        if self.access.intersects(
+            vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        ) {
+            usage |= vk::ImageUsageFlags::COLOR_ATTACHMENT;
+        }
+        if self.access.intersects(
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ) {
+            usage |= vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+        }
+        if self.access.contains(vk::AccessFlags2::SHADER_SAMPLED_READ) {
+            usage |= vk::ImageUsageFlags::SAMPLED;
+        }
+        if self.access.intersects(
+            vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+        ) {
+            usage |= vk::ImageUsageFlags::STORAGE;
+        }
+        if self.access.contains(vk::AccessFlags2::TRANSFER_READ) {
+            usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+        if self.access.contains(vk::AccessFlags2::TRANSFER_WRITE) {
+            usage |= vk::ImageUsageFlags::TRANSFER_DST;
+        }
+
+        usage
+    }
+
+    /// The buffer usage flags a buffer needs to be used in this state.
+    pub fn buffer_usage_flags(&self) -> vk::BufferUsageFlags {
+        let mut usage = vk::BufferUsageFlags::empty();
+
+        if self
+            .access
+            .contains(vk::AccessFlags2::VERTEX_ATTRIBUTE_READ)
+        {
+            usage |= vk::BufferUsageFlags::VERTEX_BUFFER;
+        }
+        if self.access.contains(vk::AccessFlags2::INDEX_READ) {
+            usage |= vk::BufferUsageFlags::INDEX_BUFFER;
+        }
+        if self
+            .access
+            .contains(vk::AccessFlags2::INDIRECT_COMMAND_READ)
+        {
+            usage |= vk::BufferUsageFlags::INDIRECT_BUFFER;
+        }
+        if self.access.contains(vk::AccessFlags2::UNIFORM_READ) {
+            usage |= vk::BufferUsageFlags::UNIFORM_BUFFER;
+        }
+        if self.access.intersects(
+            vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+        ) {
+            usage |= vk::BufferUsageFlags::STORAGE_BUFFER;
+        }
+        if self.access.contains(vk::AccessFlags2::TRANSFER_READ) {
+            usage |= vk::BufferUsageFlags::TRANSFER_SRC;
+        }
+        if self.access.contains(vk::AccessFlags2::TRANSFER_WRITE) {
+            usage |= vk::BufferUsageFlags::TRANSFER_DST;
+        }
+
+        usage
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ImageUsage {
+    ColorAttachment,
+    DepthAttachment,
+    DepthRead,
+    Sampled(vk::PipelineStageFlags2),
+    StorageRead(vk::PipelineStageFlags2),
+    StorageWrite(vk::PipelineStageFlags2),
+    TransferSrc,
+    TransferDst,
+}
+
+impl ImageUsage {
+    pub fn state(self) -> ResourceState {
+        match self {
+            Self::ColorAttachment => {
+                ResourceState::new(
+                    vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                    vk::AccessFlags2::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                )
+            }
+            Self::DepthAttachment => {
+                ResourceState::new(
+                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                    vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                )
+            }
+            Self::DepthRead => {
+                ResourceState::new(
+                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                    vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+                    vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+                )
+            }
+            Self::Sampled(stage) => {
+                ResourceState::new(
+                    stage,
+                    vk::AccessFlags2::SHADER_SAMPLED_READ,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                )
+            }
+            Self::StorageRead(stage) => {
+                ResourceState::new(
+                    stage,
+                    vk::AccessFlags2::SHADER_STORAGE_READ,
+                    vk::ImageLayout::GENERAL,
+                )
+            }
+            Self::StorageWrite(stage) => {
+                ResourceState::new(
+                    stage,
+                    vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                    vk::ImageLayout::GENERAL,
+                )
+            }
+            Self::TransferSrc => {
+                ResourceState::new(
+                    vk::PipelineStageFlags2::COPY,
+                    vk::AccessFlags2::TRANSFER_READ,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                )
+            }
+            Self::TransferDst => {
+                ResourceState::new(
+                    vk::PipelineStageFlags2::COPY,
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                )
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BufferUsage {
+    Vertex,
+    Index,
+    Indirect,
+    Uniform(vk::PipelineStageFlags2),
+    StorageRead(vk::PipelineStageFlags2),
+    StorageWrite(vk::PipelineStageFlags2),
+    TransferSrc,
+    TransferDst,
+}
+
+impl BufferUsage {
+    pub fn state(self) -> ResourceState {
+        let (stage, access) = match self {
+            Self::Vertex => {
+                (
+                    vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT,
+                    vk::AccessFlags2::VERTEX_ATTRIBUTE_READ,
+                )
+            }
+            Self::Index => {
+                (
+                    vk::PipelineStageFlags2::INDEX_INPUT,
+                    vk::AccessFlags2::INDEX_READ,
+                )
+            }
+            Self::Indirect => {
+                (
+                    vk::PipelineStageFlags2::DRAW_INDIRECT,
+                    vk::AccessFlags2::INDIRECT_COMMAND_READ,
+                )
+            }
+            Self::Uniform(stage) => (stage, vk::AccessFlags2::UNIFORM_READ),
+            Self::StorageRead(stage) => (stage, vk::AccessFlags2::SHADER_STORAGE_READ),
+            Self::StorageWrite(stage) => {
+                (
+                    stage,
+                    vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                )
+            }
+            Self::TransferSrc => {
+                (
+                    vk::PipelineStageFlags2::COPY,
+                    vk::AccessFlags2::TRANSFER_READ,
+                )
+            }
+            Self::TransferDst => {
+                (
+                    vk::PipelineStageFlags2::COPY,
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                )
+            }
+        };
+
+        ResourceState::new(stage, access, vk::ImageLayout::UNDEFINED)
+    }
+}
@@ -2,9 +2,12 @@
 
 pub mod backend;
 
+pub mod bindless;
 mod extract_param;
+pub mod graph;
 pub mod pipelined_rendering;
 pub mod renderer;
+pub mod transfer;
 pub mod view;
 
 use std::ops::{Deref, DerefMut};
@@ -14,6 +17,7 @@ use tort_app::{self as bevy_app, App, AppLabel, CoreSchedule, Plugin, SubApp};
 use tort_asset::{AddAsset, AssetServer};
 use tort_ecs::{
     self as bevy_ecs,
+    event::Events,
     schedule::{
         apply_system_buffers, IntoSystemConfig, IntoSystemSetConfig, Schedule, ScheduleLabel,
         Schedules, SystemSet,
@@ -21,16 +25,48 @@ use tort_ecs::{
     system::Resource,
     world::{Mut, World},
 };
-use tort_math::{Vec2, Vec3};
+use tort_math::{UVec2, Vec2, Vec3};
 
 use crate::{
-    backend::resource::pipeline::{PipelineCache, Shader, ShaderLoader},
-    renderer::{render_system, BuiltinPipelines, FrameCtx},
-    view::{extract_camera_system, update_camera_system, Camera, WindowRenderPlugin},
+    backend::{
+        resource::{
+            descriptor::DescriptorAllocator,
+            pipeline::{
+                create_pipeline_state_channels, PipelineCache, PipelineCacheDesc, PipelineEvent,
+                PipelineStates, Shader, ShaderLoader,
+            },
+        },
+        DeviceRequirements, PhysicalDeviceSelector,
+    },
+    bindless::{BindlessTables, BindlessTablesDesc},
+    graph::{
+        dump_render_graph_system, extract_render_graph_dump_system,
+        request_render_graph_dump_system, ExtractedRenderGraphDump, RenderGraph, RenderGraphDump,
+        TransientResourcePool,
+    },
+    renderer::{
+        add_offscreen_passes_system, add_window_passes_system, render_offscreen_system,
+        render_system, BuiltinPipelines, DeletionQueue, DepthMode, FrameCtx, GeometryPath,
+    },
+    transfer::{ReadbackManager, UploadManager, UploadManagerDesc},
+    view::{
+        create_offscreen_frame_channels, extract_camera_system, update_camera_system, Camera,
+        HeadlessDesc, OffscreenTarget, WindowRenderPlugin, OFFSCREEN_FORMAT,
+    },
 };
 
 #[derive(Default)]
-pub struct RenderPlugin;
+pub struct RenderPlugin {
+    pub depth_mode: DepthMode,
+    /// Renders into an offscreen image instead of windows, without requiring a surface or
+    /// presentation support. Rendered frames are received through
+    /// [`OffscreenFrameReceiver`](view::OffscreenFrameReceiver).
+    pub headless: Option<HeadlessDesc>,
+    /// Overridden by the `TORT_PHYSICAL_DEVICE` environment variable,
+    /// see [`PhysicalDeviceSelector::parse`].
+    pub physical_device: PhysicalDeviceSelector,
+    pub pipeline_cache: PipelineCacheDesc,
+}
 
 /// The labels of the default App rendering sets.
 ///
@@ -50,9 +86,13 @@ pub enum RenderSet {
     PrepareFlush,
     /// Actual rendering happens here.
     /// In most cases, only the render backend should insert resources here.
+    ///
+    /// Passes are added to the [`RenderGraph`](graph::RenderGraph) in this set.
     Render,
     /// The copy of [`apply_system_buffers`] that runs immediately after `Render`.
     RenderFlush,
+    /// The [`RenderGraph`](graph::RenderGraph) built in `Render` is executed and submitted here.
+    Submit,
     /// Cleanup render resources here.
     Cleanup,
     /// The copy of [`apply_system_buffers`] that runs immediately after `Cleanup`.
@@ -78,7 +118,8 @@ impl RenderSet {
         schedule.configure_set(ExtractCommands.before(Prepare));
         schedule.configure_set(Prepare.after(ExtractCommands).before(PrepareFlush));
         schedule.configure_set(Render.after(PrepareFlush).before(RenderFlush));
-        schedule.configure_set(Cleanup.after(RenderFlush).before(CleanupFlush));
+        schedule.configure_set(Submit.after(RenderFlush).before(Cleanup));
+        schedule.configure_set(Cleanup.after(Submit).before(CleanupFlush));
 
         schedule
     }
@@ -126,26 +167,44 @@ impl Plugin for RenderPlugin {
             .init_asset_loader::<ShaderLoader>()
             .init_debug_asset_loader::<ShaderLoader>();
 
-        let (instance, device) = renderer::init();
+        // Plugins built before this one may have contributed device requirements.
+        let requirements = app
+            .world
+            .remove_resource::<DeviceRequirements>()
+            .unwrap_or_default();
+
+        let (instance, device, negotiated) =
+            renderer::init(self.headless.is_some(), &self.physical_device, requirements);
+
+        let mut camera = Camera::new(Vec3::ZERO, 90., 0.1, 1000., Vec2::ONE, 1.);
+        camera.set_depth_mode(self.depth_mode);
+
+        if let Some(headless) = self.headless {
+            camera.set_target_size(Some(UVec2::new(headless.width, headless.height)));
+        }
 
         app.insert_resource(instance.clone())
             .insert_resource(device.clone())
+            .insert_resource(negotiated.clone())
             .init_resource::<ScratchMainWorld>()
-            .insert_resource(Camera::new(
-                Vec3::ZERO,
-                90.,
-                Vec2::new(1600., 900.),
-                0.1,
-                1000.,
-                Vec2::ONE,
-                1.,
-            ))
-            .add_system(update_camera_system);
-
-        let mut pipeline_cache = PipelineCache::new(device.clone());
+            .insert_resource(camera)
+            .add_system(update_camera_system)
+            .init_resource::<RenderGraphDump>()
+            .add_system(request_render_graph_dump_system)
+            .add_event::<PipelineEvent>()
+            .init_resource::<PipelineStates>()
+            .add_system(PipelineStates::receive_system);
+
+        let mut pipeline_cache = PipelineCache::new(device.clone(), &self.pipeline_cache).unwrap();
         let asset_server = app.world.resource::<AssetServer>().clone();
 
-        let builtin_pipelines = BuiltinPipelines::new(&asset_server, &mut pipeline_cache);
+        let builtin_pipelines = BuiltinPipelines::new(
+            &asset_server,
+            &mut pipeline_cache,
+            self.depth_mode,
+            self.headless.map(|_| OFFSCREEN_FORMAT),
+            GeometryPath::from_device(&device),
+        );
 
         let mut render_app = App::empty();
         render_app.add_simple_outer_schedule();
@@ -156,7 +215,8 @@ impl Plugin for RenderPlugin {
             schedule
                 .set_apply_final_buffers(false)
                 .add_system(PipelineCache::extract_shaders_system)
-                .add_system(extract_camera_system);
+                .add_system(extract_camera_system)
+                .add_system(extract_render_graph_dump_system);
         });
 
         // This set applies the commands from the extract stage while the render schedule
@@ -164,25 +224,110 @@ impl Plugin for RenderPlugin {
         render_schedule.add_system(apply_extract_commands.in_set(RenderSet::ExtractCommands));
 
         render_schedule.add_system(
-            PipelineCache::process_pipelines_system
-                .before(render_system)
-                .in_set(RenderSet::Render),
+            Events::<PipelineEvent>::update_system
+                .before(PipelineCache::process_pipelines_system)
+                .in_set(RenderSet::Prepare),
+        );
+        render_schedule
+            .add_system(PipelineCache::process_pipelines_system.in_set(RenderSet::Prepare));
+        render_schedule.add_system(
+            PipelineCache::forward_states_system
+                .after(PipelineCache::process_pipelines_system)
+                .in_set(RenderSet::Prepare),
+        );
+        render_schedule
+            .add_system(DescriptorAllocator::begin_frame_system.in_set(RenderSet::Prepare));
+        render_schedule.add_system(
+            BindlessTables::recycle_system
+                .after(DescriptorAllocator::begin_frame_system)
+                .in_set(RenderSet::Prepare),
+        );
+        render_schedule.add_system(
+            DeletionQueue::collect_system
+                .after(DescriptorAllocator::begin_frame_system)
+                .in_set(RenderSet::Prepare),
         );
 
-        render_schedule.add_system(render_system.in_set(RenderSet::Render));
+        if self.headless.is_some() {
+            render_schedule.add_system(add_offscreen_passes_system.in_set(RenderSet::Render));
+            render_schedule.add_system(
+                dump_render_graph_system
+                    .before(render_offscreen_system)
+                    .in_set(RenderSet::Submit),
+            );
+            render_schedule.add_system(
+                UploadManager::flush_system
+                    .before(render_offscreen_system)
+                    .in_set(RenderSet::Submit),
+            );
+            render_schedule.add_system(render_offscreen_system.in_set(RenderSet::Submit));
+        } else {
+            render_schedule.add_system(add_window_passes_system.in_set(RenderSet::Render));
+            render_schedule.add_system(
+                dump_render_graph_system
+                    .before(render_system)
+                    .in_set(RenderSet::Submit),
+            );
+            render_schedule.add_system(
+                UploadManager::flush_system
+                    .before(render_system)
+                    .in_set(RenderSet::Submit),
+            );
+            render_schedule.add_system(render_system.in_set(RenderSet::Submit));
+        }
+
+        render_schedule.add_system(ReadbackManager::poll_system.in_set(RenderSet::Cleanup));
+        render_schedule.add_system(PipelineCache::save_system.in_set(RenderSet::Cleanup));
         render_schedule.add_system(World::clear_entities.in_set(RenderSet::Cleanup));
 
         let frame_ctx = FrameCtx::new(device.clone(), 2);
+        let transient_pool = TransientResourcePool::new(device.clone(), frame_ctx.num_frames());
+        let upload_manager =
+            UploadManager::new(device.clone(), &UploadManagerDesc::default()).unwrap();
+        let readback_manager = ReadbackManager::new(device.clone()).unwrap();
+        let descriptor_allocator = DescriptorAllocator::new(device.clone(), frame_ctx.num_frames());
+        let bindless_tables = BindlessTables::new(
+            device.clone(),
+            &pipeline_cache,
+            &BindlessTablesDesc::default(),
+        )
+        .unwrap();
+
+        if let Some(headless) = self.headless {
+            let offscreen_target =
+                OffscreenTarget::new(&device, headless, frame_ctx.num_frames()).unwrap();
+            let (sender, receiver) = create_offscreen_frame_channels();
+
+            app.insert_resource(receiver);
+            render_app
+                .insert_resource(offscreen_target)
+                .insert_resource(sender);
+        }
 
         render_app
             .add_schedule(CoreSchedule::Main, render_schedule)
             .insert_resource(instance)
             .insert_resource(device)
+            .insert_resource(negotiated)
             .insert_resource(frame_ctx)
+            .insert_resource(self.depth_mode)
             .insert_resource(pipeline_cache)
             .insert_resource(builtin_pipelines)
+            .init_resource::<RenderGraph>()
+            .init_resource::<ExtractedRenderGraphDump>()
+            .insert_resource(transient_pool)
+            .insert_resource(upload_manager)
+            .insert_resource(readback_manager)
+            .insert_resource(descriptor_allocator)
+            .insert_resource(bindless_tables)
+            .init_resource::<DeletionQueue>()
+            .init_resource::<Events<PipelineEvent>>()
             .insert_resource(asset_server);
 
+        let (sender, receiver) = create_pipeline_state_channels();
+        app.insert_resource(receiver);
+        render_app.insert_resource(sender);
+
         let (sender, receiver) = tort_time::create_time_channels();
         app.insert_resource(receiver);
         render_app.insert_resource(sender);
@@ -219,7 +364,9 @@ impl Plugin for RenderPlugin {
             extract(main_world, render_app);
         }));
 
-        app.add_plugin(WindowRenderPlugin);
+        if self.headless.is_none() {
+            app.add_plugin(WindowRenderPlugin);
+        }
     }
 }
 
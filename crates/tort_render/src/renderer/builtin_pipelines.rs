@@ -1,28 +1,44 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, mem};
 
 use ash::vk;
 use tort_asset::AssetServer;
 use tort_ecs::{self as bevy_ecs, system::Resource};
+use tort_math::Mat4;
 use tort_utils::OrderedFloat;
 
-use crate::backend::resource::pipeline::{
-    ColorBlendStateDesc, DynamicStateDesc, GraphicsPipelineDesc, GraphicsPipelineId,
-    InputAssemblyStateDesc, MultisampleStateDesc, PipelineCache, RasterizationStateDesc,
-    RenderingStateDesc, ShaderStageDesc, ViewportStateDesc,
+use crate::{
+    backend::resource::pipeline::{
+        ColorBlendStateDesc, DepthStencilStateDesc, DynamicStateDesc, GraphicsPipelineDesc,
+        GraphicsPipelineId, InputAssemblyStateDesc, MultisampleStateDesc, PipelineCache,
+        RasterizationStateDesc, RenderingStateDesc, ShaderStageDesc, ViewportStateDesc,
+    },
+    renderer::{DepthMode, GeometryPath, DEPTH_FORMAT},
 };
 
 #[derive(Resource)]
 pub struct BuiltinPipelines {
+    pub geometry_path: GeometryPath,
     pub geometry_pipeline: GraphicsPipelineId,
 }
 
 impl BuiltinPipelines {
-    pub fn new(asset_server: &AssetServer, pipeline_cache: &mut PipelineCache) -> Self {
+    pub fn new(
+        asset_server: &AssetServer,
+        pipeline_cache: &mut PipelineCache,
+        depth_mode: DepthMode,
+        color_format: Option<vk::Format>,
+        geometry_path: GeometryPath,
+    ) -> Self {
+        let geometry_shader = match geometry_path {
+            GeometryPath::MeshShader => "shaders/geometry_pass.mesh.glsl",
+            GeometryPath::Vertex => "shaders/geometry_pass.vert.glsl",
+        };
+
         let geometry_pipeline = pipeline_cache.queue_graphics_pipeline(&GraphicsPipelineDesc {
             stages: vec![
                 ShaderStageDesc {
-                    shader: asset_server.load("shaders/geometry_pass.mesh.glsl"),
-                    stage: vk::ShaderStageFlags::MESH_EXT,
+                    shader: asset_server.load(geometry_shader),
+                    stage: geometry_path.stage(),
                     entry_point: Cow::Borrowed("main"),
                     ..Default::default()
                 },
@@ -51,6 +67,12 @@ impl BuiltinPipelines {
                 rasterization_samples: vk::SampleCountFlags::TYPE_1,
                 ..Default::default()
             },
+            depth_stencil_state: Some(DepthStencilStateDesc {
+                depth_test_enable: true,
+                depth_write_enable: true,
+                depth_compare_op: depth_mode.compare_op(),
+                ..Default::default()
+            }),
             color_blend_state: ColorBlendStateDesc {
                 attachments: vec![Default::default()],
                 ..Default::default()
@@ -60,12 +82,18 @@ impl BuiltinPipelines {
                 ..Default::default()
             },
             rendering_state: RenderingStateDesc {
-                color_attachment_formats: vec![],
+                color_attachment_formats: color_format.into_iter().collect(),
+                depth_attachment_format: DEPTH_FORMAT,
                 ..Default::default()
             },
+            // The view projection matrix.
+            push_constant_size: Some(mem::size_of::<Mat4>() as u32),
             ..Default::default()
         });
 
-        Self { geometry_pipeline }
+        Self {
+            geometry_path,
+            geometry_pipeline,
+        }
     }
 }
@@ -0,0 +1,67 @@
+use std::{any::Any, collections::VecDeque};
+
+use log::debug;
+use tort_ecs::{
+    system::{Res, ResMut, Resource},
+    {self as bevy_ecs},
+};
+
+use crate::renderer::FrameCtx;
+
+/// Defers dropping resources until the frames that may reference them have completed on the
+/// device, instead of waiting for the device to be idle.
+///
+/// Resources are pushed with the index of the last frame that may use them and are dropped by
+/// [`DeletionQueue::collect_system`] once [`FrameCtx::device_completed_frame_index`] reaches it.
+#[derive(Resource, Default)]
+pub struct DeletionQueue {
+    resources: VecDeque<(usize, Box<dyn Any + Send + Sync>)>,
+}
+
+impl DeletionQueue {
+    /// Drops `resource` once the frame `frame_index` has completed.
+    #[inline]
+    pub fn push(&mut self, frame_index: usize, resource: impl Any + Send + Sync) {
+        self.resources.push_back((frame_index, Box::new(resource)));
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+
+    /// Drops the resources of all frames up to and including `completed_frame_index`.
+    pub fn collect(&mut self, completed_frame_index: usize) {
+        let len = self.resources.len();
+
+        // Frame indices are pushed in increasing order.
+        while let Some((frame_index, _)) = self.resources.front() {
+            if *frame_index > completed_frame_index {
+                break
+            }
+
+            self.resources.pop_front();
+        }
+
+        if len != self.resources.len() {
+            debug!(
+                "Destroyed {} deferred resources",
+                len - self.resources.len()
+            );
+        }
+    }
+
+    /// Must run after the fence of the current frame was waited for, which
+    /// [`DescriptorAllocator::begin_frame_system`](crate::backend::resource::descriptor::DescriptorAllocator::begin_frame_system)
+    /// does.
+    pub fn collect_system(frame_ctx: Res<FrameCtx>, mut deletion_queue: ResMut<DeletionQueue>) {
+        if let Some(completed_frame_index) = frame_ctx.device_completed_frame_index() {
+            deletion_queue.collect(completed_frame_index);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use ash::vk;
+use tort_ecs::{self as bevy_ecs, system::Resource};
+use tort_math::Mat4;
+
+pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// How depth values are distributed in the depth buffer.
+///
+/// [`DepthMode::ReverseZ`] maps the near plane to `1.0` and the far plane to `0.0`, which
+/// distributes floating point precision much more evenly over the view distance.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Resource)]
+pub enum DepthMode {
+    Standard,
+    #[default]
+    ReverseZ,
+}
+
+impl DepthMode {
+    #[inline]
+    pub fn perspective(
+        self,
+        field_of_view: f32,
+        aspect_ratio: f32,
+        near_plane: f32,
+        far_plane: f32,
+    ) -> Mat4 {
+        match self {
+            Self::Standard => {
+                Mat4::perspective_lh(field_of_view, aspect_ratio, near_plane, far_plane)
+            }
+            Self::ReverseZ => {
+                Mat4::perspective_lh(field_of_view, aspect_ratio, far_plane, near_plane)
+            }
+        }
+    }
+
+    #[inline]
+    pub fn clear_depth(self) -> f32 {
+        match self {
+            Self::Standard => 1.0,
+            Self::ReverseZ => 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn compare_op(self) -> vk::CompareOp {
+        match self {
+            Self::Standard => vk::CompareOp::LESS_OR_EQUAL,
+            Self::ReverseZ => vk::CompareOp::GREATER_OR_EQUAL,
+        }
+    }
+}
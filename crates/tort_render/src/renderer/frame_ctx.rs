@@ -1,4 +1,5 @@
-use tort_ecs::{self as bevy_ecs, system::Resource};
+use tort_ecs::{self as bevy_ecs, entity::Entity, system::Resource};
+use tort_utils::HashMap;
 
 use crate::backend::{
     command::{CommandBuffer, CommandBufferDesc, CommandPool, CommandPoolDesc},
@@ -49,11 +50,30 @@ impl FrameCtx {
         &mut self.frames[self.frame_offset]
     }
 
+    #[inline]
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    #[inline]
+    pub fn frame_offset(&self) -> usize {
+        self.frame_offset
+    }
+
     #[inline]
     pub fn frame_index(&self) -> usize {
         self.frame_index
     }
 
+    /// Removes the semaphores of `window`, they may still be used by the frames in flight.
+    #[inline]
+    pub fn remove_window(&mut self, window: Entity) -> Vec<BinarySemaphore> {
+        self.frames
+            .iter_mut()
+            .filter_map(|frame| frame.remove_window(window))
+            .collect()
+    }
+
     #[inline]
     pub fn device_completed_frame_index(&self) -> Option<usize> {
         if self.frame_index >= self.frames.len() {
@@ -118,9 +138,10 @@ impl QueueFrame {
 
 pub struct Frame {
     queue_frames: Vec<QueueFrame>,
-    image_acquired_semaphore: BinarySemaphore,
+    image_acquired_semaphores: HashMap<Entity, BinarySemaphore>,
     rendering_done_semaphore: BinarySemaphore,
     fence: Fence,
+    device: Device,
 }
 
 impl Frame {
@@ -130,12 +151,10 @@ impl Frame {
             .iter()
             .map(|queue| QueueFrame::new(device.clone(), queue))
             .collect();
-        let image_acquired_semaphore =
-            BinarySemaphore::new(device.clone(), &BinarySemaphoreDesc::default()).unwrap();
         let rendering_done_semaphore =
             BinarySemaphore::new(device.clone(), &BinarySemaphoreDesc::default()).unwrap();
         let fence = Fence::new(
-            device,
+            device.clone(),
             &FenceDesc {
                 signaled: true,
                 ..Default::default()
@@ -145,9 +164,10 @@ impl Frame {
 
         Self {
             queue_frames,
-            image_acquired_semaphore,
+            image_acquired_semaphores: HashMap::new(),
             rendering_done_semaphore,
             fence,
+            device,
         }
     }
 
@@ -156,9 +176,21 @@ impl Frame {
         &self.queue_frames[queue_idx as usize]
     }
 
+    /// Returns the semaphore signalled when the swapchain image of `window` is acquired,
+    /// creating it on first use. Every window needs its own semaphore, since all of them are
+    /// waited on by the same submission.
+    #[inline]
+    pub fn image_acquired_semaphore(&mut self, window: Entity) -> &BinarySemaphore {
+        self.image_acquired_semaphores
+            .entry(window)
+            .or_insert_with(|| {
+                BinarySemaphore::new(self.device.clone(), &BinarySemaphoreDesc::default()).unwrap()
+            })
+    }
+
     #[inline]
-    pub fn image_acquired_semaphore(&self) -> &BinarySemaphore {
-        &self.image_acquired_semaphore
+    fn remove_window(&mut self, window: Entity) -> Option<BinarySemaphore> {
+        self.image_acquired_semaphores.remove(&window)
     }
 
     #[inline]
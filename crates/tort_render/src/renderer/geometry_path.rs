@@ -0,0 +1,37 @@
+use ash::vk;
+use tort_ecs::{self as bevy_ecs, system::Resource};
+
+use crate::backend::Device;
+
+/// How geometry is fed to the rasterizer, negotiated from the device capabilities.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Resource)]
+pub enum GeometryPath {
+    /// Mesh shaders through `VK_EXT_mesh_shader`.
+    MeshShader,
+    /// A classic vertex shader pipeline, used when mesh shaders are unavailable.
+    Vertex,
+}
+
+impl GeometryPath {
+    pub fn from_device(device: &Device) -> Self {
+        if device.extensions().ext_mesh_shader()
+            && device
+                .enabled_features()
+                .get::<vk::PhysicalDeviceMeshShaderFeaturesEXT>()
+                .map_or(false, |features| features.mesh_shader == vk::TRUE)
+        {
+            Self::MeshShader
+        } else {
+            Self::Vertex
+        }
+    }
+
+    /// The stage that consumes the geometry.
+    #[inline]
+    pub fn stage(self) -> vk::ShaderStageFlags {
+        match self {
+            Self::MeshShader => vk::ShaderStageFlags::MESH_EXT,
+            Self::Vertex => vk::ShaderStageFlags::VERTEX,
+        }
+    }
+}
@@ -1,21 +1,107 @@
 mod builtin_pipelines;
+mod deletion_queue;
+mod depth;
 mod frame_ctx;
+mod geometry_path;
 
-use std::{env, mem, slice};
+use std::{borrow::Cow, env, fs, mem, slice};
 
 use anyhow::bail;
-use ash::vk;
+use ash::{
+    extensions::{ext, khr},
+    vk,
+};
 pub use builtin_pipelines::*;
+pub use deletion_queue::*;
+pub use depth::*;
 pub use frame_ctx::*;
+pub use geometry_path::*;
+use log::{info, warn};
 use tort_ecs::system::{Res, ResMut};
-use tort_utils::slices;
+use tort_math::UVec2;
+use tort_utils::smallvec::SmallVec4;
 
 use crate::{
-    backend::{resource::pipeline::PipelineCache, Device, Instance, Swapchain},
-    view::{ExtractedCamera, ExtractedWindows, WindowSurfaces},
+    backend::{
+        resource::pipeline::PipelineCache, utils::Extent3D, Device, DeviceReport,
+        DeviceRequirements, Instance, NegotiatedCapabilities, PhysicalDeviceSelector, Swapchain,
+    },
+    device_feature,
+    graph::{
+        BufferUsage, ImageHandle, ImageUsage, ImportedBufferDesc, ImportedImageDesc, RenderGraph,
+        ResourceState, TransientImageDesc, TransientResourcePool,
+    },
+    transfer::{ReadbackManager, UploadManager},
+    view::{
+        ExtractedCamera, ExtractedWindow, ExtractedWindows, OffscreenFrameSender, OffscreenTarget,
+        WindowSurfaces,
+    },
 };
 
-pub fn init() -> (Instance, Device) {
+/// Creates the instance and device.
+///
+/// In headless mode no surface or swapchain extensions are enabled, so no presentation support is
+/// required from the platform or the device.
+///
+/// The extensions and features are negotiated from `requirements`, extended by the ones the
+/// renderer itself needs. The [`DeviceReport`] is logged and, if `TORT_DEVICE_REPORT` is set,
+/// written as JSON to that path.
+pub fn init(
+    headless: bool,
+    physical_device_selector: &PhysicalDeviceSelector,
+    mut requirements: DeviceRequirements,
+) -> (Instance, Device, NegotiatedCapabilities) {
+    requirements
+        .register_extension_features::<vk::PhysicalDeviceDynamicRenderingFeatures>(
+            khr::DynamicRendering::name(),
+        )
+        .register_extension_features::<vk::PhysicalDeviceSynchronization2Features>(
+            khr::Synchronization2::name(),
+        )
+        .register_extension_features::<vk::PhysicalDeviceMeshShaderFeaturesEXT>(
+            ext::MeshShader::name(),
+        )
+        .require_feature(device_feature!(features_12.timeline_semaphore))
+        .require_feature(device_feature!(
+            vk::PhysicalDeviceDynamicRenderingFeatures,
+            dynamic_rendering_features.dynamic_rendering
+        ))
+        .require_feature(device_feature!(
+            vk::PhysicalDeviceSynchronization2Features,
+            synchronization2_features.synchronization2
+        ))
+        // Descriptor indexing, see `BindlessTables`.
+        .require_feature(device_feature!(features_12.runtime_descriptor_array))
+        .require_feature(device_feature!(
+            features_12.descriptor_binding_partially_bound
+        ))
+        .require_feature(device_feature!(
+            features_12.descriptor_binding_update_unused_while_pending
+        ))
+        .require_feature(device_feature!(
+            features_12.descriptor_binding_sampled_image_update_after_bind
+        ))
+        .require_feature(device_feature!(
+            features_12.descriptor_binding_storage_buffer_update_after_bind
+        ))
+        .require_feature(device_feature!(
+            features_12.shader_sampled_image_array_non_uniform_indexing
+        ))
+        .require_feature(device_feature!(
+            features_12.shader_storage_buffer_array_non_uniform_indexing
+        ))
+        // Mesh shaders are optional, see `GeometryPath`.
+        .request_feature(device_feature!(
+            vk::PhysicalDeviceMeshShaderFeaturesEXT,
+            mesh_shader_features.mesh_shader
+        ));
+
+    if !headless {
+        requirements.require_extension(khr::Swapchain::name());
+    }
+
+    let mut negotiated = NegotiatedCapabilities::default();
+
     let instance = Instance::new(
         |layers| {
             if env::var("TORT_VALIDATION_LAYERS").is_ok() {
@@ -45,24 +131,39 @@ pub fn init() -> (Instance, Device) {
 
             extensions.try_push_khr_portability_enumeration();
 
-            extensions.push_khr_get_surface_capabilities2();
+            if !headless {
+                extensions.push_surface_extensions();
+                extensions.push_khr_get_surface_capabilities2();
+            }
+
+            requirements.negotiate_instance(extensions, &mut negotiated)?;
 
             Ok(version)
         },
     )
     .unwrap();
 
-    let physical_device = instance.find_optimal_physical_device();
+    // The environment variable takes precedence, so a device can be picked without rebuilding.
+    let physical_device_selector =
+        PhysicalDeviceSelector::from_env().unwrap_or_else(|| physical_device_selector.clone());
+
+    let physical_device = instance
+        .select_physical_device(
+            &physical_device_selector,
+            &requirements.required_extension_names(),
+        )
+        .unwrap();
 
     let device = unsafe {
         Device::new(
             instance.clone(),
             physical_device,
+            &requirements.extension_features,
             |properties,
              _memory_properties,
              _queue_family_properties,
              extensions,
-             _supported_features,
+             supported_features,
              enabled_features| {
                 let version = properties.properties.api_version;
                 let major = vk::api_version_minor(version);
@@ -78,64 +179,213 @@ pub fn init() -> (Instance, Device) {
                 }
 
                 extensions.try_push_khr_portability_subset();
-                extensions.push_ext_mesh_shader();
-                extensions.push_khr_dynamic_rendering();
-                extensions.push_khr_swapchain();
-                extensions.push_khr_synchronization2();
-
-                enabled_features.features = vk::PhysicalDeviceFeatures::default();
-                enabled_features.features_11 = vk::PhysicalDeviceVulkan11Features::default();
-                enabled_features.features_12 =
-                    vk::PhysicalDeviceVulkan12Features::default().timeline_semaphore(true);
-                enabled_features.dynamic_rendering_features =
-                    vk::PhysicalDeviceDynamicRenderingFeatures::default().dynamic_rendering(true);
-                enabled_features.mesh_shader_features =
-                    vk::PhysicalDeviceMeshShaderFeaturesEXT::default().mesh_shader(true);
-                enabled_features.synchronization2_features =
-                    vk::PhysicalDeviceSynchronization2FeaturesKHR::default().synchronization2(true);
-
-                Ok(())
+
+                requirements.negotiate(
+                    extensions,
+                    supported_features,
+                    enabled_features,
+                    &mut negotiated,
+                )
             },
         )
     }
     .unwrap();
 
-    (instance, device)
+    if !negotiated.missing_optional_features.is_empty() {
+        info!(
+            "Optional device features are unavailable: {:?}",
+            negotiated.missing_optional_features
+        );
+    }
+
+    info!(
+        "Using the {:?} geometry path",
+        GeometryPath::from_device(&device)
+    );
+
+    let device_report = DeviceReport::new(&device);
+    info!("{device_report}");
+
+    if let Ok(path) = env::var("TORT_DEVICE_REPORT") {
+        let result = device_report
+            .to_json()
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Ok(fs::write(&path, json)?));
+
+        if let Err(e) = result {
+            warn!("Failed to write device report to {path}: {e}");
+        }
+    }
+
+    (instance, device, negotiated)
 }
 
+#[inline]
+fn windows_to_render(windows: &ExtractedWindows) -> SmallVec4<&ExtractedWindow> {
+    windows
+        .windows
+        .values()
+        .filter(|window| window.physical_width != 0 && window.physical_height != 0)
+        .collect()
+}
+
+/// Adds the geometry pass of every window to the [`RenderGraph`].
+pub fn add_window_passes_system(
+    windows: Res<ExtractedWindows>,
+    mut graph: ResMut<RenderGraph>,
+    pipeline_cache: Res<PipelineCache>,
+    builtin_pipelines: Res<BuiltinPipelines>,
+    camera: Res<ExtractedCamera>,
+    depth_mode: Res<DepthMode>,
+) {
+    for window in windows_to_render(&windows) {
+        let color_image = graph.import_image(ImportedImageDesc {
+            label: Cow::Borrowed("swapchain_image"),
+            image: window.swap_chain_image,
+            image_view: window.swap_chain_image_view,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            // The acquire semaphore is waited for at this stage.
+            initial_state: ResourceState::new(
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::NONE,
+                vk::ImageLayout::UNDEFINED,
+            ),
+            final_state: Some(ResourceState::new(
+                vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+                vk::AccessFlags2::NONE,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            )),
+        });
+
+        let size = UVec2::new(window.physical_width, window.physical_height);
+        let depth_image = create_depth_image(&mut graph, size);
+
+        add_geometry_pass(
+            &mut graph,
+            color_image,
+            depth_image,
+            size,
+            &pipeline_cache,
+            &builtin_pipelines,
+            &camera,
+            *depth_mode,
+        );
+    }
+}
+
+/// Adds the geometry pass rendering into the [`OffscreenTarget`] and the pass copying the result
+/// into the readback buffer of the current frame to the [`RenderGraph`].
+pub fn add_offscreen_passes_system(
+    offscreen_target: Res<OffscreenTarget>,
+    frame_ctx: Res<FrameCtx>,
+    mut graph: ResMut<RenderGraph>,
+    pipeline_cache: Res<PipelineCache>,
+    builtin_pipelines: Res<BuiltinPipelines>,
+    camera: Res<ExtractedCamera>,
+    depth_mode: Res<DepthMode>,
+) {
+    let desc = *offscreen_target.desc();
+    let size = UVec2::new(desc.width, desc.height);
+
+    let color_image = graph.import_image(ImportedImageDesc {
+        label: Cow::Borrowed("offscreen_color_image"),
+        image: **offscreen_target.color_image(),
+        image_view: **offscreen_target.color_image_view(),
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        // The copy of the previous frame has to finish before the image is overwritten.
+        initial_state: ResourceState::new(
+            vk::PipelineStageFlags2::COPY,
+            vk::AccessFlags2::NONE,
+            vk::ImageLayout::UNDEFINED,
+        ),
+        final_state: None,
+    });
+
+    let depth_image = create_depth_image(&mut graph, size);
+
+    let readback_buffer = graph.import_buffer(ImportedBufferDesc {
+        label: Cow::Borrowed("offscreen_readback_buffer"),
+        buffer: **offscreen_target.readback_buffer(frame_ctx.frame_offset()),
+        initial_state: ResourceState::default(),
+        final_state: Some(ResourceState::new(
+            vk::PipelineStageFlags2::HOST,
+            vk::AccessFlags2::HOST_READ,
+            vk::ImageLayout::UNDEFINED,
+        )),
+    });
+
+    add_geometry_pass(
+        &mut graph,
+        color_image,
+        depth_image,
+        size,
+        &pipeline_cache,
+        &builtin_pipelines,
+        &camera,
+        *depth_mode,
+    );
+
+    graph
+        .add_pass("offscreen_readback")
+        .read_image(color_image, ImageUsage::TransferSrc)
+        .write_buffer(readback_buffer, BufferUsage::TransferDst)
+        .execute(move |ctx| unsafe {
+            ctx.device.loader().cmd_copy_image_to_buffer(
+                ctx.command_buffer,
+                ctx.image(color_image),
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ctx.buffer(readback_buffer),
+                slice::from_ref(
+                    &vk::BufferImageCopy::default()
+                        .image_subresource(
+                            vk::ImageSubresourceLayers::default()
+                                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                                .layer_count(1),
+                        )
+                        .image_extent(vk::Extent3D {
+                            width: size.x,
+                            height: size.y,
+                            depth: 1,
+                        }),
+                ),
+            );
+        });
+}
+
+/// Executes the [`RenderGraph`] and presents all windows.
 pub fn render_system(
     windows: Res<ExtractedWindows>,
     mut window_surfaces: ResMut<WindowSurfaces>,
-    frame_ctx: ResMut<FrameCtx>,
+    mut frame_ctx: ResMut<FrameCtx>,
+    mut graph: ResMut<RenderGraph>,
+    mut transient_pool: ResMut<TransientResourcePool>,
+    mut upload_manager: ResMut<UploadManager>,
+    mut readback_manager: ResMut<ReadbackManager>,
+    mut deletion_queue: ResMut<DeletionQueue>,
     instance: Res<Instance>,
     device: Res<Device>,
-    pipeline_cache: Res<PipelineCache>,
-    builtin_pipelines: Res<BuiltinPipelines>,
-    camera: Res<ExtractedCamera>,
 ) {
+    let frame_index = frame_ctx.frame_index();
     let frame = frame_ctx.current();
 
     let device_loader = device.loader();
-    let dynamic_rendering_loader = device.dynamic_rendering_loader();
-    let synchronization2_loader = device.synchronization2_loader();
 
     let queue_frame = frame.queue_frame(0);
     let command_pool = **queue_frame.command_pool();
     let command_buffer = **queue_frame.command_buffer();
 
-    let image_acquired_semaphore = frame.image_acquired_semaphore();
     let rendering_done_semaphore = frame.rendering_done_semaphore();
 
-    for window in windows.windows.values() {
-        if window.physical_width == 0 || window.physical_height == 0 {
-            continue
-        }
-
-        let (surface, swapchain) = window_surfaces.surfaces.get_mut(&window.entity).unwrap();
+    let windows_to_render = windows_to_render(&windows);
 
+    if windows_to_render.is_empty() {
+        graph.clear();
+        // Discards the readbacks added to the graph.
+        readback_manager.submit();
+    } else {
         unsafe {
+            // The fence was already waited for in `prepare_windows` before acquiring the images.
             let fence = frame.fence();
-            fence.wait_for(u64::MAX).unwrap();
             fence.reset().unwrap();
 
             device_loader
@@ -150,159 +400,334 @@ pub fn render_system(
                 )
                 .unwrap();
 
-            synchronization2_loader.cmd_pipeline_barrier2(
-                command_buffer,
-                &vk::DependencyInfo::default().image_memory_barriers(slice::from_ref(
-                    &vk::ImageMemoryBarrier2::default()
-                        .src_stage_mask(vk::PipelineStageFlags2::TOP_OF_PIPE)
-                        .dst_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-                        .dst_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
-                        .old_layout(vk::ImageLayout::UNDEFINED)
-                        .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                        .image(window.swap_chain_image)
-                        .subresource_range(
-                            vk::ImageSubresourceRange::default()
-                                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                                .level_count(1)
-                                .layer_count(1),
-                        ),
-                )),
-            );
-
-            let color_attachment = vk::RenderingAttachmentInfo::default()
-                .image_view(window.swap_chain_image_view)
-                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                .load_op(vk::AttachmentLoadOp::CLEAR)
-                .store_op(vk::AttachmentStoreOp::STORE)
-                .clear_value(vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [100.0 / 255.0, 149.0 / 255.0, 237.0 / 255.0, 1.0],
-                    },
-                });
-
-            let rendering_info = vk::RenderingInfo::default()
-                .render_area(
-                    vk::Rect2D::default().extent(
-                        vk::Extent2D::default()
-                            .width(window.physical_width)
-                            .height(window.physical_height),
-                    ),
-                )
-                .layer_count(1)
-                .color_attachments(slice::from_ref(&color_attachment));
+            let upload_acquires = upload_manager.take_acquires();
+            if let Some(upload_acquires) = &upload_acquires {
+                upload_acquires.record(&device, command_buffer);
+            }
 
-            dynamic_rendering_loader.cmd_begin_rendering(command_buffer, &rendering_info);
+            graph
+                .execute(&device, command_buffer, &mut transient_pool, frame_index)
+                .unwrap();
 
-            if let Some(pipeline) =
-                pipeline_cache.get_graphics_pipeline(&builtin_pipelines.geometry_pipeline)
-            {
-                device_loader.cmd_bind_pipeline(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    **pipeline,
-                );
+            device_loader.end_command_buffer(command_buffer).unwrap();
 
-                device_loader.cmd_set_viewport(
-                    command_buffer,
-                    0,
-                    slice::from_ref(
-                        &vk::Viewport::default()
-                            .width(1600.0)
-                            .height(900.0)
-                            .max_depth(1.0),
-                    ),
-                );
-                device_loader.cmd_set_scissor(
-                    command_buffer,
-                    0,
-                    slice::from_ref(&vk::Rect2D::default().extent(vk::Extent2D {
-                        width: 1600,
-                        height: 900,
-                    })),
-                );
+            let direct_queue = **device.direct_queue();
 
-                device_loader.cmd_push_constants(
-                    command_buffer,
-                    ***pipeline.pipeline_layout(),
-                    vk::ShaderStageFlags::MESH_EXT,
-                    0,
-                    slices::bytes_of(slice::from_ref(&camera.view_projection_matrix)),
-                );
+            let mut wait_semaphores = windows_to_render
+                .iter()
+                .map(|window| window.image_acquired_semaphore)
+                .collect::<SmallVec4<_>>();
+            let mut wait_dst_stage_masks = SmallVec4::from_elem(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                windows_to_render.len(),
+            );
+            // The values of the binary semaphores are ignored.
+            let mut wait_values = SmallVec4::from_elem(0, windows_to_render.len());
 
-                device
-                    .mesh_shader_loader()
-                    .cmd_draw_mesh_tasks(command_buffer, 1, 1, 1);
+            if let Some(upload_acquires) = &upload_acquires {
+                wait_semaphores.push(upload_acquires.semaphore);
+                wait_dst_stage_masks.push(vk::PipelineStageFlags::ALL_COMMANDS);
+                wait_values.push(upload_acquires.value);
             }
 
-            dynamic_rendering_loader.cmd_end_rendering(command_buffer);
-
-            synchronization2_loader.cmd_pipeline_barrier2(
-                command_buffer,
-                &vk::DependencyInfo::default().image_memory_barriers(slice::from_ref(
-                    &vk::ImageMemoryBarrier2::default()
-                        .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
-                        .src_access_mask(vk::AccessFlags2::COLOR_ATTACHMENT_WRITE)
-                        .dst_stage_mask(vk::PipelineStageFlags2::BOTTOM_OF_PIPE)
-                        .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-                        .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-                        .image(window.swap_chain_image)
-                        .subresource_range(
-                            vk::ImageSubresourceRange::default()
-                                .aspect_mask(vk::ImageAspectFlags::COLOR)
-                                .level_count(1)
-                                .layer_count(1),
-                        ),
-                )),
-            );
+            let mut signal_semaphores = SmallVec4::from_elem(*rendering_done_semaphore, 1);
+            let mut signal_values = SmallVec4::from_elem(0, 1);
 
-            device_loader.end_command_buffer(command_buffer).unwrap();
-
-            let direct_queue = **device.direct_queue();
+            if let Some(readback_value) = readback_manager.submit() {
+                signal_semaphores.push(**readback_manager.timeline_semaphore());
+                signal_values.push(readback_value);
+            }
 
             device_loader
                 .queue_submit(
                     direct_queue,
                     slice::from_ref(
                         &vk::SubmitInfo::default()
-                            .wait_semaphores(slice::from_ref(image_acquired_semaphore))
-                            .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
+                            .wait_semaphores(&wait_semaphores)
+                            .wait_dst_stage_mask(&wait_dst_stage_masks)
                             .command_buffers(slice::from_ref(&command_buffer))
-                            .signal_semaphores(slice::from_ref(rendering_done_semaphore)),
+                            .signal_semaphores(&signal_semaphores)
+                            .push_next(
+                                &mut vk::TimelineSemaphoreSubmitInfo::default()
+                                    .wait_semaphore_values(&wait_values)
+                                    .signal_semaphore_values(&signal_values),
+                            ),
                     ),
                     **fence,
                 )
                 .unwrap();
 
-            match device.swapchain_loader().queue_present(
+            let swapchains = windows_to_render
+                .iter()
+                .map(|window| *window_surfaces.surfaces[&window.entity].1)
+                .collect::<SmallVec4<_>>();
+            let image_indices = windows_to_render
+                .iter()
+                .map(|window| window.swap_chain_image_index)
+                .collect::<SmallVec4<_>>();
+            let mut results = SmallVec4::from_elem(vk::Result::SUCCESS, windows_to_render.len());
+
+            if let Err(result) = device.swapchain_loader().queue_present(
                 direct_queue,
                 &vk::PresentInfoKHR::default()
                     .wait_semaphores(slice::from_ref(rendering_done_semaphore))
-                    .swapchains(slice::from_ref(swapchain))
-                    .image_indices(slice::from_ref(&window.swap_chain_image_index)),
+                    .swapchains(&swapchains)
+                    .image_indices(&image_indices)
+                    .results(&mut results),
             ) {
-                Ok(is_suboptimal) => {
-                    if is_suboptimal {
-                        device.loader().device_wait_idle().unwrap();
-
-                        let _ = mem::replace(
-                            swapchain,
-                            Swapchain::new(
-                                instance.clone(),
-                                surface.clone(),
-                                device.clone(),
-                                window.present_mode,
-                                Some(swapchain),
-                            )
-                            .unwrap(),
-                        );
-                    }
+                if result != vk::Result::ERROR_OUT_OF_DATE_KHR {
+                    panic!("vkQueuePresentKHR failed");
                 }
-                Err(result) => {
-                    if result != vk::Result::ERROR_OUT_OF_DATE_KHR {
-                        panic!("vkQueuePresentKHR failed");
-                    }
+            }
+
+            // Out of date swapchains are recreated when acquiring the next image.
+            for (window, result) in windows_to_render.iter().zip(results) {
+                if result != vk::Result::SUBOPTIMAL_KHR {
+                    continue
                 }
+
+                let (surface, swapchain) =
+                    window_surfaces.surfaces.get_mut(&window.entity).unwrap();
+
+                // The old swapchain was presented to by this frame.
+                let old_swapchain = mem::replace(
+                    swapchain,
+                    Swapchain::new(
+                        instance.clone(),
+                        surface.clone(),
+                        device.clone(),
+                        window.present_mode,
+                        Some(swapchain),
+                    )
+                    .unwrap(),
+                );
+                deletion_queue.push(frame_index, old_swapchain);
             }
         }
     }
+
+    frame_ctx.increment();
+}
+
+/// Executes the [`RenderGraph`] and reads the [`OffscreenTarget`] back to the CPU.
+///
+/// Used instead of [`render_system`] in headless mode. Frames are read back once the device has
+/// finished them, which is `num_frames` frames after they were submitted.
+pub fn render_offscreen_system(
+    mut offscreen_target: ResMut<OffscreenTarget>,
+    sender: Res<OffscreenFrameSender>,
+    mut frame_ctx: ResMut<FrameCtx>,
+    mut graph: ResMut<RenderGraph>,
+    mut transient_pool: ResMut<TransientResourcePool>,
+    mut upload_manager: ResMut<UploadManager>,
+    mut readback_manager: ResMut<ReadbackManager>,
+    device: Res<Device>,
+) {
+    let frame_index = frame_ctx.frame_index();
+    let frame_offset = frame_ctx.frame_offset();
+    let frame = frame_ctx.current();
+
+    let device_loader = device.loader();
+
+    let queue_frame = frame.queue_frame(0);
+    let command_pool = **queue_frame.command_pool();
+    let command_buffer = **queue_frame.command_buffer();
+
+    unsafe {
+        let fence = frame.fence();
+        fence.wait_for(u64::MAX).unwrap();
+
+        if let Some(offscreen_frame) = offscreen_target.take_readback(frame_offset).unwrap() {
+            // Nobody is consuming the frames, so drop them instead of stalling.
+            let _ = sender.0.try_send(offscreen_frame);
+        }
+
+        fence.reset().unwrap();
+
+        device_loader
+            .reset_command_pool(command_pool, vk::CommandPoolResetFlags::empty())
+            .unwrap();
+
+        device_loader
+            .begin_command_buffer(
+                command_buffer,
+                &vk::CommandBufferBeginInfo::default()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            )
+            .unwrap();
+
+        let upload_acquires = upload_manager.take_acquires();
+        if let Some(upload_acquires) = &upload_acquires {
+            upload_acquires.record(&device, command_buffer);
+        }
+
+        graph
+            .execute(&device, command_buffer, &mut transient_pool, frame_index)
+            .unwrap();
+
+        device_loader.end_command_buffer(command_buffer).unwrap();
+
+        let (wait_semaphores, wait_values) = match &upload_acquires {
+            Some(upload_acquires) => (vec![upload_acquires.semaphore], vec![upload_acquires.value]),
+            None => (Vec::new(), Vec::new()),
+        };
+        let wait_dst_stage_masks =
+            vec![vk::PipelineStageFlags::ALL_COMMANDS; wait_semaphores.len()];
+        let (signal_semaphores, signal_values) = match readback_manager.submit() {
+            Some(readback_value) => {
+                (
+                    vec![**readback_manager.timeline_semaphore()],
+                    vec![readback_value],
+                )
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+
+        device_loader
+            .queue_submit(
+                **device.direct_queue(),
+                slice::from_ref(
+                    &vk::SubmitInfo::default()
+                        .wait_semaphores(&wait_semaphores)
+                        .wait_dst_stage_mask(&wait_dst_stage_masks)
+                        .command_buffers(slice::from_ref(&command_buffer))
+                        .signal_semaphores(&signal_semaphores)
+                        .push_next(
+                            &mut vk::TimelineSemaphoreSubmitInfo::default()
+                                .wait_semaphore_values(&wait_values)
+                                .signal_semaphore_values(&signal_values),
+                        ),
+                ),
+                **fence,
+            )
+            .unwrap();
+    }
+
+    offscreen_target.set_pending_readback(frame_offset, frame_index);
+
+    frame_ctx.increment();
+}
+
+/// The depth image is only used by the geometry pass, so its memory is shared with the other
+/// transient images of the frame.
+fn create_depth_image(graph: &mut RenderGraph, size: UVec2) -> ImageHandle {
+    graph.create_image(TransientImageDesc {
+        label: Cow::Borrowed("depth_image"),
+        format: DEPTH_FORMAT,
+        extent: Extent3D::new(size.x, size.y, 1),
+        aspect_mask: vk::ImageAspectFlags::DEPTH,
+        ..Default::default()
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_geometry_pass(
+    graph: &mut RenderGraph,
+    color_image: ImageHandle,
+    depth_image: ImageHandle,
+    size: UVec2,
+    pipeline_cache: &PipelineCache,
+    builtin_pipelines: &BuiltinPipelines,
+    camera: &ExtractedCamera,
+    depth_mode: DepthMode,
+) {
+    // Pipelines are only replaced before passes are added, so the handles stay valid.
+    let pipeline = pipeline_cache
+        .get_graphics_pipeline(&builtin_pipelines.geometry_pipeline)
+        .map(|pipeline| (**pipeline, pipeline.pipeline_layout().clone()));
+    let geometry_path = builtin_pipelines.geometry_path;
+    let viewport = camera.viewport.unwrap_or_default().to_physical(size);
+    let view_projection_matrix = camera.view_projection_matrix(&viewport);
+
+    graph
+        .add_pass("geometry")
+        .write_image(color_image, ImageUsage::ColorAttachment)
+        .write_image(depth_image, ImageUsage::DepthAttachment)
+        .execute(move |ctx| unsafe {
+            let device_loader = ctx.device.loader();
+            let dynamic_rendering_loader = ctx.device.dynamic_rendering_loader();
+            let command_buffer = ctx.command_buffer;
+
+            let color_attachment = vk::RenderingAttachmentInfo::default()
+                .image_view(ctx.image_view(color_image))
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .clear_value(vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: [100.0 / 255.0, 149.0 / 255.0, 237.0 / 255.0, 1.0],
+                    },
+                });
+
+            let depth_attachment = vk::RenderingAttachmentInfo::default()
+                .image_view(ctx.image_view(depth_image))
+                .image_layout(vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .clear_value(vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: depth_mode.clear_depth(),
+                        stencil: 0,
+                    },
+                });
+
+            let rendering_info = vk::RenderingInfo::default()
+                .render_area(
+                    vk::Rect2D::default()
+                        .extent(vk::Extent2D::default().width(size.x).height(size.y)),
+                )
+                .layer_count(1)
+                .color_attachments(slice::from_ref(&color_attachment))
+                .depth_attachment(&depth_attachment);
+
+            dynamic_rendering_loader.cmd_begin_rendering(command_buffer, &rendering_info);
+
+            let has_area = viewport.extent.width > 0 && viewport.extent.height > 0;
+
+            if has_area {
+                if let Some((pipeline, pipeline_layout)) = pipeline {
+                    device_loader.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+
+                    device_loader.cmd_set_viewport(
+                        command_buffer,
+                        0,
+                        slice::from_ref(
+                            &vk::Viewport::default()
+                                .x(viewport.offset.x as _)
+                                .y(viewport.offset.y as _)
+                                .width(viewport.extent.width as _)
+                                .height(viewport.extent.height as _)
+                                .max_depth(1.0),
+                        ),
+                    );
+                    device_loader.cmd_set_scissor(
+                        command_buffer,
+                        0,
+                        slice::from_ref(&viewport.into()),
+                    );
+
+                    // The size was checked when the pipeline was created.
+                    ctx.push_constants(&pipeline_layout, &view_projection_matrix)
+                        .unwrap();
+
+                    match geometry_path {
+                        GeometryPath::MeshShader => {
+                            ctx.device.mesh_shader_loader().cmd_draw_mesh_tasks(
+                                command_buffer,
+                                1,
+                                1,
+                                1,
+                            );
+                        }
+                        GeometryPath::Vertex => device_loader.cmd_draw(command_buffer, 3, 1, 0, 0),
+                    }
+                }
+            }
+
+            dynamic_rendering_loader.cmd_end_rendering(command_buffer);
+        });
 }
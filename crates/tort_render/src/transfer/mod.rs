@@ -0,0 +1,7 @@
+mod readback;
+mod staging;
+mod upload;
+
+pub use readback::*;
+pub use staging::*;
+pub use upload::*;
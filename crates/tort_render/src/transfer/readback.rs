@@ -0,0 +1,443 @@
+use std::{
+    borrow::Cow,
+    future::Future,
+    mem,
+    ops::Deref,
+    pin::Pin,
+    slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use ash::vk;
+use parking_lot::Mutex;
+use thiserror::Error;
+use tort_ecs::{
+    system::{ResMut, Resource},
+    {self as bevy_ecs},
+};
+use tort_utils::HashMap;
+use vk_mem_alloc::{AllocationCreateFlags, MemoryUsage};
+
+use crate::{
+    backend::{
+        resource::{Buffer, BufferDesc},
+        sync::{TimelineSemaphore, TimelineSemaphoreDesc},
+        utils::BackendError,
+        Device,
+    },
+    graph::{
+        BufferHandle, BufferUsage, ImageHandle, ImageUsage, ImportedBufferDesc, RenderGraph,
+        ResourceState,
+    },
+};
+
+/// Readback buffers are pooled by their size rounded up to a power of two, but at least this.
+const MIN_READBACK_BUFFER_SIZE: vk::DeviceSize = 64 * 1024;
+/// The number of unused buffers kept per size.
+const MAX_FREE_READBACK_BUFFERS: usize = 4;
+
+#[derive(Debug, Error)]
+pub enum ReadbackError {
+    /// The pass copying the data wasn't submitted, e.g. because no frame was rendered.
+    #[error("The readback was discarded before it was submitted")]
+    Discarded,
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+}
+
+/// A region of an image to read back.
+#[derive(Copy, Clone, Debug)]
+pub struct ImageReadback {
+    pub aspect_mask: vk::ImageAspectFlags,
+    pub mip_level: u32,
+    pub base_array_layer: u32,
+    pub layer_count: u32,
+    pub offset: vk::Offset3D,
+    pub extent: vk::Extent3D,
+    /// The size of a texel of the image format, the texels are read back tightly packed.
+    pub texel_size: u32,
+}
+
+impl ImageReadback {
+    #[inline]
+    pub fn size(&self) -> vk::DeviceSize {
+        self.extent.width as vk::DeviceSize
+            * self.extent.height as vk::DeviceSize
+            * self.extent.depth as vk::DeviceSize
+            * self.layer_count as vk::DeviceSize
+            * self.texel_size as vk::DeviceSize
+    }
+}
+
+#[derive(Default)]
+struct ReadbackPool {
+    free_buffers: Mutex<HashMap<vk::DeviceSize, Vec<Buffer>>>,
+}
+
+impl ReadbackPool {
+    fn acquire(&self, device: &Device, size: vk::DeviceSize) -> Result<Buffer, BackendError> {
+        let size = size.next_power_of_two().max(MIN_READBACK_BUFFER_SIZE);
+
+        if let Some(buffer) = self
+            .free_buffers
+            .lock()
+            .get_mut(&size)
+            .and_then(|buffers| buffers.pop())
+        {
+            return Ok(buffer)
+        }
+
+        Buffer::new(
+            device.clone(),
+            &BufferDesc {
+                label: Some(Cow::Borrowed("readback_buffer")),
+                size,
+                usage: vk::BufferUsageFlags::TRANSFER_DST,
+                allocation_flags: AllocationCreateFlags::HOST_ACCESS_RANDOM
+                    | AllocationCreateFlags::MAPPED,
+                memory_usage: MemoryUsage::AUTO_PREFER_HOST,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn recycle(&self, buffer: Buffer) {
+        let mut free_buffers = self.free_buffers.lock();
+        let buffers = free_buffers.entry(buffer.desc().size).or_default();
+
+        if buffers.len() < MAX_FREE_READBACK_BUFFERS {
+            buffers.push(buffer);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ReadbackStatus {
+    Pending,
+    /// Submitted with the timeline value the copy is complete at.
+    Submitted(u64),
+    Discarded,
+}
+
+struct ReadbackState {
+    status: ReadbackStatus,
+    /// Taken by the future once the copy is complete.
+    buffer: Option<Buffer>,
+    waker: Option<Waker>,
+}
+
+struct Readback {
+    size: usize,
+    /// Set when the copy is recorded by the graph.
+    recorded: AtomicBool,
+    state: Mutex<ReadbackState>,
+    timeline_semaphore: Arc<TimelineSemaphore>,
+    pool: Arc<ReadbackPool>,
+}
+
+impl Readback {
+    fn finish(&self, status: ReadbackStatus) {
+        let mut state = self.state.lock();
+        state.status = status;
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for Readback {
+    #[inline]
+    fn drop(&mut self) {
+        // The manager keeps the readback alive until the device is done with the buffer.
+        if let Some(buffer) = self.state.get_mut().buffer.take() {
+            self.pool.recycle(buffer);
+        }
+    }
+}
+
+/// Data read back from the device. The buffer holding it is recycled once this is dropped.
+pub struct ReadbackData {
+    buffer: Option<Buffer>,
+    size: usize,
+    pool: Arc<ReadbackPool>,
+}
+
+impl Deref for ReadbackData {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.buffer.as_ref().unwrap().mapped_slice().unwrap()[..self.size]
+    }
+}
+
+impl Drop for ReadbackData {
+    #[inline]
+    fn drop(&mut self) {
+        self.pool.recycle(self.buffer.take().unwrap());
+    }
+}
+
+/// Resolves once the submission copying the data has completed on the device.
+///
+/// Can be awaited on any thread, e.g. in a task spawned on the
+/// [`AsyncComputeTaskPool`](tort_tasks::AsyncComputeTaskPool). Waiting tasks are woken by
+/// [`ReadbackManager::poll_system`], so the future only makes progress while frames are rendered.
+pub struct ReadbackFuture {
+    readback: Arc<Readback>,
+}
+
+impl Future for ReadbackFuture {
+    type Output = Result<ReadbackData, ReadbackError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let readback = &self.readback;
+        let mut state = readback.state.lock();
+
+        match state.status {
+            ReadbackStatus::Pending => {}
+            ReadbackStatus::Discarded => return Poll::Ready(Err(ReadbackError::Discarded)),
+            ReadbackStatus::Submitted(value) => {
+                let completed_value =
+                    unsafe { readback.timeline_semaphore.value() }.map_err(BackendError::from)?;
+
+                if completed_value >= value {
+                    let buffer = state
+                        .buffer
+                        .take()
+                        .expect("ReadbackFuture polled after completion");
+                    buffer.invalidate(0, readback.size as vk::DeviceSize)?;
+
+                    return Poll::Ready(Ok(ReadbackData {
+                        buffer: Some(buffer),
+                        size: readback.size,
+                        pool: readback.pool.clone(),
+                    }))
+                }
+            }
+        }
+
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Reads data back from the device without blocking the render thread.
+///
+/// [`ReadbackManager::read_buffer`] and [`ReadbackManager::read_image`] add a pass copying the
+/// data into a pooled host visible buffer to the [`RenderGraph`]. The submission executing the
+/// graph signals the timeline value returned by [`ReadbackManager::submit`], which resolves the
+/// returned [`ReadbackFuture`].
+#[derive(Resource)]
+pub struct ReadbackManager {
+    timeline_semaphore: Arc<TimelineSemaphore>,
+    pool: Arc<ReadbackPool>,
+    /// Readbacks added to the graph that wasn't submitted yet.
+    pending: Vec<Arc<Readback>>,
+    in_flight: Vec<Arc<Readback>>,
+    submitted_value: u64,
+    device: Device,
+}
+
+impl ReadbackManager {
+    pub fn new(device: Device) -> Result<Self, BackendError> {
+        Ok(Self {
+            timeline_semaphore: Arc::new(TimelineSemaphore::new(
+                device.clone(),
+                &TimelineSemaphoreDesc {
+                    label: Some("readback_timeline_semaphore"),
+                    initial_value: 0,
+                },
+            )?),
+            pool: Arc::new(ReadbackPool::default()),
+            pending: Vec::new(),
+            in_flight: Vec::new(),
+            submitted_value: 0,
+            device,
+        })
+    }
+
+    #[inline]
+    pub fn timeline_semaphore(&self) -> &TimelineSemaphore {
+        &self.timeline_semaphore
+    }
+
+    /// Returns the number of readbacks that are not complete yet.
+    #[inline]
+    pub fn num_in_flight(&self) -> usize {
+        self.pending.len() + self.in_flight.len()
+    }
+
+    fn add_readback(
+        &mut self,
+        graph: &mut RenderGraph,
+        size: vk::DeviceSize,
+    ) -> Result<(Arc<Readback>, BufferHandle), BackendError> {
+        let buffer = self.pool.acquire(&self.device, size)?;
+
+        let readback_buffer = graph.import_buffer(ImportedBufferDesc {
+            label: Cow::Borrowed("readback_buffer"),
+            buffer: *buffer,
+            initial_state: ResourceState::default(),
+            final_state: Some(ResourceState::new(
+                vk::PipelineStageFlags2::HOST,
+                vk::AccessFlags2::HOST_READ,
+                vk::ImageLayout::UNDEFINED,
+            )),
+        });
+
+        let readback = Arc::new(Readback {
+            size: size as usize,
+            recorded: AtomicBool::new(false),
+            state: Mutex::new(ReadbackState {
+                status: ReadbackStatus::Pending,
+                buffer: Some(buffer),
+                waker: None,
+            }),
+            timeline_semaphore: self.timeline_semaphore.clone(),
+            pool: self.pool.clone(),
+        });
+        self.pending.push(readback.clone());
+
+        Ok((readback, readback_buffer))
+    }
+
+    /// Reads back `size` bytes of `buffer` starting at `offset` once the graph has executed.
+    pub fn read_buffer(
+        &mut self,
+        graph: &mut RenderGraph,
+        buffer: BufferHandle,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+    ) -> Result<ReadbackFuture, BackendError> {
+        let (readback, readback_buffer) = self.add_readback(graph, size)?;
+        let recorded = readback.clone();
+
+        graph
+            .add_pass("readback_buffer")
+            .read_buffer(buffer, BufferUsage::TransferSrc)
+            .write_buffer(readback_buffer, BufferUsage::TransferDst)
+            .side_effects()
+            .execute(move |ctx| {
+                unsafe {
+                    ctx.device.loader().cmd_copy_buffer(
+                        ctx.command_buffer,
+                        ctx.buffer(buffer),
+                        ctx.buffer(readback_buffer),
+                        slice::from_ref(
+                            &vk::BufferCopy::default()
+                                .src_offset(offset)
+                                .dst_offset(0)
+                                .size(size),
+                        ),
+                    );
+                }
+
+                recorded.recorded.store(true, Ordering::Release);
+            });
+
+        Ok(ReadbackFuture { readback })
+    }
+
+    /// Reads back a region of `image` once the graph has executed.
+    pub fn read_image(
+        &mut self,
+        graph: &mut RenderGraph,
+        image: ImageHandle,
+        region: &ImageReadback,
+    ) -> Result<ReadbackFuture, BackendError> {
+        let (readback, readback_buffer) = self.add_readback(graph, region.size())?;
+        let recorded = readback.clone();
+        let region = *region;
+
+        graph
+            .add_pass("readback_image")
+            .read_image(image, ImageUsage::TransferSrc)
+            .write_buffer(readback_buffer, BufferUsage::TransferDst)
+            .side_effects()
+            .execute(move |ctx| {
+                unsafe {
+                    ctx.device.loader().cmd_copy_image_to_buffer(
+                        ctx.command_buffer,
+                        ctx.image(image),
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        ctx.buffer(readback_buffer),
+                        slice::from_ref(
+                            &vk::BufferImageCopy::default()
+                                .image_subresource(
+                                    vk::ImageSubresourceLayers::default()
+                                        .aspect_mask(region.aspect_mask)
+                                        .mip_level(region.mip_level)
+                                        .base_array_layer(region.base_array_layer)
+                                        .layer_count(region.layer_count),
+                                )
+                                .image_offset(region.offset)
+                                .image_extent(region.extent),
+                        ),
+                    );
+                }
+
+                recorded.recorded.store(true, Ordering::Release);
+            });
+
+        Ok(ReadbackFuture { readback })
+    }
+
+    /// Must be called after the graph was executed and before its submission, which has to
+    /// signal the timeline semaphore with the returned value if any.
+    ///
+    /// Readbacks whose copy wasn't recorded are discarded.
+    pub fn submit(&mut self) -> Option<u64> {
+        let value = self.submitted_value + 1;
+        let mut submitted = false;
+
+        for readback in mem::take(&mut self.pending) {
+            if readback.recorded.load(Ordering::Acquire) {
+                readback.state.lock().status = ReadbackStatus::Submitted(value);
+                self.in_flight.push(readback);
+                submitted = true;
+            } else {
+                readback.finish(ReadbackStatus::Discarded);
+            }
+        }
+
+        if !submitted {
+            return None
+        }
+
+        self.submitted_value = value;
+        Some(value)
+    }
+
+    /// Wakes the tasks waiting for completed readbacks.
+    pub fn poll(&mut self) -> Result<(), BackendError> {
+        if self.in_flight.is_empty() {
+            return Ok(())
+        }
+
+        let completed_value = unsafe { self.timeline_semaphore.value() }?;
+
+        self.in_flight.retain(|readback| {
+            let status = readback.state.lock().status;
+
+            match status {
+                ReadbackStatus::Submitted(value) if value <= completed_value => {
+                    readback.finish(status);
+                    false
+                }
+                _ => true,
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn poll_system(mut readback_manager: ResMut<ReadbackManager>) {
+        readback_manager.poll().unwrap();
+    }
+}
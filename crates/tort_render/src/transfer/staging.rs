@@ -0,0 +1,137 @@
+use std::{borrow::Cow, collections::VecDeque};
+
+use ash::vk;
+use vk_mem_alloc::{AllocationCreateFlags, MemoryUsage};
+
+use crate::backend::{
+    resource::{Buffer, BufferDesc},
+    utils::BackendError,
+    Device,
+};
+
+#[inline]
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+/// Creates a persistently mapped buffer the host writes data to before it is copied on the device.
+pub(crate) fn create_staging_buffer(
+    device: Device,
+    label: &'static str,
+    size: vk::DeviceSize,
+) -> Result<Buffer, BackendError> {
+    Buffer::new(
+        device,
+        &BufferDesc {
+            label: Some(Cow::Borrowed(label)),
+            size,
+            usage: vk::BufferUsageFlags::TRANSFER_SRC,
+            allocation_flags: AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE
+                | AllocationCreateFlags::MAPPED,
+            memory_usage: MemoryUsage::AUTO_PREFER_HOST,
+            ..Default::default()
+        },
+    )
+}
+
+/// A staging buffer used as a ring.
+///
+/// Regions are handed out in order and given back once the timeline value of the batch that
+/// used them has been reached.
+pub struct StagingRing {
+    buffer: Buffer,
+    size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    /// Offsets grow monotonically, the offset into the buffer is `offset % size`.
+    head: vk::DeviceSize,
+    tail: vk::DeviceSize,
+    /// The end of the regions of every submitted batch and the value it completes at.
+    retired: VecDeque<(vk::DeviceSize, u64)>,
+}
+
+impl StagingRing {
+    pub fn new(device: Device, size: vk::DeviceSize) -> Result<Self, BackendError> {
+        let limits = &device.properties().properties.limits;
+        // Buffer to image copies need offsets aligned to the texel size, 16 covers all formats.
+        let alignment = limits
+            .optimal_buffer_copy_offset_alignment
+            .max(limits.non_coherent_atom_size)
+            .max(16);
+
+        Ok(Self {
+            buffer: create_staging_buffer(device, "staging_ring", size)?,
+            size,
+            alignment,
+            head: 0,
+            tail: 0,
+            retired: VecDeque::new(),
+        })
+    }
+
+    #[inline]
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    #[inline]
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    /// Copies `data` into the ring, returning its offset in the buffer, or `None` if there is
+    /// not enough free space.
+    pub fn push(&mut self, data: &[u8]) -> Result<Option<vk::DeviceSize>, BackendError> {
+        let len = data.len() as vk::DeviceSize;
+
+        if len > self.size {
+            return Ok(None)
+        }
+
+        let mut start = align_up(self.head, self.alignment);
+
+        // Regions never wrap around the end of the buffer.
+        if start % self.size + len > self.size {
+            start = align_up(start, self.size);
+        }
+
+        if start + len - self.tail > self.size {
+            return Ok(None)
+        }
+
+        self.head = start + len;
+
+        let offset = start % self.size;
+        let range = offset as usize..(offset + len) as usize;
+        self.buffer.mapped_slice_mut().unwrap()[range].copy_from_slice(data);
+        self.buffer.flush(offset, len)?;
+
+        Ok(Some(offset))
+    }
+
+    /// Marks everything pushed since the last call as used by the batch completing at `value`.
+    #[inline]
+    pub fn retire(&mut self, value: u64) {
+        if self.retired.back().map(|(end, _)| *end) != Some(self.head) && self.head != self.tail {
+            self.retired.push_back((self.head, value));
+        }
+    }
+
+    /// Gives back the regions of all batches completed at `completed_value`.
+    #[inline]
+    pub fn reclaim(&mut self, completed_value: u64) {
+        while let Some((end, value)) = self.retired.front().copied() {
+            if value > completed_value {
+                break
+            }
+
+            self.tail = end;
+            self.retired.pop_front();
+        }
+    }
+
+    /// Returns the value of the oldest batch still using the ring.
+    #[inline]
+    pub fn oldest_value(&self) -> Option<u64> {
+        self.retired.front().map(|(_, value)| *value)
+    }
+}
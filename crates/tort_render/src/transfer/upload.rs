@@ -0,0 +1,520 @@
+use std::{collections::VecDeque, slice};
+
+use ash::{prelude::VkResult, vk};
+use log::debug;
+use tort_ecs::{
+    system::{ResMut, Resource},
+    {self as bevy_ecs},
+};
+
+use crate::{
+    backend::{
+        command::{CommandBuffer, CommandBufferDesc, CommandPool, CommandPoolDesc},
+        resource::Buffer,
+        sync::{TimelineSemaphore, TimelineSemaphoreDesc},
+        utils::BackendError,
+        Device,
+    },
+    graph::ResourceState,
+    transfer::{create_staging_buffer, StagingRing},
+};
+
+/// The timeline value an upload is complete at.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UploadTicket(pub u64);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UploadManagerDesc {
+    pub staging_size: vk::DeviceSize,
+}
+
+impl Default for UploadManagerDesc {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            staging_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// A region of an image to upload.
+#[derive(Copy, Clone, Debug)]
+pub struct ImageUpload<'a> {
+    pub image: vk::Image,
+    pub aspect_mask: vk::ImageAspectFlags,
+    pub mip_level: u32,
+    pub base_array_layer: u32,
+    pub layer_count: u32,
+    pub offset: vk::Offset3D,
+    pub extent: vk::Extent3D,
+    /// Tightly packed texels of the region.
+    pub data: &'a [u8],
+    /// The layout the subresources are in before the upload. [`vk::ImageLayout::UNDEFINED`]
+    /// discards their contents, so it may only be used if the region covers them entirely.
+    ///
+    /// Preserving the contents of an image with exclusive sharing across queue families
+    /// requires its ownership to be released to the transfer queue family beforehand.
+    pub src_layout: vk::ImageLayout,
+    /// The first use of the image on the direct queue and the layout it is transitioned to.
+    pub dst_state: ResourceState,
+}
+
+/// A region of a buffer to upload.
+#[derive(Copy, Clone, Debug)]
+pub struct BufferUpload<'a> {
+    pub buffer: vk::Buffer,
+    pub offset: vk::DeviceSize,
+    pub data: &'a [u8],
+    /// The first use of the buffer on the direct queue.
+    pub dst_state: ResourceState,
+}
+
+/// The barriers acquiring the ownership of uploaded resources on the direct queue, and the
+/// timeline value the direct queue has to wait for before executing them.
+#[derive(Default)]
+pub struct UploadAcquires {
+    pub semaphore: vk::Semaphore,
+    pub value: u64,
+    pub image_barriers: Vec<vk::ImageMemoryBarrier2<'static>>,
+    pub buffer_barriers: Vec<vk::BufferMemoryBarrier2<'static>>,
+}
+
+impl UploadAcquires {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.image_barriers.is_empty() && self.buffer_barriers.is_empty()
+    }
+
+    /// Records the barriers into a command buffer of the direct queue.
+    pub unsafe fn record(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        if self.is_empty() {
+            return
+        }
+
+        device.synchronization2_loader().cmd_pipeline_barrier2(
+            command_buffer,
+            &vk::DependencyInfo::default()
+                .image_memory_barriers(&self.image_barriers)
+                .buffer_memory_barriers(&self.buffer_barriers),
+        );
+    }
+}
+
+struct UploadBatch {
+    command_pool: CommandPool,
+    command_buffer: CommandBuffer,
+    value: u64,
+    /// Staging buffers of uploads that didn't fit into the ring.
+    dedicated_staging_buffers: Vec<Buffer>,
+    image_acquires: Vec<vk::ImageMemoryBarrier2<'static>>,
+    buffer_acquires: Vec<vk::BufferMemoryBarrier2<'static>>,
+}
+
+/// Uploads data to device local resources on the transfer queue.
+///
+/// Uploads are recorded into a batch that is submitted by [`UploadManager::flush`], which
+/// signals the timeline value of the returned [`UploadTicket`]s. The host can poll or wait for
+/// them, while the direct queue waits for them on the device with the barriers returned by
+/// [`UploadManager::take_acquires`], which also acquire the ownership of the resources if the
+/// transfer queue is of another family.
+#[derive(Resource)]
+pub struct UploadManager {
+    staging_ring: StagingRing,
+    timeline_semaphore: TimelineSemaphore,
+    recording: Option<UploadBatch>,
+    in_flight: VecDeque<UploadBatch>,
+    free_command_buffers: Vec<(CommandPool, CommandBuffer)>,
+    /// The value of the last submitted batch.
+    submitted_value: u64,
+    /// The value the acquires of the direct queue were taken at.
+    acquired_value: u64,
+    image_acquires: Vec<vk::ImageMemoryBarrier2<'static>>,
+    buffer_acquires: Vec<vk::BufferMemoryBarrier2<'static>>,
+    device: Device,
+}
+
+unsafe impl Send for UploadManager {}
+unsafe impl Sync for UploadManager {}
+
+impl UploadManager {
+    pub fn new(device: Device, desc: &UploadManagerDesc) -> Result<Self, BackendError> {
+        Ok(Self {
+            staging_ring: StagingRing::new(device.clone(), desc.staging_size)?,
+            timeline_semaphore: TimelineSemaphore::new(
+                device.clone(),
+                &TimelineSemaphoreDesc {
+                    label: Some("upload_timeline_semaphore"),
+                    initial_value: 0,
+                },
+            )?,
+            recording: None,
+            in_flight: VecDeque::new(),
+            free_command_buffers: Vec::new(),
+            submitted_value: 0,
+            acquired_value: 0,
+            image_acquires: Vec::new(),
+            buffer_acquires: Vec::new(),
+            device,
+        })
+    }
+
+    #[inline]
+    pub fn timeline_semaphore(&self) -> &TimelineSemaphore {
+        &self.timeline_semaphore
+    }
+
+    #[inline]
+    pub fn completed_value(&self) -> VkResult<u64> {
+        unsafe { self.timeline_semaphore.value() }
+    }
+
+    #[inline]
+    pub fn is_complete(&self, ticket: UploadTicket) -> VkResult<bool> {
+        Ok(self.completed_value()? >= ticket.0)
+    }
+
+    /// Blocks until the upload of `ticket` is complete. Submits the pending uploads if needed.
+    pub fn wait(&mut self, ticket: UploadTicket, timeout: u64) -> Result<(), BackendError> {
+        if ticket.0 > self.submitted_value {
+            self.flush()?;
+        }
+
+        unsafe { self.timeline_semaphore.wait_for_value(ticket.0, timeout) }?;
+        self.reclaim()
+    }
+
+    #[inline]
+    fn queue_family_indices(&self) -> (u32, u32) {
+        (
+            self.device.transfer_queue().family_index(),
+            self.device.direct_queue().family_index(),
+        )
+    }
+
+    fn reclaim(&mut self) -> Result<(), BackendError> {
+        let completed_value = self.completed_value()?;
+
+        self.staging_ring.reclaim(completed_value);
+
+        while let Some(batch) = self.in_flight.front() {
+            if batch.value > completed_value {
+                break
+            }
+
+            let batch = self.in_flight.pop_front().unwrap();
+            self.free_command_buffers
+                .push((batch.command_pool, batch.command_buffer));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the batch recording the uploads, beginning a new one if needed.
+    fn recording(&mut self) -> Result<&mut UploadBatch, BackendError> {
+        if self.recording.is_none() {
+            let (command_pool, command_buffer) = match self.free_command_buffers.pop() {
+                Some((command_pool, command_buffer)) => {
+                    unsafe {
+                        self.device
+                            .loader()
+                            .reset_command_pool(*command_pool, vk::CommandPoolResetFlags::empty())
+                    }?;
+
+                    (command_pool, command_buffer)
+                }
+                None => {
+                    let command_pool = CommandPool::new(
+                        self.device.clone(),
+                        &CommandPoolDesc {
+                            label: Some("upload_command_pool"),
+                            flags: vk::CommandPoolCreateFlags::TRANSIENT,
+                            family_index: self.device.transfer_queue().family_index(),
+                        },
+                    )?;
+                    let command_buffer = CommandBuffer::new(
+                        self.device.clone(),
+                        command_pool.clone(),
+                        &CommandBufferDesc {
+                            label: Some("upload_command_buffer"),
+                        },
+                    )?;
+
+                    (command_pool, command_buffer)
+                }
+            };
+
+            unsafe {
+                self.device.loader().begin_command_buffer(
+                    *command_buffer,
+                    &vk::CommandBufferBeginInfo::default()
+                        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+                )
+            }?;
+
+            self.recording = Some(UploadBatch {
+                command_pool,
+                command_buffer,
+                value: self.submitted_value + 1,
+                dedicated_staging_buffers: Vec::new(),
+                image_acquires: Vec::new(),
+                buffer_acquires: Vec::new(),
+            });
+        }
+
+        Ok(self.recording.as_mut().unwrap())
+    }
+
+    /// Copies `data` into a staging buffer, returning the buffer and the offset into it.
+    fn stage(&mut self, data: &[u8]) -> Result<(vk::Buffer, vk::DeviceSize), BackendError> {
+        loop {
+            if let Some(offset) = self.staging_ring.push(data)? {
+                return Ok((**self.staging_ring.buffer(), offset))
+            }
+
+            if data.len() as vk::DeviceSize > self.staging_ring.size() / 2 {
+                break
+            }
+
+            // The ring is full, so the oldest batch using it has to finish.
+            self.reclaim()?;
+
+            if let Some(value) = self.staging_ring.oldest_value() {
+                debug!("Staging ring is full, waiting for upload {}", value);
+                unsafe { self.timeline_semaphore.wait_for_value(value, u64::MAX) }?;
+                self.reclaim()?;
+            } else if self.recording.is_some() {
+                self.flush()?;
+            } else {
+                break
+            }
+        }
+
+        // Large uploads get their own staging buffer instead of flushing the ring.
+        let mut staging_buffer = create_staging_buffer(
+            self.device.clone(),
+            "dedicated_staging_buffer",
+            data.len() as vk::DeviceSize,
+        )?;
+        staging_buffer
+            .mapped_slice_mut()
+            .unwrap()
+            .copy_from_slice(data);
+        staging_buffer.flush(0, vk::WHOLE_SIZE)?;
+
+        let buffer = *staging_buffer;
+        self.recording()?
+            .dedicated_staging_buffers
+            .push(staging_buffer);
+
+        Ok((buffer, 0))
+    }
+
+    pub fn upload_buffer(&mut self, upload: &BufferUpload) -> Result<UploadTicket, BackendError> {
+        let (staging_buffer, staging_offset) = self.stage(upload.data)?;
+        let (src_family_index, dst_family_index) = self.queue_family_indices();
+        let device = self.device.clone();
+        let batch = self.recording()?;
+
+        unsafe {
+            device.loader().cmd_copy_buffer(
+                *batch.command_buffer,
+                staging_buffer,
+                upload.buffer,
+                slice::from_ref(
+                    &vk::BufferCopy::default()
+                        .src_offset(staging_offset)
+                        .dst_offset(upload.offset)
+                        .size(upload.data.len() as vk::DeviceSize),
+                ),
+            );
+        }
+
+        let size = upload.data.len() as vk::DeviceSize;
+
+        // The timeline semaphore makes the copy visible if no ownership has to be transferred.
+        if src_family_index != dst_family_index {
+            let release = vk::BufferMemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .src_queue_family_index(src_family_index)
+                .dst_queue_family_index(dst_family_index)
+                .buffer(upload.buffer)
+                .offset(upload.offset)
+                .size(size);
+
+            unsafe {
+                device.synchronization2_loader().cmd_pipeline_barrier2(
+                    *batch.command_buffer,
+                    &vk::DependencyInfo::default()
+                        .buffer_memory_barriers(slice::from_ref(&release)),
+                );
+            }
+
+            batch.buffer_acquires.push(
+                vk::BufferMemoryBarrier2::default()
+                    .dst_stage_mask(upload.dst_state.stage)
+                    .dst_access_mask(upload.dst_state.access)
+                    .src_queue_family_index(src_family_index)
+                    .dst_queue_family_index(dst_family_index)
+                    .buffer(upload.buffer)
+                    .offset(upload.offset)
+                    .size(size),
+            );
+        }
+
+        Ok(UploadTicket(batch.value))
+    }
+
+    /// Uploads a region of an image.
+    ///
+    /// The image must not be in use on the direct queue until the upload completes, the
+    /// transfer queue doesn't synchronize with previous work on it.
+    pub fn upload_image(&mut self, upload: &ImageUpload) -> Result<UploadTicket, BackendError> {
+        let (staging_buffer, staging_offset) = self.stage(upload.data)?;
+        let (src_family_index, dst_family_index) = self.queue_family_indices();
+        let device = self.device.clone();
+        let batch = self.recording()?;
+
+        let subresource_range = vk::ImageSubresourceRange::default()
+            .aspect_mask(upload.aspect_mask)
+            .base_mip_level(upload.mip_level)
+            .level_count(1)
+            .base_array_layer(upload.base_array_layer)
+            .layer_count(upload.layer_count);
+
+        let release = vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::COPY)
+            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(upload.dst_state.layout)
+            .image(upload.image)
+            .subresource_range(subresource_range);
+
+        let release = if src_family_index != dst_family_index {
+            release
+                .src_queue_family_index(src_family_index)
+                .dst_queue_family_index(dst_family_index)
+        } else {
+            release
+        };
+
+        unsafe {
+            let synchronization2_loader = device.synchronization2_loader();
+
+            // The contents outside of the region are only kept if the image wasn't undefined.
+            synchronization2_loader.cmd_pipeline_barrier2(
+                *batch.command_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(slice::from_ref(
+                    &vk::ImageMemoryBarrier2::default()
+                        .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+                        .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                        .old_layout(upload.src_layout)
+                        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                        .image(upload.image)
+                        .subresource_range(subresource_range),
+                )),
+            );
+
+            device.loader().cmd_copy_buffer_to_image(
+                *batch.command_buffer,
+                staging_buffer,
+                upload.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                slice::from_ref(
+                    &vk::BufferImageCopy::default()
+                        .buffer_offset(staging_offset)
+                        .image_subresource(
+                            vk::ImageSubresourceLayers::default()
+                                .aspect_mask(upload.aspect_mask)
+                                .mip_level(upload.mip_level)
+                                .base_array_layer(upload.base_array_layer)
+                                .layer_count(upload.layer_count),
+                        )
+                        .image_offset(upload.offset)
+                        .image_extent(upload.extent),
+                ),
+            );
+
+            // Transitions the image into its final layout, which has to be repeated by the
+            // acquire if the ownership is transferred.
+            synchronization2_loader.cmd_pipeline_barrier2(
+                *batch.command_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(slice::from_ref(&release)),
+            );
+        }
+
+        if src_family_index != dst_family_index {
+            batch.image_acquires.push(
+                vk::ImageMemoryBarrier2::default()
+                    .dst_stage_mask(upload.dst_state.stage)
+                    .dst_access_mask(upload.dst_state.access)
+                    .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .new_layout(upload.dst_state.layout)
+                    .src_queue_family_index(src_family_index)
+                    .dst_queue_family_index(dst_family_index)
+                    .image(upload.image)
+                    .subresource_range(subresource_range),
+            );
+        }
+
+        Ok(UploadTicket(batch.value))
+    }
+
+    /// Submits the recorded uploads to the transfer queue.
+    pub fn flush(&mut self) -> Result<(), BackendError> {
+        self.reclaim()?;
+
+        let Some(mut batch) = self.recording.take() else {
+            return Ok(())
+        };
+
+        let device_loader = self.device.loader();
+
+        unsafe {
+            device_loader.end_command_buffer(*batch.command_buffer)?;
+
+            device_loader.queue_submit(
+                **self.device.transfer_queue(),
+                slice::from_ref(
+                    &vk::SubmitInfo::default()
+                        .command_buffers(slice::from_ref(&*batch.command_buffer))
+                        .signal_semaphores(slice::from_ref(&*self.timeline_semaphore))
+                        .push_next(
+                            &mut vk::TimelineSemaphoreSubmitInfo::default()
+                                .signal_semaphore_values(slice::from_ref(&batch.value)),
+                        ),
+                ),
+                vk::Fence::null(),
+            )?;
+        }
+
+        self.staging_ring.retire(batch.value);
+        self.submitted_value = batch.value;
+        self.image_acquires.append(&mut batch.image_acquires);
+        self.buffer_acquires.append(&mut batch.buffer_acquires);
+        self.in_flight.push_back(batch);
+
+        Ok(())
+    }
+
+    /// Returns what the direct queue has to wait for and record before using the uploads
+    /// submitted since the last call.
+    pub fn take_acquires(&mut self) -> Option<UploadAcquires> {
+        if self.acquired_value == self.submitted_value {
+            return None
+        }
+
+        self.acquired_value = self.submitted_value;
+
+        Some(UploadAcquires {
+            semaphore: *self.timeline_semaphore,
+            value: self.submitted_value,
+            image_barriers: self.image_acquires.drain(..).collect(),
+            buffer_barriers: self.buffer_acquires.drain(..).collect(),
+        })
+    }
+
+    pub fn flush_system(mut upload_manager: ResMut<UploadManager>) {
+        upload_manager.flush().unwrap();
+    }
+}
@@ -6,19 +6,58 @@ use tort_ecs::{
     system::{Commands, Query, Res, ResMut, Resource},
 };
 use tort_input::{keyboard::KeyCode, mouse::MouseMotion, Input};
-use tort_math::{Mat4, Vec2, Vec3};
+use tort_math::{Mat4, UVec2, Vec2, Vec3};
 use tort_time::Time;
 use tort_window::{PrimaryWindow, Window};
 
-use crate::Extract;
+use crate::{backend::utils::Rect2D, renderer::DepthMode, Extract};
+
+/// A sub-rectangle of a render target in normalized coordinates,
+/// where `(0, 0)` is the top left and `(1, 1)` the bottom right corner.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NormalizedViewport {
+    pub position: Vec2,
+    pub size: Vec2,
+}
+
+impl Default for NormalizedViewport {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            size: Vec2::ONE,
+        }
+    }
+}
+
+impl NormalizedViewport {
+    #[inline]
+    pub fn new(position: Vec2, size: Vec2) -> Self {
+        Self { position, size }
+    }
+
+    /// Converts the viewport into pixels of a render target with the given physical size.
+    pub fn to_physical(&self, target_size: UVec2) -> Rect2D {
+        let target_size = target_size.as_vec2();
+
+        let min = (self.position.clamp(Vec2::ZERO, Vec2::ONE) * target_size).round();
+        let max = ((self.position + self.size).clamp(Vec2::ZERO, Vec2::ONE) * target_size).round();
+        let size = (max - min).max(Vec2::ZERO);
+
+        Rect2D::new(min.x as _, min.y as _, size.x as _, size.y as _)
+    }
+}
 
 #[derive(Resource)]
 pub struct Camera {
     camera_rig: CameraRig<LeftHanded>,
     field_of_view: f32,
     window_size: Vec2,
+    target_size: Option<UVec2>,
+    viewport: Option<NormalizedViewport>,
     near_plane: f32,
     far_plane: f32,
+    depth_mode: DepthMode,
 
     sensitivity: Vec2,
     speed: f32,
@@ -32,7 +71,6 @@ impl Camera {
     pub fn new(
         position: Vec3,
         field_of_view: f32,
-        window_size: Vec2,
         near_plane: f32,
         far_plane: f32,
         sensitivity: Vec2,
@@ -47,9 +85,12 @@ impl Camera {
         Self {
             camera_rig,
             field_of_view: field_of_view.to_radians(),
-            window_size,
+            window_size: Vec2::ONE,
+            target_size: None,
+            viewport: None,
             near_plane,
             far_plane,
+            depth_mode: DepthMode::default(),
             sensitivity,
             speed,
 
@@ -62,12 +103,20 @@ impl Camera {
     pub fn update(&mut self) {
         let final_transform = &self.camera_rig.final_transform;
 
-        self.projection_matrix = Mat4::perspective_lh(
-            self.field_of_view,
-            self.window_size.x / self.window_size.y,
-            self.near_plane,
-            self.far_plane,
-        );
+        let viewport = self
+            .viewport
+            .unwrap_or_default()
+            .to_physical(self.target_size());
+
+        // Minimized windows have no area, so the last projection is kept.
+        if viewport.extent.width > 0 && viewport.extent.height > 0 {
+            self.projection_matrix = self.depth_mode.perspective(
+                self.field_of_view,
+                viewport.extent.width as f32 / viewport.extent.height as f32,
+                self.near_plane,
+                self.far_plane,
+            );
+        }
         self.view_matrix = Mat4::look_at_lh(
             final_transform.position,
             final_transform.position + final_transform.forward(),
@@ -102,6 +151,32 @@ impl Camera {
         self.window_size = window_size;
     }
 
+    /// The physical size of the render target, which is either the explicit size
+    /// set with [`Camera::set_target_size`] or the physical size of the primary window.
+    ///
+    /// Only the matrices of the main world camera use it, the renderer builds the projection of
+    /// every render target from its own size, see [`ExtractedCamera::view_projection_matrix`].
+    #[inline]
+    pub fn target_size(&self) -> UVec2 {
+        self.target_size
+            .unwrap_or_else(|| self.window_size.as_uvec2())
+    }
+
+    #[inline]
+    pub fn set_target_size(&mut self, target_size: Option<UVec2>) {
+        self.target_size = target_size;
+    }
+
+    #[inline]
+    pub fn viewport(&self) -> Option<NormalizedViewport> {
+        self.viewport
+    }
+
+    #[inline]
+    pub fn set_viewport(&mut self, viewport: Option<NormalizedViewport>) {
+        self.viewport = viewport;
+    }
+
     #[inline]
     pub fn near_plane(&self) -> f32 {
         self.near_plane
@@ -122,6 +197,16 @@ impl Camera {
         self.far_plane = far_plane;
     }
 
+    #[inline]
+    pub fn depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    #[inline]
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        self.depth_mode = depth_mode;
+    }
+
     #[inline]
     pub fn sensitivity(&self) -> Vec2 {
         self.sensitivity
@@ -165,9 +250,13 @@ pub fn update_camera_system(
     keys: Res<Input<KeyCode>>,
     time: Res<Time>,
 ) {
-    let window_size = &window.single().1.resolution;
-
-    camera.set_window_size(Vec2::new(window_size.width(), window_size.height()));
+    // There is no primary window when rendering headless.
+    if let Ok((_, window, _)) = window.get_single() {
+        camera.set_window_size(Vec2::new(
+            window.resolution.physical_width() as f32,
+            window.resolution.physical_height() as f32,
+        ));
+    }
 
     let mut delta_pos = Vec3::ZERO;
     if keys.pressed(KeyCode::W) {
@@ -215,16 +304,44 @@ pub fn update_camera_system(
     camera.update();
 }
 
+/// The camera in the render world. The projection is built per render target, since every
+/// window has its own aspect ratio.
 #[derive(Resource)]
 pub struct ExtractedCamera {
-    pub view_projection_matrix: Mat4,
+    pub view_matrix: Mat4,
+    /// In radians.
+    pub field_of_view: f32,
+    pub near_plane: f32,
+    pub far_plane: f32,
+    pub depth_mode: DepthMode,
+    pub viewport: Option<NormalizedViewport>,
+}
+
+impl ExtractedCamera {
+    /// The view projection matrix for the physical `viewport` of a render target.
+    pub fn view_projection_matrix(&self, viewport: &Rect2D) -> Mat4 {
+        let aspect_ratio =
+            viewport.extent.width.max(1) as f32 / viewport.extent.height.max(1) as f32;
+
+        self.depth_mode.perspective(
+            self.field_of_view,
+            aspect_ratio,
+            self.near_plane,
+            self.far_plane,
+        ) * self.view_matrix
+    }
 }
 
 impl From<&Camera> for ExtractedCamera {
     #[inline]
     fn from(camera: &Camera) -> Self {
         Self {
-            view_projection_matrix: *camera.view_projection_matrix(),
+            view_matrix: *camera.view_matrix(),
+            field_of_view: camera.field_of_view,
+            near_plane: camera.near_plane(),
+            far_plane: camera.far_plane(),
+            depth_mode: camera.depth_mode(),
+            viewport: camera.viewport(),
         }
     }
 }
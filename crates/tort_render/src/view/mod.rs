@@ -1,5 +1,7 @@
 mod camera;
+mod offscreen;
 mod window;
 
 pub use camera::*;
+pub use offscreen::*;
 pub use window::*;
@@ -0,0 +1,167 @@
+use std::borrow::Cow;
+
+use ash::vk;
+use async_channel::{Receiver, Sender};
+use tort_ecs::{self as bevy_ecs, system::Resource};
+use vk_mem_alloc::{AllocationCreateFlags, MemoryUsage};
+
+use crate::backend::{
+    resource::{Buffer, BufferDesc, Image, ImageDesc, ImageView, ImageViewDesc},
+    utils::{BackendError, Extent3D},
+    Device,
+};
+
+pub const OFFSCREEN_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// The size of the offscreen image rendered to in headless mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HeadlessDesc {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A frame rendered in headless mode, read back as tightly packed [`OFFSCREEN_FORMAT`] pixels.
+#[derive(Clone, Debug)]
+pub struct OffscreenFrame {
+    pub frame_index: usize,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// Channel to send read back frames from the render world to the main world.
+#[derive(Resource)]
+pub struct OffscreenFrameSender(pub Sender<OffscreenFrame>);
+
+/// Channel to receive read back frames from the render world in the main world.
+#[derive(Resource)]
+pub struct OffscreenFrameReceiver(pub Receiver<OffscreenFrame>);
+
+pub fn create_offscreen_frame_channels() -> (OffscreenFrameSender, OffscreenFrameReceiver) {
+    // Frames are dropped instead of blocking the renderer if nobody consumes them.
+    let (sender, receiver) = async_channel::bounded(4);
+    (
+        OffscreenFrameSender(sender),
+        OffscreenFrameReceiver(receiver),
+    )
+}
+
+#[derive(Resource)]
+pub struct OffscreenTarget {
+    color_image: Image,
+    color_image_view: ImageView,
+    readback_buffers: Vec<Buffer>,
+    pending_readbacks: Vec<Option<usize>>,
+    desc: HeadlessDesc,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: &Device,
+        desc: HeadlessDesc,
+        num_frames: usize,
+    ) -> Result<Self, BackendError> {
+        let color_image = Image::new(
+            device.clone(),
+            &ImageDesc {
+                label: Some(Cow::Borrowed("offscreen_color_image")),
+                image_type: vk::ImageType::TYPE_2D,
+                format: OFFSCREEN_FORMAT,
+                extent: Extent3D::new(desc.width, desc.height, 1),
+                mip_levels: 1,
+                array_layers: 1,
+                samples: vk::SampleCountFlags::TYPE_1,
+                tiling: vk::ImageTiling::OPTIMAL,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                memory_usage: MemoryUsage::AUTO_PREFER_DEVICE,
+                ..Default::default()
+            },
+        )?;
+
+        let color_image_view = ImageView::new(
+            device.clone(),
+            *color_image,
+            &ImageViewDesc {
+                label: Some(Cow::Borrowed("offscreen_color_image_view")),
+                view_type: vk::ImageViewType::TYPE_2D,
+                format: OFFSCREEN_FORMAT,
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                level_count: 1,
+                layer_count: 1,
+                ..Default::default()
+            },
+        )?;
+
+        let readback_buffers = (0..num_frames)
+            .map(|_| {
+                Buffer::new(
+                    device.clone(),
+                    &BufferDesc {
+                        label: Some(Cow::Borrowed("offscreen_readback_buffer")),
+                        size: desc.width as vk::DeviceSize * desc.height as vk::DeviceSize * 4,
+                        usage: vk::BufferUsageFlags::TRANSFER_DST,
+                        allocation_flags: AllocationCreateFlags::HOST_ACCESS_RANDOM
+                            | AllocationCreateFlags::MAPPED,
+                        memory_usage: MemoryUsage::AUTO_PREFER_HOST,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            color_image,
+            color_image_view,
+            readback_buffers,
+            pending_readbacks: vec![None; num_frames],
+            desc,
+        })
+    }
+
+    #[inline]
+    pub fn color_image(&self) -> &Image {
+        &self.color_image
+    }
+
+    #[inline]
+    pub fn color_image_view(&self) -> &ImageView {
+        &self.color_image_view
+    }
+
+    #[inline]
+    pub fn readback_buffer(&self, frame_offset: usize) -> &Buffer {
+        &self.readback_buffers[frame_offset]
+    }
+
+    #[inline]
+    pub fn desc(&self) -> &HeadlessDesc {
+        &self.desc
+    }
+
+    #[inline]
+    pub(crate) fn set_pending_readback(&mut self, frame_offset: usize, frame_index: usize) {
+        self.pending_readbacks[frame_offset] = Some(frame_index);
+    }
+
+    /// Reads back the last frame rendered with `frame_offset`.
+    /// The fence of that frame must have been waited for.
+    pub(crate) fn take_readback(
+        &mut self,
+        frame_offset: usize,
+    ) -> Result<Option<OffscreenFrame>, BackendError> {
+        let Some(frame_index) = self.pending_readbacks[frame_offset].take() else {
+            return Ok(None);
+        };
+
+        let readback_buffer = &self.readback_buffers[frame_offset];
+        readback_buffer.invalidate(0, vk::WHOLE_SIZE)?;
+
+        Ok(Some(OffscreenFrame {
+            frame_index,
+            width: self.desc.width,
+            height: self.desc.height,
+            data: readback_buffer.mapped_slice().unwrap().to_vec(),
+        }))
+    }
+}
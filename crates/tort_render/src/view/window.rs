@@ -21,7 +21,7 @@ use tort_window::{
 
 use crate::{
     backend::{Device, Instance, Surface, Swapchain},
-    renderer::FrameCtx,
+    renderer::{DeletionQueue, FrameCtx},
     Extract, ExtractSchedule, RenderApp, RenderSet,
 };
 
@@ -62,6 +62,7 @@ pub struct ExtractedWindow {
     pub swap_chain_image_view: vk::ImageView,
     pub swap_chain_image_index: u32,
     pub swap_chain_format: Option<vk::Format>,
+    pub image_acquired_semaphore: vk::Semaphore,
     pub size_changed: bool,
     pub present_mode_changed: bool,
     pub alpha_mode: CompositeAlphaMode,
@@ -114,6 +115,7 @@ fn extract_windows(
             swap_chain_image_view: vk::ImageView::null(),
             swap_chain_image_index: 0,
             swap_chain_format: None,
+            image_acquired_semaphore: vk::Semaphore::null(),
             size_changed: false,
             present_mode_changed: false,
             alpha_mode: window.composite_alpha_mode,
@@ -121,6 +123,7 @@ fn extract_windows(
 
         extracted_window.swap_chain_image = vk::Image::null();
         extracted_window.swap_chain_image_view = vk::ImageView::null();
+        extracted_window.image_acquired_semaphore = vk::Semaphore::null();
         extracted_window.size_changed = new_width != extracted_window.physical_width
             || new_height != extracted_window.physical_height;
         extracted_window.present_mode_changed =
@@ -164,45 +167,64 @@ fn prepare_windows(
     instance: Res<Instance>,
     device: Res<Device>,
     mut frame_ctx: ResMut<FrameCtx>,
+    mut deletion_queue: ResMut<DeletionQueue>,
 ) {
-    let frame = frame_ctx.current();
+    let surfaces = &mut window_surfaces.surfaces;
+
+    let frame_index = frame_ctx.frame_index();
+
+    let closed_windows = surfaces
+        .keys()
+        .filter(|entity| !windows.contains_key(*entity))
+        .copied()
+        .collect::<Vec<_>>();
+
+    // The frames in flight may still present to the swapchains of closed windows.
+    for entity in closed_windows {
+        if let Some(surface) = surfaces.remove(&entity) {
+            deletion_queue.push(frame_index, surface);
+        }
+        deletion_queue.push(frame_index, frame_ctx.remove_window(entity));
+    }
+
+    let frame = frame_ctx.current_mut();
+
+    // Wait until the last submission using this frame has finished, so its semaphores and
+    // command buffers can be reused.
+    unsafe { frame.fence().wait_for(u64::MAX) }.unwrap();
 
     let mut swapchain_image_shift = None;
 
     for window in windows.windows.values_mut() {
-        let (surface, swapchain) = window_surfaces
-            .surfaces
-            .entry(window.entity)
-            .or_insert_with(|| {
-                let raw_handle = unsafe { window.handle.get_handle() };
-                let surface = Surface::new(
+        let (surface, swapchain) = surfaces.entry(window.entity).or_insert_with(|| {
+            let raw_handle = unsafe { window.handle.get_handle() };
+            let surface = Surface::new(
+                instance.clone(),
+                raw_handle.raw_display_handle(),
+                raw_handle.raw_window_handle(),
+            )
+            .unwrap();
+
+            (
+                surface.clone(),
+                Swapchain::new(
                     instance.clone(),
-                    raw_handle.raw_display_handle(),
-                    raw_handle.raw_window_handle(),
-                )
-                .unwrap();
-
-                (
-                    surface.clone(),
-                    Swapchain::new(
-                        instance.clone(),
-                        surface,
-                        device.clone(),
-                        window.present_mode,
-                        None,
-                    )
-                    .unwrap(),
+                    surface,
+                    device.clone(),
+                    window.present_mode,
+                    None,
                 )
-            });
+                .unwrap(),
+            )
+        });
 
         if window.size_changed || window.present_mode_changed {
-            unsafe { device.loader().device_wait_idle() }.unwrap();
-
             if window.physical_width == 0 || window.physical_height == 0 {
                 continue
             }
 
-            let _ = mem::replace(
+            // The frames in flight may still use the images of the old swapchain.
+            let old_swapchain = mem::replace(
                 swapchain,
                 Swapchain::new(
                     instance.clone(),
@@ -213,8 +235,9 @@ fn prepare_windows(
                 )
                 .unwrap(),
             );
+            deletion_queue.push(frame_index, old_swapchain);
 
-            swapchain_image_shift = Some(frame_ctx.frame_index() % swapchain.images().len());
+            swapchain_image_shift = Some(frame_index % swapchain.images().len());
 
             debug!("Swapchain recreated");
         }
@@ -223,51 +246,24 @@ fn prepare_windows(
             continue
         }
 
+        let image_acquired_semaphore = **frame.image_acquired_semaphore(window.entity);
+
         let image_index = unsafe {
             match device.swapchain_loader().acquire_next_image(
                 **swapchain,
                 u64::MAX,
-                **frame.image_acquired_semaphore(),
+                image_acquired_semaphore,
                 vk::Fence::null(),
             ) {
-                Ok((index, is_suboptimal)) => {
-                    if is_suboptimal {
-                        device.loader().device_wait_idle().unwrap();
-
-                        let _ = mem::replace(
-                            swapchain,
-                            Swapchain::new(
-                                instance.clone(),
-                                surface.clone(),
-                                device.clone(),
-                                window.present_mode,
-                                Some(swapchain),
-                            )
-                            .unwrap(),
-                        );
-
-                        device
-                            .swapchain_loader()
-                            .acquire_next_image(
-                                **swapchain,
-                                u64::MAX,
-                                **frame.image_acquired_semaphore(),
-                                vk::Fence::null(),
-                            )
-                            .unwrap()
-                            .0
-                    } else {
-                        index
-                    }
-                }
+                // A suboptimal image has already signalled the semaphore, so it is still rendered
+                // and presented. The swapchain is recreated after presenting.
+                Ok((index, _)) => index,
                 Err(result) => {
                     if result != vk::Result::ERROR_OUT_OF_DATE_KHR {
                         panic!("vkAcquireNextImageKHR failed");
                     }
 
-                    device.loader().device_wait_idle().unwrap();
-
-                    let _ = mem::replace(
+                    let old_swapchain = mem::replace(
                         swapchain,
                         Swapchain::new(
                             instance.clone(),
@@ -278,13 +274,14 @@ fn prepare_windows(
                         )
                         .unwrap(),
                     );
+                    deletion_queue.push(frame_index, old_swapchain);
 
                     device
                         .swapchain_loader()
                         .acquire_next_image(
                             **swapchain,
                             u64::MAX,
-                            **frame.image_acquired_semaphore(),
+                            image_acquired_semaphore,
                             vk::Fence::null(),
                         )
                         .unwrap()
@@ -296,6 +293,7 @@ fn prepare_windows(
         window.swap_chain_image = swapchain.images()[image_index as usize];
         window.swap_chain_image_view = swapchain.image_views()[image_index as usize];
         window.swap_chain_image_index = image_index;
+        window.image_acquired_semaphore = image_acquired_semaphore;
         window.swap_chain_format = Some(swapchain.used_surface_format().format);
     }
 